@@ -0,0 +1,64 @@
+use std::io;
+use std::rc::Rc;
+
+/// Check that lowering and then lifting `spv_words` is semantically lossless.
+///
+/// Because lowering normalizes e.g. ID numbering and the order of decorations,
+/// the first lifted module can't be compared with the original one directly,
+/// but it can be lowered+lifted once more, which should produce the exact same
+/// words (i.e. the first roundtrip reached a "fixpoint").
+fn assert_spv_roundtrip(spv_words: &[u32]) -> io::Result<()> {
+    let lower_lift = |spv_words: &[u32]| {
+        spirt::Module::lower_from_spv_words(Rc::new(spirt::Context::new()), spv_words)?
+            .lift_to_spv_words()
+    };
+
+    let first_words = lower_lift(spv_words)?;
+    let second_words = lower_lift(&first_words)?;
+
+    if first_words != second_words {
+        // FIXME: show a diff (e.g. of the disassembly) instead.
+        let first_mismatch = first_words.iter().zip(&second_words).position(|(a, b)| a != b);
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "lower->lift roundtrip is not a fixpoint: {} vs {} words \
+                 (first mismatch at word {first_mismatch:?})",
+                first_words.len(),
+                second_words.len(),
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    match &std::env::args().collect::<Vec<_>>()[..] {
+        [_, in_files @ ..] if !in_files.is_empty() => {
+            for in_file in in_files {
+                let spv_bytes = std::fs::read(in_file)?;
+                if spv_bytes.len() % 4 != 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{in_file}: not a multiple of 4 bytes"),
+                    ));
+                }
+                // NOTE: endianness is handled by `lower_from_spv_words` itself.
+                let spv_words: Vec<u32> = spv_bytes
+                    .chunks_exact(4)
+                    .map(|w| u32::from_ne_bytes(w.try_into().unwrap()))
+                    .collect();
+
+                assert_spv_roundtrip(&spv_words)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{in_file}: {e}")))?;
+                eprintln!("{in_file}: OK");
+            }
+            Ok(())
+        }
+        args => {
+            eprintln!("Usage: {} FILES", args[0]);
+            std::process::exit(1);
+        }
+    }
+}
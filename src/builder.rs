@@ -0,0 +1,168 @@
+//! Programmatic [`Module`] construction (e.g. for tests or code generators).
+
+use crate::{
+    spv, Context, ExportKey, Exportee, Func, FuncDecl, GlobalVar, GlobalVarDecl, Module,
+    ModuleDebugInfo, ModuleDialect, Type, TypeKind, TypeOrConst,
+};
+use smallvec::SmallVec;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+/// Builder for a [`Module`], starting with an empty SPIR-V module (i.e. no
+/// capabilities/extensions, `Logical` addressing, and `GLSL450` memory model),
+/// which can be customized before [`finish`](ModuleBuilder::finish)ing it.
+///
+/// Unlike with lowering (which requires exactly one `OpMemoryModel`), there
+/// is always a memory model, which defaults to `GLSL450`.
+pub struct ModuleBuilder {
+    module: Module,
+}
+
+impl ModuleBuilder {
+    pub fn new(cx: Rc<Context>) -> Self {
+        let wk = &spv::spec::Spec::get().well_known;
+        Self {
+            module: Module::new(
+                cx,
+                ModuleDialect::Spv(spv::Dialect {
+                    version_major: 1,
+                    version_minor: 0,
+
+                    capabilities: BTreeSet::new(),
+                    extensions: BTreeSet::new(),
+
+                    addressing_model: wk.Logical,
+                    memory_model: wk.GLSL450,
+                }),
+                ModuleDebugInfo::Spv(spv::ModuleDebugInfo {
+                    original_generator_magic: None,
+                    original_id_bound: None,
+
+                    source_languages: BTreeMap::new(),
+                    source_extensions: vec![],
+                    module_processes: vec![],
+                }),
+            ),
+        }
+    }
+
+    pub fn cx(&self) -> Rc<Context> {
+        self.module.cx()
+    }
+
+    pub fn cx_ref(&self) -> &Rc<Context> {
+        self.module.cx_ref()
+    }
+
+    fn spv_dialect(&mut self) -> &mut spv::Dialect {
+        let ModuleDialect::Spv(dialect) = &mut self.module.dialect;
+        dialect
+    }
+
+    pub fn version(&mut self, version: spv::SpvVersion) -> &mut Self {
+        let spv::SpvVersion(major, minor) = version;
+        let dialect = self.spv_dialect();
+        dialect.version_major = major;
+        dialect.version_minor = minor;
+        self
+    }
+
+    pub fn addressing_model(&mut self, addressing_model: u32) -> &mut Self {
+        self.spv_dialect().addressing_model = addressing_model;
+        self
+    }
+
+    pub fn memory_model(&mut self, memory_model: u32) -> &mut Self {
+        self.spv_dialect().memory_model = memory_model;
+        self
+    }
+
+    pub fn capability(&mut self, cap: u32) -> &mut Self {
+        self.spv_dialect().capabilities.insert(cap);
+        self
+    }
+
+    pub fn extension(&mut self, ext: impl Into<String>) -> &mut Self {
+        self.spv_dialect().extensions.insert(ext.into());
+        self
+    }
+
+    /// Intern a SPIR-V type, given its instruction (e.g. `OpTypeVector`), and
+    /// any IDs it refers to (e.g. the element type of a vector).
+    pub fn spv_type(
+        &self,
+        spv_inst: impl Into<spv::Inst>,
+        type_and_const_inputs: impl IntoIterator<Item = TypeOrConst>,
+    ) -> Type {
+        self.cx_ref().intern(TypeKind::SpvInst {
+            spv_inst: spv_inst.into(),
+            type_and_const_inputs: type_and_const_inputs.into_iter().collect(),
+        })
+    }
+
+    pub fn void_type(&self) -> Type {
+        self.spv_type(spv::spec::Spec::get().well_known.OpTypeVoid, [])
+    }
+
+    pub fn bool_type(&self) -> Type {
+        self.spv_type(spv::spec::Spec::get().well_known.OpTypeBool, [])
+    }
+
+    pub fn int_type(&self, width: u32, signed: bool) -> Type {
+        let wk = &spv::spec::Spec::get().well_known;
+        let imms: SmallVec<_> = [
+            spv::Imm::Short(wk.LiteralInteger, width),
+            spv::Imm::Short(wk.LiteralInteger, signed.into()),
+        ]
+        .into_iter()
+        .collect();
+        self.spv_type(spv::Inst { opcode: wk.OpTypeInt, imms }, [])
+    }
+
+    pub fn float_type(&self, width: u32) -> Type {
+        let wk = &spv::spec::Spec::get().well_known;
+        let imms = [spv::Imm::Short(wk.LiteralInteger, width)].into_iter().collect();
+        self.spv_type(spv::Inst { opcode: wk.OpTypeFloat, imms }, [])
+    }
+
+    pub fn declare_global_var(&mut self, gv_decl: GlobalVarDecl) -> GlobalVar {
+        self.module.global_vars.define(&self.module.cx(), gv_decl)
+    }
+
+    pub fn declare_func(&mut self, func_decl: FuncDecl) -> Func {
+        self.module.funcs.define(&self.module.cx(), func_decl)
+    }
+
+    pub fn export(&mut self, key: ExportKey, exportee: Exportee) -> &mut Self {
+        self.module.exports.insert(key, exportee);
+        self
+    }
+
+    /// Export `exportee` with the link name `name` (i.e. [`ExportKey::LinkName`]).
+    pub fn export_link_name(&mut self, name: &str, exportee: Exportee) -> &mut Self {
+        let name = self.cx_ref().intern(name);
+        self.export(ExportKey::LinkName(name), exportee)
+    }
+
+    pub fn finish(self) -> Module {
+        self.module
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn empty_module_roundtrip() {
+    let mut builder = ModuleBuilder::new(Rc::new(Context::new()));
+    builder.version(spv::SpvVersion(1, 3));
+    let module = builder.finish();
+
+    // NOTE: lowering requires an `OpMemoryModel`, which lifting will
+    // always emit, given that `ModuleBuilder` always sets a memory model.
+    let spv_words = module.lift_to_spv_words().unwrap();
+    let relowered = Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words).unwrap();
+
+    let (ModuleDialect::Spv(dialect), ModuleDialect::Spv(relowered_dialect)) =
+        (&module.dialect, &relowered.dialect);
+    assert_eq!(relowered_dialect.version(), spv::SpvVersion(1, 3));
+    assert_eq!(relowered_dialect.memory_model, dialect.memory_model);
+}
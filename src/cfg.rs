@@ -100,6 +100,45 @@ impl ControlFlowGraph {
         );
         post_order.into_iter().rev()
     }
+
+    /// The [`ControlRegion`]s that control-flow can continue to, after exiting
+    /// `region` (i.e. the targets of its [`ControlInst`], in order, and including
+    /// duplicates, e.g. for `SelectBranch`es with several identical cases).
+    ///
+    /// Panics if `region` isn't part of this CFG (i.e. it has no [`ControlInst`]).
+    pub fn successors(&self, region: ControlRegion) -> impl Iterator<Item = ControlRegion> + '_ {
+        self.control_inst_on_exit_from
+            .get(region)
+            .expect("cfg: missing `ControlInst` for `ControlRegion` not in this CFG")
+            .targets
+            .iter()
+            .copied()
+    }
+
+    /// Compute the predecessors of every [`ControlRegion`] in `func_def_body`'s CFG
+    /// (i.e. the inverse of [`successors`](ControlFlowGraph::successors)), with
+    /// each predecessor appearing at most once, in reverse post-order.
+    ///
+    /// Only regions reachable from the entry (i.e. `func_def_body.body`) are
+    /// considered, so unreachable regions have no predecessors, and are never
+    /// predecessors themselves (while the entry only has predecessors if it's
+    /// also a loop header).
+    pub fn predecessors(
+        &self,
+        func_def_body: &FuncDefBody,
+    ) -> EntityOrientedDenseMap<ControlRegion, SmallVec<[ControlRegion; 2]>> {
+        let mut predecessors = EntityOrientedDenseMap::new();
+        for region in self.rev_post_order(func_def_body) {
+            for target in self.successors(region) {
+                let target_predecessors: &mut SmallVec<_> =
+                    predecessors.entry(target).get_or_insert_with(SmallVec::new);
+                if target_predecessors.last() != Some(&region) {
+                    target_predecessors.push(region);
+                }
+            }
+        }
+        predecessors
+    }
 }
 
 // HACK(eddyb) this only serves to disallow accessing `private_count` field of
@@ -1449,3 +1488,56 @@ impl<'a> Structurizer<'a> {
         })
     }
 }
+
+#[test]
+fn diamond_with_unreachable_region() {
+    let spv_words = crate::spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "main" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %9 = OpTypeBool
+            %10 = OpConstantTrue %9
+            %1 = OpFunction %2 None %3
+            %4 = OpLabel
+            OpBranchConditional %10 %5 %6
+            %5 = OpLabel
+            OpBranch %7
+            %6 = OpLabel
+            OpBranch %7
+            %7 = OpLabel
+            OpReturn
+            %8 = OpLabel
+            OpBranch %7
+            OpFunctionEnd
+            "#,
+    );
+    let module = crate::Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words).unwrap();
+    let main = match *module.exports.values().next().unwrap() {
+        crate::Exportee::Func(func) => func,
+        crate::Exportee::GlobalVar(_) => unreachable!(),
+    };
+    let crate::DeclDef::Present(func_def_body) = &module.funcs[main].def else { unreachable!() };
+    let cfg = func_def_body.unstructured_cfg.as_ref().unwrap();
+
+    let entry = func_def_body.body;
+    let [then_region, else_region] = cfg.successors(entry).collect::<Vec<_>>()[..] else {
+        unreachable!()
+    };
+    let [merge_region] = cfg.successors(then_region).collect::<Vec<_>>()[..] else {
+        unreachable!()
+    };
+    assert!(cfg.successors(else_region).eq([merge_region]));
+    assert!(cfg.successors(merge_region).next().is_none());
+    assert!(cfg.rev_post_order(func_def_body).eq([entry, then_region, else_region, merge_region]));
+
+    // NOTE: the unreachable `%8` doesn't count as a predecessor.
+    let predecessors = cfg.predecessors(func_def_body);
+    assert!(predecessors.get(entry).is_none());
+    assert!(predecessors.get(then_region).unwrap()[..] == [entry]);
+    assert!(predecessors.get(else_region).unwrap()[..] == [entry]);
+    assert!(predecessors.get(merge_region).unwrap()[..] == [then_region, else_region]);
+}
@@ -20,6 +20,12 @@ use std::ops::{Deref, DerefMut};
 ///   dynamically registered, to account for any such "extended instruction set"
 ///   not covered by [`spv::spec::Spec`](crate::spv::spec::Spec)'s built-in list
 ///   (e.g. non-standard tool-specific sets), and only used for pretty-printing
+///
+/// The definition of any interned value can be accessed by indexing the
+/// [`Context`] it was interned in (e.g. `&cx[ty]` for a [`TypeDef`](crate::TypeDef),
+/// `&cx[ct]` for a [`ConstDef`](crate::ConstDef), or `&cx[data_inst_form]` for
+/// a [`DataInstFormDef`](crate::DataInstFormDef)), which is also how passes
+/// outside of SPIR-T itself are expected to inspect them.
 #[derive(Default)]
 pub struct Context {
     interners: Interners,
@@ -96,6 +102,38 @@ mod sealed {
             let (i, _) = self.0.insert_full(value.into());
             I::from_u32(i.try_into().expect("interner overflowed u32"))
         }
+
+        /// Number of values interned so far.
+        //
+        // HACK: `elsa::FrozenIndexSet` doesn't expose its length, but
+        // all indices below it are valid, so it can be binary-searched for.
+        pub(super) fn len(&self) -> usize {
+            let (mut valid, mut invalid) = (0, 1);
+            while self.0.get_index(invalid - 1).is_some() {
+                valid = invalid;
+                invalid *= 2;
+            }
+            while invalid - valid > 1 {
+                let mid = valid + (invalid - valid) / 2;
+                if self.0.get_index(mid - 1).is_some() {
+                    valid = mid;
+                } else {
+                    invalid = mid;
+                }
+            }
+            valid
+        }
+
+        pub(super) fn stats(&self) -> super::InternerStats {
+            let count = self.len();
+            let approx_bytes = (0..count)
+                .map(|i| {
+                    std::mem::size_of::<Box<I::Def>>()
+                        + std::mem::size_of_val(self.0.get_index(i).unwrap())
+                })
+                .sum();
+            super::InternerStats { count, approx_bytes }
+        }
     }
 
     impl<I: Interned> std::ops::Index<I> for Interner<I> {
@@ -186,8 +224,54 @@ impl Context {
     pub fn intern<T: InternInCx<I>, I>(&self, x: T) -> I {
         x.intern_in_cx(self)
     }
+
+    /// Number of values of one interned kind (e.g. `cx.intern_count::<Type>()`)
+    /// interned so far in this [`Context`] (see also [`Context::stats`]).
+    pub fn intern_count<I: sealed::Interned>(&self) -> usize {
+        I::cx_interner(self).len()
+    }
+
+    /// Statistics about everything interned so far in this [`Context`], e.g.
+    /// to help decide when to stop reusing it (as interned values are never freed).
+    pub fn stats(&self) -> ContextStats {
+        let interners = &self.interners;
+        ContextStats {
+            interned_strs: interners.InternedStr.stats(),
+            attr_sets: interners.AttrSet.stats(),
+            types: interners.Type.stats(),
+            consts: interners.Const.stats(),
+            data_inst_forms: interners.DataInstForm.stats(),
+        }
+    }
+}
+
+/// Statistics about a [`Context`] (see [`Context::stats`]).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContextStats {
+    pub interned_strs: InternerStats,
+    pub attr_sets: InternerStats,
+    pub types: InternerStats,
+    pub consts: InternerStats,
+    pub data_inst_forms: InternerStats,
 }
 
+/// Statistics about one interned kind (e.g. [`Type`](crate::Type)s) in a [`Context`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InternerStats {
+    /// Number of (unique) values interned so far.
+    pub count: usize,
+
+    /// Approximate memory usage (in bytes) of all the interned values.
+    //
+    // FIXME: this doesn't account for heap allocations owned by values
+    // (e.g. `SmallVec`s that spilled), or the interner's own hash table.
+    pub approx_bytes: usize,
+}
+
+/// Get the definition of an interned value (e.g. `&cx[ty]` for a [`Type`](crate::Type)).
+///
+/// Indexing with a value interned in a different [`Context`] will either panic,
+/// or (worse) silently return an unrelated definition.
 impl<I: sealed::Interned> std::ops::Index<I> for Context {
     type Output = I::Def;
 
@@ -274,6 +358,21 @@ impl<E: sealed::Entity> EntityDefs<E> {
         entity
     }
 
+    /// Number of entities defined in this [`EntityDefs`].
+    pub fn len(&self) -> usize {
+        self.flattened.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flattened.is_empty()
+    }
+
+    /// Get the definition of `entity`, if it was defined in this [`EntityDefs`]
+    /// (indexing, i.e. `defs[entity]`, panics otherwise).
+    pub fn get(&self, entity: E) -> Option<&E::Def> {
+        self.entity_to_flattened(entity).and_then(|i| self.flattened.get(i))
+    }
+
     fn entity_to_flattened(&self, entity: E) -> Option<usize> {
         let (chunk_start, intra_chunk_idx) = entity.to_chunk_start_and_intra_chunk_idx();
         let flattened_base = match self.incomplete_chunk_start_and_flattened_base {
@@ -972,3 +1071,27 @@ entities! {
     ControlNode => chunk_size(0x1000) EntityListNode<ControlNode, crate::ControlNodeDef>,
     DataInst => chunk_size(0x1000) EntityListNode<DataInst, crate::DataInstDef>,
 }
+
+#[test]
+fn intern_count_and_stats() {
+    let cx = Context::new();
+    let initial_stats = cx.stats();
+    let initial_count = cx.intern_count::<InternedStr>();
+    assert_eq!(initial_stats.interned_strs.count, initial_count);
+
+    // NOTE: this covers counts on both sides of several powers of two, which
+    // `Interner::len` relies on (to binary-search for the number of values).
+    for i in 0..300 {
+        let s = format!("s{i}");
+        let [a, b]: [InternedStr; 2] = [(); 2].map(|()| cx.intern(&s[..]));
+        assert!(a == b);
+        assert_eq!(cx.intern_count::<InternedStr>(), initial_count + i + 1);
+    }
+
+    let stats = cx.stats();
+    assert_eq!(stats.interned_strs.count, initial_count + 300);
+    assert!(stats.interned_strs.approx_bytes > initial_stats.interned_strs.approx_bytes + 300);
+
+    // Other interned kinds are unaffected.
+    assert_eq!(ContextStats { interned_strs: initial_stats.interned_strs, ..stats }, initial_stats);
+}
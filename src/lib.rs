@@ -152,6 +152,7 @@
 
 // NOTE(eddyb) all the modules are declared here, but they're documented "inside"
 // (i.e. using inner doc comments).
+pub mod builder;
 pub mod cfg;
 mod context;
 pub mod func_at;
@@ -163,9 +164,19 @@ pub mod passes {
     //
     // NOTE(eddyb) inline `mod` to avoid adding APIs here, it's just namespacing.
 
+    pub mod call_graph;
+    pub mod canonicalize;
+    pub mod compact;
+    pub mod dce;
+    pub mod fold;
+    pub mod inline;
     pub mod legalize;
     pub mod link;
+    pub mod merge;
     pub mod qptr;
+    pub mod strip_debug;
+    pub mod validate;
+    pub mod version;
 }
 pub mod qptr;
 pub mod spv;
@@ -186,7 +197,8 @@ type FxIndexSet<V> = indexmap::IndexSet<V, std::hash::BuildHasherDefault<rustc_h
 // FIXME(eddyb) maybe make an `entity` module to move either the definitions,
 // or at least the re-exports - an `ir` module might help too, organizationally?
 pub use context::{
-    Context, EntityDefs, EntityList, EntityListIter, EntityOrientedDenseMap, EntityOrientedMapKey,
+    Context, ContextStats, EntityDefs, EntityList, EntityListIter, EntityOrientedDenseMap,
+    EntityOrientedMapKey, InternerStats,
 };
 
 /// Interned handle for a [`str`].
@@ -245,8 +257,106 @@ mod sealed {
 }
 pub use sealed::Module;
 
+/// Coarse classification of a [`Module`], based on its [`ExportKey`]s
+/// (see [`Module::kind`]).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModuleKind {
+    /// No entry points, only (if any) [`ExportKey::LinkName`] exports.
+    Library,
+
+    /// Only [`ExportKey::SpvEntryPoint`] exports.
+    Executable,
+
+    /// Both [`ExportKey::SpvEntryPoint`] and [`ExportKey::LinkName`] exports.
+    Mixed,
+}
+
+impl Module {
+    /// Create a module without any definitions, exports, or debuginfo, e.g.
+    /// to [`merge`](passes::merge::merge) other modules into it.
+    ///
+    /// As long as the module has no exports, merging into it adopts the
+    /// SPIR-V memory/addressing models of the merged module (instead of
+    /// requiring them to match `dialect`'s), except for any model for which
+    /// [`ModelPolicy::TakeMergee`] was requested, which keeps `dialect`'s.
+    ///
+    /// [`ModelPolicy::TakeMergee`]: passes::merge::ModelPolicy::TakeMergee
+    pub fn new_empty(cx: Rc<Context>, dialect: ModuleDialect) -> Self {
+        Self::new(
+            cx,
+            dialect,
+            ModuleDebugInfo::Spv(spv::ModuleDebugInfo {
+                original_generator_magic: None,
+                original_id_bound: None,
+
+                source_languages: Default::default(),
+                source_extensions: vec![],
+                module_processes: vec![],
+            }),
+        )
+    }
+
+    pub fn kind(&self) -> ModuleKind {
+        let has_entry_points =
+            self.exports.keys().any(|key| matches!(key, ExportKey::SpvEntryPoint { .. }));
+        let has_link_names = self.exports.keys().any(|key| matches!(key, ExportKey::LinkName(_)));
+        match (has_entry_points, has_link_names) {
+            (false, _) => ModuleKind::Library,
+            (true, false) => ModuleKind::Executable,
+            (true, true) => ModuleKind::Mixed,
+        }
+    }
+
+    /// All the [`Type`]s used by (i.e. reachable from the exports of) the module,
+    /// deduplicated, and in the (deterministic) order they were first found in.
+    ///
+    /// Unlike everything interned in the [`Context`], this only includes the
+    /// types actually used, e.g. as input for generating descriptor layouts.
+    pub fn collect_types(&self) -> Vec<Type> {
+        self.collect_types_and_consts().0.into_iter().collect()
+    }
+
+    /// All the [`Const`]s used by (i.e. reachable from the exports of) the module,
+    /// deduplicated, and in the (deterministic) order they were first found in.
+    pub fn collect_consts(&self) -> Vec<Const> {
+        self.collect_types_and_consts().1.into_iter().collect()
+    }
+
+    fn collect_types_and_consts(&self) -> (FxIndexSet<Type>, FxIndexSet<Const>) {
+        // NOTE: `AttrSet`s can't be ignored here, as they can refer
+        // to `Type`s and `Const`s (e.g. through `OpDecorateId`).
+        let mut collector = visit::ReachableUseCollector::new(self);
+        collector.visit_attr_sets = true;
+        collector.visit_module_exports();
+        (collector.seen_types, collector.seen_consts)
+    }
+}
+
+// NOTE: `Module`s are (de)serialized as SPIR-V words, as that's already a
+// stable encoding, which includes every interned value and entity by value (and
+// deduplicates strings), and it can be lowered into any (fresh) `Context`.
+//
+// FIXME: this requires the module to be liftable to SPIR-V (e.g. no `qptr`),
+// and it loses any information lifting can't preserve (e.g. structured control-flow).
+#[cfg(feature = "serde")]
+impl serde::Serialize for Module {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.lift_to_spv_words().map_err(serde::ser::Error::custom)?.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Module {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let spv_words = Vec::<u32>::deserialize(deserializer)?;
+        Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 /// Semantic properties of a SPIR-T module (not tied to any declarations/definitions).
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ModuleDialect {
     Spv(spv::Dialect),
 }
@@ -345,6 +455,14 @@ pub enum Attr {
 
     SpvAnnotation(spv::Inst),
 
+    /// Like [`Attr::SpvAnnotation`], but for SPIR-V annotations which also take
+    /// `<id>` operands (i.e. `OpExecutionModeId`/`OpDecorateId`, e.g. `LocalSizeId`),
+    /// other than the annotation target, which are referenced as [`Const`]s.
+    SpvAnnotationWithConstInputs {
+        spv_inst: spv::Inst,
+        const_inputs: OrdAssertEq<SmallVec<[Const; 2]>>,
+    },
+
     SpvDebugLine {
         file_path: OrdAssertEq<InternedStr>,
         line: u32,
@@ -910,3 +1028,57 @@ pub enum Value {
     /// The output value of a [`DataInst`].
     DataInstOutput(DataInst),
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip() {
+    let spv_words = crate::spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "foo" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %1 = OpFunction %2 None %3
+            %4 = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#,
+    );
+    let module = Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words).unwrap();
+
+    let json = serde_json::to_string(&module).unwrap();
+    let deserialized: Module = serde_json::from_str(&json).unwrap();
+    assert!(!Rc::ptr_eq(module.cx_ref(), deserialized.cx_ref()));
+    assert!(module.structurally_eq(&deserialized), "{:?}", module.diff(&deserialized));
+}
+
+#[test]
+fn module_kind() {
+    let kind = |entry_point: &str, linkage_decoration: &str| {
+        let spv_words = crate::spv::test_asm::assemble(&format!(
+            r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            {entry_point}
+            {linkage_decoration}
+            %void = OpTypeVoid
+            %func_type = OpTypeFunction %void
+            %main = OpFunction %void None %func_type
+            %entry = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#
+        ));
+        Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words).unwrap().kind()
+    };
+    let entry_point = r#"OpEntryPoint GLCompute %main "main""#;
+    let linkage_decoration = r#"OpDecorate %main LinkageAttributes "main" Export"#;
+
+    assert_eq!(kind("", ""), ModuleKind::Library);
+    assert_eq!(kind("", linkage_decoration), ModuleKind::Library);
+    assert_eq!(kind(entry_point, ""), ModuleKind::Executable);
+    assert_eq!(kind(entry_point, linkage_decoration), ModuleKind::Mixed);
+}
@@ -0,0 +1,196 @@
+//! Call graph construction (see [`Module::call_graph`]).
+
+use crate::visit::{InnerVisit, ReachableUseCollector, Visitor};
+use crate::{
+    AttrSet, Const, Context, DataInstForm, DataInstKind, DeclDef, Func, FxIndexMap, FxIndexSet,
+    GlobalVar, Module, Type,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// The call graph of (the functions reachable from the exports of) a [`Module`],
+/// i.e. which functions each function calls (see [`Module::call_graph`]).
+///
+/// Only direct calls (i.e. [`DataInstKind::FuncCall`]) can be tracked, any
+/// function containing indirect calls (e.g. through SPIR-V function pointers)
+/// is instead flagged (see [`CallGraph::has_indirect_calls`]), and imported
+/// functions (i.e. [`DeclDef::Imported`]) are always treated as leaves.
+pub struct CallGraph {
+    /// Every function reachable from exports (in the order they were first
+    /// found in), along with its callees (in the order calls were found in).
+    callees: FxIndexMap<Func, FxIndexSet<Func>>,
+
+    callers: FxHashMap<Func, FxIndexSet<Func>>,
+
+    funcs_with_indirect_calls: FxHashSet<Func>,
+}
+
+impl Module {
+    /// Build the [`CallGraph`] of all the functions reachable from exports.
+    pub fn call_graph(&self) -> CallGraph {
+        let cx = &self.cx();
+
+        // FIXME: reuse this collection work in some kind of "pass manager".
+        let mut collector = ReachableUseCollector::new(self);
+        collector.visit_module_exports();
+
+        let mut call_graph = CallGraph {
+            callees: FxIndexMap::default(),
+            callers: FxHashMap::default(),
+            funcs_with_indirect_calls: FxHashSet::default(),
+        };
+        for &func in &collector.seen_funcs {
+            let mut call_collector =
+                CallCollector { cx, callees: FxIndexSet::default(), has_indirect_calls: false };
+            if let DeclDef::Present(func_def_body) = &self.funcs[func].def {
+                func_def_body.inner_visit_with(&mut call_collector);
+            }
+
+            for &callee in &call_collector.callees {
+                call_graph.callers.entry(callee).or_default().insert(func);
+            }
+            if call_collector.has_indirect_calls {
+                call_graph.funcs_with_indirect_calls.insert(func);
+            }
+            call_graph.callees.insert(func, call_collector.callees);
+        }
+        call_graph
+    }
+}
+
+impl CallGraph {
+    /// All the functions in the call graph (i.e. reachable from exports).
+    pub fn funcs(&self) -> impl Iterator<Item = Func> + '_ {
+        self.callees.keys().copied()
+    }
+
+    /// The functions directly called by `func` (if any).
+    pub fn callees_of(&self, func: Func) -> impl Iterator<Item = Func> + '_ {
+        self.callees.get(&func).into_iter().flatten().copied()
+    }
+
+    /// The functions directly calling `func` (if any).
+    pub fn callers_of(&self, func: Func) -> impl Iterator<Item = Func> + '_ {
+        self.callers.get(&func).into_iter().flatten().copied()
+    }
+
+    /// Whether `func` contains any calls whose callee isn't statically known
+    /// (and which therefore can't be part of the call graph).
+    pub fn has_indirect_calls(&self, func: Func) -> bool {
+        self.funcs_with_indirect_calls.contains(&func)
+    }
+
+    /// Check whether `func` is part of any cycle in the call graph (i.e.
+    /// whether it can end up calling itself, directly or indirectly).
+    pub fn is_recursive(&self, func: Func) -> bool {
+        let mut seen = FxHashSet::default();
+        let mut queue: Vec<Func> = self.callees_of(func).collect();
+        while let Some(callee) = queue.pop() {
+            if callee == func {
+                return true;
+            }
+            if seen.insert(callee) {
+                queue.extend(self.callees_of(callee));
+            }
+        }
+        false
+    }
+
+    /// All the functions in the call graph, with callees always before their
+    /// callers (i.e. a post-order), except for cycles (see [`CallGraph::is_recursive`]),
+    /// which are broken arbitrarily (but deterministically).
+    pub fn post_order(&self) -> impl Iterator<Item = Func> {
+        let mut post_order = FxIndexSet::default();
+        let mut visiting = FxHashSet::default();
+        for func in self.funcs() {
+            self.post_order_visit(func, &mut visiting, &mut post_order);
+        }
+        post_order.into_iter()
+    }
+
+    fn post_order_visit(
+        &self,
+        func: Func,
+        visiting: &mut FxHashSet<Func>,
+        post_order: &mut FxIndexSet<Func>,
+    ) {
+        if post_order.contains(&func) || !visiting.insert(func) {
+            return;
+        }
+        for callee in self.callees_of(func) {
+            self.post_order_visit(callee, visiting, post_order);
+        }
+        post_order.insert(func);
+    }
+}
+
+struct CallCollector<'a> {
+    cx: &'a Context,
+    callees: FxIndexSet<Func>,
+    has_indirect_calls: bool,
+}
+
+impl Visitor<'_> for CallCollector<'_> {
+    // NOTE: only the instructions in a single function body are
+    // inspected, so the only interesting uses are of `DataInstForm`s.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
+        match &self.cx[data_inst_form].kind {
+            &DataInstKind::FuncCall(callee) => {
+                self.callees.insert(callee);
+            }
+            // FIXME: this should be more principled than checking names
+            // (but SPIR-V function pointers are only supported by extensions).
+            DataInstKind::SpvInst(spv_inst)
+                if spv_inst.opcode.name() == "OpFunctionPointerCallINTEL" =>
+            {
+                self.has_indirect_calls = true;
+            }
+            _ => {}
+        }
+    }
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+}
+
+#[test]
+fn recursive_call_graph() {
+    let spv_words = crate::spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "main" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %4 = OpFunction %2 None %3
+            %5 = OpLabel
+            %6 = OpFunctionCall %2 %4
+            OpReturn
+            OpFunctionEnd
+            %1 = OpFunction %2 None %3
+            %7 = OpLabel
+            %8 = OpFunctionCall %2 %4
+            OpReturn
+            OpFunctionEnd
+            "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+    let call_graph = module.call_graph();
+
+    let main = match *module.exports.values().next().unwrap() {
+        crate::Exportee::Func(func) => func,
+        crate::Exportee::GlobalVar(_) => unreachable!(),
+    };
+    let callees: Vec<_> = call_graph.callees_of(main).collect();
+    let [recursive] = callees[..] else { unreachable!() };
+
+    assert!(call_graph.callers_of(main).next().is_none());
+    assert!(call_graph.callers_of(recursive).collect::<Vec<_>>() == [main, recursive]);
+    assert!(call_graph.is_recursive(recursive));
+    assert!(!call_graph.is_recursive(main));
+    assert!(!call_graph.has_indirect_calls(main));
+    assert!(call_graph.post_order().collect::<Vec<_>>() == [recursive, main]);
+}
@@ -0,0 +1,256 @@
+//! Canonicalization (i.e. removing sources of nondeterminism in the output).
+
+use crate::{
+    Attr, AttrSet, Const, ConstKind, Context, DeclDef, ExportKey, GlobalVar, Import, Module, Type,
+    TypeKind, TypeOrConst,
+};
+use rustc_hash::{FxHashMap, FxHasher};
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+/// Sort the `exports` of `module`, by the contents of their [`ExportKey`]s
+/// (entry points first, then link names), and the interface global variables
+/// of entry points (by a hash of their contents), to make lifting output stable.
+///
+/// Types, constants, and (reachable) global variables, aren't stored in any
+/// particular order (as they're interned, or stored in [`EntityDefs`]), and
+/// are only ordered (when lifting) by a dependencies-first walk starting from
+/// `module.exports`, i.e. by their first use. To also make their order canonical
+/// (e.g. so changes to one function don't reorder unrelated definitions), lift
+/// with [`LiftOptions::canonical_definition_order`], which instead sorts them
+/// topologically (dependencies first), breaking ties by a hash of their contents.
+///
+/// [`EntityDefs`]: crate::EntityDefs
+/// [`LiftOptions::canonical_definition_order`]: crate::spv::lift::LiftOptions::canonical_definition_order
+pub fn sort_definitions(module: &mut Module) {
+    let cx = module.cx();
+
+    let interface_global_var_hashes: FxHashMap<_, _> = {
+        let mut content_hasher = ContentHasher::new(&cx, module);
+        module
+            .exports
+            .keys()
+            .flat_map(|export_key| match export_key {
+                ExportKey::SpvEntryPoint { interface_global_vars, .. } => {
+                    &interface_global_vars[..]
+                }
+                ExportKey::LinkName(_) => &[],
+            })
+            .map(|&gv| (gv, content_hasher.global_var_hash(gv)))
+            .collect()
+    };
+    module.exports = mem::take(&mut module.exports)
+        .into_iter()
+        .map(|(mut export_key, exportee)| {
+            if let ExportKey::SpvEntryPoint { interface_global_vars, .. } = &mut export_key {
+                interface_global_vars.sort_by_key(|gv| interface_global_var_hashes[gv]);
+            }
+            (export_key, exportee)
+        })
+        .collect();
+
+    module.exports.sort_by(|a, _, b, _| match (a, b) {
+        (ExportKey::SpvEntryPoint { imms: a, .. }, ExportKey::SpvEntryPoint { imms: b, .. }) => {
+            a.cmp(b)
+        }
+        (ExportKey::SpvEntryPoint { .. }, ExportKey::LinkName(_)) => Ordering::Less,
+        (ExportKey::LinkName(_), ExportKey::SpvEntryPoint { .. }) => Ordering::Greater,
+        (&ExportKey::LinkName(a), &ExportKey::LinkName(b)) => cx[a].cmp(&cx[b]),
+    });
+}
+
+/// Hashes of the contents of definitions (recursively, including the contents
+/// of any definitions they refer to), which, unlike the handles themselves, don't
+/// depend on the order definitions were interned in (or added to a [`Module`]).
+///
+/// Only intended for tiebreaking, i.e. distinct definitions may (rarely) have
+/// the same hash, and any [`Attr`]s not relevant to SPIR-V (`Attr::QPtr` and
+/// `Attr::Diagnostics`) only contribute their variant to the hash.
+pub(crate) struct ContentHasher<'a> {
+    cx: &'a Context,
+    module: &'a Module,
+
+    type_hashes: FxHashMap<Type, u64>,
+    const_hashes: FxHashMap<Const, u64>,
+    global_var_hashes: FxHashMap<GlobalVar, u64>,
+}
+
+fn hash_of(x: impl Hash) -> u64 {
+    let mut hasher = FxHasher::default();
+    x.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<'a> ContentHasher<'a> {
+    pub(crate) fn new(cx: &'a Context, module: &'a Module) -> Self {
+        Self {
+            cx,
+            module,
+            type_hashes: FxHashMap::default(),
+            const_hashes: FxHashMap::default(),
+            global_var_hashes: FxHashMap::default(),
+        }
+    }
+
+    fn attrs_hash(&mut self, attrs: AttrSet) -> u64 {
+        // NOTE: `Attr`s are ordered by the handles they contain (if any), so
+        // their hashes are sorted to get the same hash regardless of that order.
+        let cx = self.cx;
+        let mut attr_hashes: Vec<_> = cx[attrs]
+            .attrs
+            .iter()
+            .map(|attr| {
+                let contents_hash = match attr {
+                    Attr::QPtr(_) | Attr::Diagnostics(_) => 0,
+                    Attr::SpvAnnotation(spv_inst) => hash_of(spv_inst),
+                    Attr::SpvAnnotationWithConstInputs { spv_inst, const_inputs } => hash_of((
+                        spv_inst,
+                        const_inputs.0.iter().map(|&ct| self.const_hash(ct)).collect::<Vec<_>>(),
+                    )),
+                    Attr::SpvDebugLine { file_path, line, col } => {
+                        hash_of((&cx[file_path.0], line, col))
+                    }
+                    Attr::SpvBitflagsOperand(imm) => hash_of(imm),
+                };
+                hash_of((mem::discriminant(attr), contents_hash))
+            })
+            .collect();
+        attr_hashes.sort_unstable();
+        hash_of(attr_hashes)
+    }
+
+    pub(crate) fn type_hash(&mut self, ty: Type) -> u64 {
+        if let Some(&hash) = self.type_hashes.get(&ty) {
+            return hash;
+        }
+        let ty_def = &self.cx[ty];
+        let kind_hash = match &ty_def.kind {
+            TypeKind::QPtr => hash_of("qptr"),
+            TypeKind::SpvInst { spv_inst, type_and_const_inputs } => hash_of((
+                spv_inst,
+                type_and_const_inputs
+                    .iter()
+                    .map(|&ty_or_ct| match ty_or_ct {
+                        TypeOrConst::Type(ty) => self.type_hash(ty),
+                        TypeOrConst::Const(ct) => self.const_hash(ct),
+                    })
+                    .collect::<Vec<_>>(),
+            )),
+            TypeKind::SpvStringLiteralForExtInst => hash_of("string literal"),
+        };
+        let hash = hash_of(("type", self.attrs_hash(ty_def.attrs), kind_hash));
+        self.type_hashes.insert(ty, hash);
+        hash
+    }
+
+    pub(crate) fn const_hash(&mut self, ct: Const) -> u64 {
+        if let Some(&hash) = self.const_hashes.get(&ct) {
+            return hash;
+        }
+        let ct_def = &self.cx[ct];
+        let kind_hash = match &ct_def.kind {
+            &ConstKind::PtrToGlobalVar(gv) => self.global_var_hash(gv),
+            ConstKind::SpvInst { spv_inst_and_const_inputs } => {
+                let (spv_inst, const_inputs) = &**spv_inst_and_const_inputs;
+                hash_of((
+                    spv_inst,
+                    const_inputs.iter().map(|&ct| self.const_hash(ct)).collect::<Vec<_>>(),
+                ))
+            }
+            &ConstKind::SpvStringLiteralForExtInst(s) => hash_of(&self.cx[s]),
+        };
+        let hash =
+            hash_of(("const", self.attrs_hash(ct_def.attrs), self.type_hash(ct_def.ty), kind_hash));
+        self.const_hashes.insert(ct, hash);
+        hash
+    }
+
+    pub(crate) fn global_var_hash(&mut self, gv: GlobalVar) -> u64 {
+        if let Some(&hash) = self.global_var_hashes.get(&gv) {
+            return hash;
+        }
+
+        // NOTE: initializers can (invalidly) refer back to the global variable
+        // itself, in which case this placeholder hash is used for the cycle.
+        self.global_var_hashes.insert(gv, 0);
+
+        let gv_decl = &self.module.global_vars[gv];
+        let def_hash = match &gv_decl.def {
+            &DeclDef::Imported(Import::LinkName(name)) => hash_of(("import", &self.cx[name])),
+            DeclDef::Present(gv_def_body) => {
+                hash_of(("present", gv_def_body.initializer.map(|ct| self.const_hash(ct))))
+            }
+        };
+        let hash = hash_of((
+            "global_var",
+            self.attrs_hash(gv_decl.attrs),
+            self.type_hash(gv_decl.type_of_ptr_to),
+            gv_decl.addr_space,
+            def_hash,
+        ));
+        self.global_var_hashes.insert(gv, hash);
+        hash
+    }
+}
+
+#[test]
+fn sort_definitions_of_equivalent_modules() {
+    // Both modules only differ in the order of the entry point's interface
+    // global variables, and of the type definitions.
+    let lower_sort_and_lift = |interface: &str, types: &str| {
+        let spv_words = crate::spv::test_asm::assemble(&format!(
+            r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            OpEntryPoint Fragment %main "main" {interface}
+            OpExecutionMode %main OriginUpperLeft
+            OpName %in "in"
+            OpName %out "out"
+            OpDecorate %in Location 0
+            OpDecorate %out Location 0
+            {types}
+            %in = OpVariable %in_ptr Input
+            %out = OpVariable %out_ptr Output
+            %main = OpFunction %void None %func_type
+            %entry = OpLabel
+            %x = OpLoad %float %in
+            OpStore %out %x
+            OpReturn
+            OpFunctionEnd
+            "#
+        ));
+        let mut module =
+            Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+        sort_definitions(&mut module);
+        module
+            .lift_to_spv_module_emitter_with(crate::spv::lift::LiftOptions {
+                canonical_definition_order: true,
+                ..Default::default()
+            })
+            .unwrap()
+            .words
+    };
+
+    let a = lower_sort_and_lift(
+        "%in %out",
+        "
+        %void = OpTypeVoid
+        %float = OpTypeFloat 32
+        %in_ptr = OpTypePointer Input %float
+        %out_ptr = OpTypePointer Output %float
+        %func_type = OpTypeFunction %void
+        ",
+    );
+    let b = lower_sort_and_lift(
+        "%out %in",
+        "
+        %float = OpTypeFloat 32
+        %out_ptr = OpTypePointer Output %float
+        %void = OpTypeVoid
+        %func_type = OpTypeFunction %void
+        %in_ptr = OpTypePointer Input %float
+        ",
+    );
+    assert!(a == b);
+}
@@ -0,0 +1,146 @@
+//! Compaction of module-stored entities (i.e. [`GlobalVar`]s and [`Func`]s).
+
+use crate::transform::{
+    cached_transform_interned_uses, InnerInPlaceTransform, Transformed, Transformer,
+};
+use crate::visit::ReachableUseCollector;
+use crate::{AttrSet, Const, Context, DataInstForm, EntityDefs, Func, GlobalVar, Module, Type};
+use rustc_hash::FxHashMap;
+
+/// Replace `module.global_vars` and `module.funcs` with new [`EntityDefs`],
+/// only containing the definitions reachable from `module.exports` (in the
+/// order they're first reached in), and rewrite all references to them.
+///
+/// This is mostly useful after e.g. merging or linking, which can leave behind
+/// many unused definitions (which are never lifted, but still use up memory).
+///
+/// Note that the new handles are allocated from the [`Context`], like any other
+/// new definitions, so they will differ from (i.e. be larger than) the original
+/// ones, instead of reusing the (now free) smaller ones.
+//
+// NOTE: `Type`s, `Const`s, etc. are interned in the `Context`, so there
+// is nothing to compact for them, other than re-interning those which refer to
+// `GlobalVar`s (e.g. `ConstKind::PtrToGlobalVar`), to use the new handles.
+pub fn compact_ids(module: &mut Module) {
+    let cx = &module.cx();
+
+    let (global_vars, funcs) = {
+        // NOTE: unlike other passes, `AttrSet`s can't be ignored here,
+        // as they can refer to `Const`s (which can refer to `GlobalVar`s).
+        let mut collector = ReachableUseCollector::new(module);
+        collector.visit_attr_sets = true;
+        collector.visit_module_exports();
+        (collector.seen_global_vars, collector.seen_funcs)
+    };
+
+    let mut rewriter = EntityRewriter {
+        cx,
+
+        new_global_vars: FxHashMap::default(),
+        new_funcs: FxHashMap::default(),
+
+        transformed_attr_sets: FxHashMap::default(),
+        transformed_types: FxHashMap::default(),
+        transformed_consts: FxHashMap::default(),
+        transformed_data_inst_forms: FxHashMap::default(),
+    };
+
+    // Define all the new entities first (without any changes), so that they're
+    // all known before any references are rewritten.
+    let mut new_global_vars = EntityDefs::new();
+    let mut new_funcs = EntityDefs::new();
+    for &gv in &global_vars {
+        let new_gv = new_global_vars.define(cx, module.global_vars[gv].clone());
+        rewriter.new_global_vars.insert(gv, new_gv);
+    }
+    for &func in &funcs {
+        let new_func = new_funcs.define(cx, module.funcs[func].clone());
+        rewriter.new_funcs.insert(func, new_func);
+    }
+
+    for &gv in &global_vars {
+        new_global_vars[rewriter.new_global_vars[&gv]].inner_in_place_transform_with(&mut rewriter);
+    }
+    for &func in &funcs {
+        new_funcs[rewriter.new_funcs[&func]].inner_in_place_transform_with(&mut rewriter);
+    }
+    module.inner_in_place_transform_with(&mut rewriter);
+
+    module.global_vars = new_global_vars;
+    module.funcs = new_funcs;
+}
+
+struct EntityRewriter<'a> {
+    cx: &'a Context,
+
+    new_global_vars: FxHashMap<GlobalVar, GlobalVar>,
+    new_funcs: FxHashMap<Func, Func>,
+
+    // FIXME: build some automation to avoid ever repeating these.
+    transformed_attr_sets: FxHashMap<AttrSet, Transformed<AttrSet>>,
+    transformed_types: FxHashMap<Type, Transformed<Type>>,
+    transformed_consts: FxHashMap<Const, Transformed<Const>>,
+    transformed_data_inst_forms: FxHashMap<DataInstForm, Transformed<DataInstForm>>,
+}
+
+impl Transformer for EntityRewriter<'_> {
+    cached_transform_interned_uses!();
+
+    fn transform_attr_set_use(&mut self, attrs: AttrSet) -> Transformed<AttrSet> {
+        if let Some(&cached) = self.transformed_attr_sets.get(&attrs) {
+            return cached;
+        }
+        let transformed =
+            self.transform_attr_set_def(&self.cx[attrs]).map(|attrs_def| self.cx.intern(attrs_def));
+        self.transformed_attr_sets.insert(attrs, transformed);
+        transformed
+    }
+
+    fn transform_global_var_use(&mut self, gv: GlobalVar) -> Transformed<GlobalVar> {
+        Transformed::Changed(self.new_global_vars[&gv])
+    }
+    fn transform_func_use(&mut self, func: Func) -> Transformed<Func> {
+        Transformed::Changed(self.new_funcs[&func])
+    }
+}
+
+#[test]
+fn compact_ids_keeps_only_reachable_definitions() {
+    let spv_words = crate::spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpName %helper "helper"
+        OpDecorate %main LinkageAttributes "main" Export
+        %void = OpTypeVoid
+        %uint = OpTypeInt 32 0
+        %uint_ptr = OpTypePointer Private %uint
+        %used = OpVariable %uint_ptr Private
+        %unused = OpVariable %uint_ptr Private
+        %func_type = OpTypeFunction %void
+        %helper = OpFunction %void None %func_type
+        %helper_entry = OpLabel
+        %loaded = OpLoad %uint %used
+        OpReturn
+        OpFunctionEnd
+        %unused_func = OpFunction %void None %func_type
+        %unused_func_entry = OpLabel
+        OpReturn
+        OpFunctionEnd
+        %main = OpFunction %void None %func_type
+        %main_entry = OpLabel
+        %call = OpFunctionCall %void %helper
+        OpReturn
+        OpFunctionEnd
+        "#,
+    );
+    let mut module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+    assert_eq!((module.global_vars.len(), module.funcs.len()), (2, 3));
+
+    let before = module.clone();
+    compact_ids(&mut module);
+    assert_eq!((module.global_vars.len(), module.funcs.len()), (1, 2));
+    assert!(module.structurally_eq(&before));
+}
@@ -0,0 +1,71 @@
+//! Dead code elimination (of module-stored entities unreachable from exports).
+
+use crate::passes::compact;
+use crate::Module;
+
+/// Remove all the [`GlobalVar`]s and [`Func`]s not reachable from `module.exports`
+/// (which also include SPIR-V entry points, as [`ExportKey::SpvEntryPoint`]),
+/// returning how many definitions were removed.
+///
+/// This is done by [`compact::compact_ids`], which rebuilds `module.global_vars`
+/// and `module.funcs` with only the reachable definitions (rewriting all the
+/// references to them). [`Type`]s and [`Const`]s are interned in the [`Context`],
+/// so they can't be removed, but unreachable ones are never lifted anyway.
+///
+/// [`GlobalVar`]: crate::GlobalVar
+/// [`Func`]: crate::Func
+/// [`ExportKey::SpvEntryPoint`]: crate::ExportKey::SpvEntryPoint
+/// [`Type`]: crate::Type
+/// [`Const`]: crate::Const
+/// [`Context`]: crate::Context
+pub fn eliminate_dead_code(module: &mut Module) -> usize {
+    let old_count = module.global_vars.len() + module.funcs.len();
+    compact::compact_ids(module);
+    old_count - (module.global_vars.len() + module.funcs.len())
+}
+
+#[test]
+fn eliminate_unreachable_definitions() {
+    let spv_words = crate::spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpDecorate %main LinkageAttributes "main" Export
+        %void = OpTypeVoid
+        %int = OpTypeInt 32 1
+        %int_ptr = OpTypePointer Private %int
+        %used_var = OpVariable %int_ptr Private
+        %unused_var = OpVariable %int_ptr Private
+        %func_type = OpTypeFunction %void
+        %helper = OpFunction %void None %func_type
+        %helper_entry = OpLabel
+        %x = OpLoad %int %used_var
+        OpReturn
+        OpFunctionEnd
+        %unused = OpFunction %void None %func_type
+        %unused_entry = OpLabel
+        %y = OpLoad %int %unused_var
+        OpReturn
+        OpFunctionEnd
+        %main = OpFunction %void None %func_type
+        %main_entry = OpLabel
+        %call = OpFunctionCall %void %helper
+        OpReturn
+        OpFunctionEnd
+        "#,
+    );
+    let mut module =
+        crate::Module::lower_from_spv_words(std::rc::Rc::new(crate::Context::new()), &spv_words)
+            .unwrap();
+    assert_eq!((module.global_vars.len(), module.funcs.len()), (2, 3));
+    let original_words = module.lift_to_spv_words().unwrap();
+
+    // `unused_var` and `unused` (the only user of `unused_var`) are removed.
+    assert_eq!(eliminate_dead_code(&mut module), 2);
+    assert_eq!((module.global_vars.len(), module.funcs.len()), (1, 2));
+    assert_eq!(eliminate_dead_code(&mut module), 0);
+
+    // Only unreachable definitions (which are never lifted) were removed.
+    assert!(module.lift_to_spv_words().unwrap() == original_words);
+}
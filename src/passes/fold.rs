@@ -0,0 +1,218 @@
+//! Constant folding (of instructions with constant inputs).
+
+use crate::func_at::FuncAt;
+use crate::transform::{InnerInPlaceTransform, Transformed, Transformer};
+use crate::visit::{InnerVisit, ReachableUseCollector, Visitor};
+use crate::{
+    spv, AttrSet, Const, ConstKind, Context, ControlNode, ControlNodeKind, DataInst, DataInstForm,
+    DataInstKind, DeclDef, Func, FxIndexMap, GlobalVar, Module, Type, Value,
+};
+
+/// Replace every SPIR-V `OpSelect` with a constant (scalar) boolean condition,
+/// with the operand it would select (i.e. `OpSelect true x y` becomes `x`).
+///
+/// The `OpSelect` instructions themselves are removed, but any operands which
+/// end up unused are left in place (for a separate dead code elimination pass).
+//
+// FIXME: also handle vector conditions (with per-component folding).
+pub fn fold_constant_select(module: &mut Module) {
+    let cx = &module.cx();
+
+    let seen_funcs = {
+        // FIXME: reuse this collection work in some kind of "pass manager".
+        let mut collector = ReachableUseCollector::new(module);
+        collector.visit_module_exports();
+        collector.seen_funcs
+    };
+
+    for &func in &seen_funcs {
+        let func_def_body = match &mut module.funcs[func].def {
+            DeclDef::Present(func_def_body) => func_def_body,
+            DeclDef::Imported(_) => continue,
+        };
+
+        let mut finder = ConstantSelectFinder { cx, selects: FxIndexMap::default() };
+        func_def_body.inner_visit_with(&mut finder);
+        if finder.selects.is_empty() {
+            continue;
+        }
+
+        let mut replacer = ValueReplacer {
+            replacements: finder
+                .selects
+                .iter()
+                .map(|(&inst, &(_, replacement))| (inst, replacement))
+                .collect(),
+        };
+        func_def_body.inner_in_place_transform_with(&mut replacer);
+
+        for (inst, (block_node, _)) in finder.selects {
+            match &mut func_def_body.control_nodes[block_node].kind {
+                ControlNodeKind::Block { insts } => {
+                    insts.remove(inst, &mut func_def_body.data_insts);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Finds all the `OpSelect`s with constant boolean conditions (in a function),
+/// recording the `ControlNodeKind::Block` they're in and their replacement.
+struct ConstantSelectFinder<'a> {
+    cx: &'a Context,
+
+    selects: FxIndexMap<DataInst, (ControlNode, Value)>,
+}
+
+impl ConstantSelectFinder<'_> {
+    fn constant_select_replacement(&self, func_at_inst: FuncAt<'_, DataInst>) -> Option<Value> {
+        let wk = &spv::spec::Spec::get().well_known;
+
+        let inst_def = func_at_inst.def();
+        match &self.cx[inst_def.form].kind {
+            DataInstKind::SpvInst(spv_inst) if spv_inst.opcode == wk.OpSelect => {}
+            _ => return None,
+        }
+
+        let (cond, true_value, false_value) = match inst_def.inputs[..] {
+            [Value::Const(cond), true_value, false_value] => (cond, true_value, false_value),
+            _ => return None,
+        };
+        match &self.cx[cond].kind {
+            ConstKind::SpvInst { spv_inst_and_const_inputs } => {
+                let (spv_inst, _const_inputs) = &**spv_inst_and_const_inputs;
+                if spv_inst.opcode == wk.OpConstantTrue {
+                    Some(true_value)
+                } else if spv_inst.opcode == wk.OpConstantFalse {
+                    Some(false_value)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for ConstantSelectFinder<'_> {
+    // NOTE: uses of interned/module-stored definitions are irrelevant
+    // here, as only the instructions in a single function body are inspected.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, _data_inst_form: DataInstForm) {}
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+
+    fn visit_control_node_def(&mut self, func_at_control_node: FuncAt<'a, ControlNode>) {
+        if let ControlNodeKind::Block { insts } = func_at_control_node.def().kind {
+            for func_at_inst in func_at_control_node.at(insts) {
+                if let Some(replacement) = self.constant_select_replacement(func_at_inst) {
+                    self.selects.insert(
+                        func_at_inst.position,
+                        (func_at_control_node.position, replacement),
+                    );
+                }
+            }
+        }
+        func_at_control_node.inner_visit_with(self);
+    }
+}
+
+/// Replaces all uses of the outputs of some [`DataInst`]s with other [`Value`]s.
+struct ValueReplacer {
+    replacements: FxIndexMap<DataInst, Value>,
+}
+
+impl Transformer for ValueReplacer {
+    fn transform_value_use(&mut self, v: &Value) -> Transformed<Value> {
+        let mut v = *v;
+        let mut changed = false;
+
+        // NOTE: replacements can refer to other replaced `DataInst`s
+        // (e.g. `OpSelect true (OpSelect false a b) c`), so they're followed
+        // until reaching a `Value` which isn't being replaced.
+        while let Value::DataInstOutput(inst) = v {
+            match self.replacements.get(&inst) {
+                Some(&replacement) => {
+                    v = replacement;
+                    changed = true;
+                }
+                None => break,
+            }
+        }
+
+        if changed { Transformed::Changed(v) } else { Transformed::Unchanged }
+    }
+}
+
+#[test]
+fn fold_constant_select_only_with_constant_condition() {
+    let spv_spec = spv::spec::Spec::get();
+    let wk = &spv_spec.well_known;
+    let op_fadd = spv_spec.instructions.lookup("OpFAdd").unwrap();
+
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpDecorate %func LinkageAttributes "func" Export
+        %void = OpTypeVoid
+        %bool = OpTypeBool
+        %float = OpTypeFloat 32
+        %true = OpConstantTrue %bool
+        %one = OpConstant %float 0x3f800000
+        %two = OpConstant %float 0x40000000
+        %func_type = OpTypeFunction %void %bool
+        %func = OpFunction %void None %func_type
+        %cond = OpFunctionParameter %bool
+        %entry = OpLabel
+        %constant_select = OpSelect %float %true %one %two
+        %dynamic_select = OpSelect %float %cond %one %two
+        %sum = OpFAdd %float %constant_select %dynamic_select
+        OpReturn
+        OpFunctionEnd
+        "#,
+    );
+    let mut module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+    fold_constant_select(&mut module);
+
+    let cx = module.cx();
+    let func = match module.exports.values().next() {
+        Some(&crate::Exportee::Func(func)) => func,
+        _ => unreachable!(),
+    };
+    let DeclDef::Present(func_def_body) = &module.funcs[func].def else {
+        unreachable!();
+    };
+    let insts: Vec<_> = func_def_body
+        .at_body()
+        .at_children()
+        .into_iter()
+        .flat_map(|func_at_control_node| match func_at_control_node.def().kind {
+            ControlNodeKind::Block { insts } => func_at_control_node.at(insts).into_iter(),
+            _ => unreachable!(),
+        })
+        .map(|func_at_inst| (func_at_inst.position, func_at_inst.def()))
+        .collect();
+    let opcodes: Vec<_> = insts
+        .iter()
+        .map(|(_, inst_def)| match &cx[inst_def.form].kind {
+            DataInstKind::SpvInst(spv_inst) => spv_inst.opcode,
+            _ => unreachable!(),
+        })
+        .collect();
+    assert!(opcodes == [wk.OpSelect, op_fadd]);
+
+    // The constant `OpSelect` was replaced by its `true` operand (`%one`),
+    // while the non-constant one was left alone (and its use kept as-is).
+    let (dynamic_select, dynamic_select_def) = insts[0];
+    let (_, sum_def) = insts[1];
+    assert!(matches!(dynamic_select_def.inputs[0], Value::ControlRegionInput { .. }));
+    let one = dynamic_select_def.inputs[1];
+    assert!(matches!(one, Value::Const(_)));
+    assert!(sum_def.inputs[..] == [one, Value::DataInstOutput(dynamic_select)]);
+}
@@ -0,0 +1,473 @@
+//! Function inlining (currently only of "trivial" functions, see [`inline_trivial`]).
+
+use crate::func_at::FuncAt;
+use crate::transform::{InnerInPlaceTransform, Transformed, Transformer};
+use crate::visit::{InnerVisit, Visitor};
+use crate::{
+    AttrSet, Const, Context, ControlNode, ControlNodeDef, ControlNodeKind, ControlRegion,
+    ControlRegionDef, DataInst, DataInstForm, DataInstKind, DeclDef, EntityList, Exportee, Func,
+    FuncDefBody, FxIndexMap, FxIndexSet, GlobalVar, Module, Type, Value,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+
+/// Maximum size (i.e. number of [`ControlNode`]s and [`DataInst`]s) of the
+/// body of a function, for it to be considered "trivial" by [`inline_trivial`].
+pub const TRIVIAL_FUNC_MAX_SIZE: usize = 16;
+
+/// Inline every call to a "trivial" function, i.e. a function which:
+/// * is called from exactly one place (in functions reachable from exports)
+/// * isn't itself exported (e.g. as an entry-point)
+/// * isn't recursive (i.e. isn't part of any cycle in the call graph)
+/// * has a fully structured body (see also [`legalize::structurize_func_cfgs`]),
+///   of at most [`TRIVIAL_FUNC_MAX_SIZE`] (see its documentation for details)
+///
+/// Returns the number of inlined call sites, which (given the above) is also
+/// the number of functions that become unused (left in place, but unreachable
+/// from exports, so e.g. lifting to SPIR-V will not emit them anymore).
+///
+/// [`legalize::structurize_func_cfgs`]: super::legalize::structurize_func_cfgs
+pub fn inline_trivial(module: &mut Module) -> usize {
+    let cx = &module.cx();
+
+    let call_graph = module.call_graph();
+
+    // All call sites (as `(caller, call_inst)` pairs), grouped by callee.
+    let mut call_sites = FxIndexMap::<Func, SmallVec<[(Func, DataInst); 1]>>::default();
+    for caller in call_graph.funcs() {
+        if let DeclDef::Present(func_def_body) = &module.funcs[caller].def {
+            let mut collector = CallCollector { cx, calls: vec![] };
+            func_def_body.inner_visit_with(&mut collector);
+            for call in collector.calls {
+                call_sites.entry(call.callee).or_default().push((caller, call.inst));
+            }
+        }
+    }
+
+    let exported_funcs: FxHashSet<Func> = module
+        .exports
+        .values()
+        .filter_map(|&exportee| match exportee {
+            Exportee::Func(func) => Some(func),
+            Exportee::GlobalVar(_) => None,
+        })
+        .collect();
+
+    let candidates: FxIndexMap<Func, (Func, DataInst)> = call_sites
+        .into_iter()
+        .filter_map(|(callee, sites)| match sites[..] {
+            [site] => Some((callee, site)),
+            _ => None,
+        })
+        .filter(|&(callee, _)| {
+            !exported_funcs.contains(&callee)
+                && !call_graph.is_recursive(callee)
+                && match &module.funcs[callee].def {
+                    DeclDef::Present(func_def_body) => is_trivial(func_def_body),
+                    DeclDef::Imported(_) => false,
+                }
+        })
+        .collect();
+
+    // NOTE: callees have to be inlined into candidates before those
+    // candidates are themselves inlined (as their bodies are copied), i.e.
+    // candidates are processed in post-order (wrt the call graph), and call
+    // sites have to be searched for right before inlining (as inlining may
+    // have split the `ControlNodeKind::Block` a later call site was in).
+    let mut post_order = FxIndexSet::default();
+    for &callee in candidates.keys() {
+        candidates_post_order(&candidates, callee, &mut post_order);
+    }
+
+    for &callee in &post_order {
+        let (caller, call_inst) = candidates[&callee];
+        inline_call(cx, module, callee, caller, call_inst);
+    }
+
+    post_order.len()
+}
+
+fn is_trivial(func_def_body: &FuncDefBody) -> bool {
+    if func_def_body.unstructured_cfg.is_some() {
+        return false;
+    }
+    let mut counter = SizeCounter { size: 0 };
+    func_def_body.inner_visit_with(&mut counter);
+    counter.size <= TRIVIAL_FUNC_MAX_SIZE
+}
+
+fn candidates_post_order(
+    candidates: &FxIndexMap<Func, (Func, DataInst)>,
+    callee: Func,
+    post_order: &mut FxIndexSet<Func>,
+) {
+    if post_order.contains(&callee) {
+        return;
+    }
+    for (&inner_callee, &(caller, _)) in candidates {
+        if caller == callee {
+            candidates_post_order(candidates, inner_callee, post_order);
+        }
+    }
+    post_order.insert(callee);
+}
+
+/// Replace `call_inst` (in `caller`) with a copy of the body of `callee`.
+fn inline_call(cx: &Context, module: &mut Module, callee: Func, caller: Func, call_inst: DataInst) {
+    // FIXME: avoid cloning the whole body of `callee` (which is only
+    // needed because `module.funcs` can't be borrowed twice).
+    let callee_body = match &module.funcs[callee].def {
+        DeclDef::Present(func_def_body) => func_def_body.clone(),
+        DeclDef::Imported(_) => unreachable!(),
+    };
+    let func_def_body = match &mut module.funcs[caller].def {
+        DeclDef::Present(func_def_body) => func_def_body,
+        DeclDef::Imported(_) => unreachable!(),
+    };
+
+    let (parent_region, block_node) = {
+        let mut collector = CallCollector { cx, calls: vec![] };
+        func_def_body.inner_visit_with(&mut collector);
+        let call = collector.calls.into_iter().find(|call| call.inst == call_inst).unwrap();
+        (call.parent_region, call.block_node)
+    };
+
+    let args = func_def_body.data_insts[call_inst].inputs.clone();
+    let mut copier = BodyCopier {
+        cx,
+        from: &callee_body,
+        to: func_def_body,
+        remapper: CopyRemapper {
+            params_region: callee_body.body,
+            args,
+            control_regions: FxHashMap::default(),
+            control_nodes: FxHashMap::default(),
+            data_insts: FxHashMap::default(),
+        },
+    };
+    let new_nodes: SmallVec<[ControlNode; 4]> = callee_body
+        .at_body()
+        .at_children()
+        .into_iter()
+        .map(|func_at_control_node| copier.copy_control_node(func_at_control_node.position))
+        .collect();
+    let BodyCopier { to: func_def_body, mut remapper, .. } = copier;
+    for &new_node in &new_nodes {
+        func_def_body.at_mut(new_node).inner_in_place_transform_with(&mut remapper);
+    }
+    let ret_value = callee_body.at_body().def().outputs.first().map(|&v| {
+        let mut v = v;
+        remapper.transform_value_use(&v).apply_to(&mut v);
+        v
+    });
+
+    // Split the `ControlNodeKind::Block` containing the call, into the part
+    // before the call (which is left in `block_node`), and the part after it,
+    // with the copied body of `callee` inserted between them.
+    let after_insts = {
+        let mut after_insts = EntityList::empty();
+        let insts = match &mut func_def_body.control_nodes[block_node].kind {
+            ControlNodeKind::Block { insts } => insts,
+            _ => unreachable!(),
+        };
+        while let Some(inst) = func_def_body.data_insts[call_inst].next_in_list() {
+            insts.remove(inst, &mut func_def_body.data_insts);
+            after_insts.insert_last(inst, &mut func_def_body.data_insts);
+        }
+        insts.remove(call_inst, &mut func_def_body.data_insts);
+        after_insts
+    };
+    let block_is_empty = match func_def_body.control_nodes[block_node].kind {
+        ControlNodeKind::Block { insts } => insts.is_empty(),
+        _ => unreachable!(),
+    };
+
+    let mut next_node = func_def_body.control_nodes[block_node].next_in_list();
+    if !after_insts.is_empty() {
+        let after_node = func_def_body.control_nodes.define(
+            cx,
+            ControlNodeDef {
+                kind: ControlNodeKind::Block { insts: after_insts },
+                outputs: SmallVec::new(),
+            }
+            .into(),
+        );
+        insert_control_node(func_def_body, parent_region, after_node, next_node);
+        next_node = Some(after_node);
+    }
+    for new_node in new_nodes {
+        insert_control_node(func_def_body, parent_region, new_node, next_node);
+    }
+    if block_is_empty {
+        func_def_body.control_regions[parent_region]
+            .children
+            .remove(block_node, &mut func_def_body.control_nodes);
+    }
+
+    if let Some(ret_value) = ret_value {
+        func_def_body.inner_in_place_transform_with(&mut CallOutputReplacer {
+            call_inst,
+            replacement: ret_value,
+        });
+    }
+}
+
+/// Insert `control_node` into `region`, before `next_node` (or at the end).
+fn insert_control_node(
+    func_def_body: &mut FuncDefBody,
+    region: ControlRegion,
+    control_node: ControlNode,
+    next_node: Option<ControlNode>,
+) {
+    let children = &mut func_def_body.control_regions[region].children;
+    match next_node {
+        Some(next_node) => {
+            children.insert_before(control_node, next_node, &mut func_def_body.control_nodes);
+        }
+        None => children.insert_last(control_node, &mut func_def_body.control_nodes),
+    }
+}
+
+/// A call to `callee`, found (by [`CallCollector`]) in `block_node` (part of
+/// `parent_region`), as the [`DataInstKind::FuncCall`] instruction `inst`.
+struct Call {
+    parent_region: ControlRegion,
+    block_node: ControlNode,
+    inst: DataInst,
+    callee: Func,
+}
+
+/// Collects all the calls (in a function).
+struct CallCollector<'a> {
+    cx: &'a Context,
+
+    calls: Vec<Call>,
+}
+
+impl<'a> Visitor<'a> for CallCollector<'_> {
+    // NOTE: uses of interned/module-stored definitions are irrelevant
+    // here, as only the instructions in a single function body are inspected.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, _data_inst_form: DataInstForm) {}
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+
+    fn visit_control_region_def(&mut self, func_at_control_region: FuncAt<'a, ControlRegion>) {
+        for func_at_control_node in func_at_control_region.at_children() {
+            if let ControlNodeKind::Block { insts } = func_at_control_node.def().kind {
+                for func_at_inst in func_at_control_node.at(insts) {
+                    if let DataInstKind::FuncCall(callee) = self.cx[func_at_inst.def().form].kind {
+                        self.calls.push(Call {
+                            parent_region: func_at_control_region.position,
+                            block_node: func_at_control_node.position,
+                            inst: func_at_inst.position,
+                            callee,
+                        });
+                    }
+                }
+            }
+        }
+        func_at_control_region.inner_visit_with(self);
+    }
+}
+
+/// Computes the size of a function (see [`TRIVIAL_FUNC_MAX_SIZE`]).
+struct SizeCounter {
+    size: usize,
+}
+
+impl<'a> Visitor<'a> for SizeCounter {
+    // NOTE: uses of interned/module-stored definitions are irrelevant
+    // here, as only the instructions in a single function body are inspected.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, _data_inst_form: DataInstForm) {}
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+
+    fn visit_control_node_def(&mut self, func_at_control_node: FuncAt<'a, ControlNode>) {
+        self.size += 1;
+        if let ControlNodeKind::Block { insts } = func_at_control_node.def().kind {
+            self.size += func_at_control_node.at(insts).into_iter().count();
+        }
+        func_at_control_node.inner_visit_with(self);
+    }
+}
+
+/// Copies [`ControlNode`]s (and everything nested in them) from one function
+/// body (`from`) into another (`to`), recording the mapping in `remapper`
+/// (which has to be applied to all the copies, after copying).
+struct BodyCopier<'a> {
+    cx: &'a Context,
+
+    from: &'a FuncDefBody,
+    to: &'a mut FuncDefBody,
+
+    remapper: CopyRemapper,
+}
+
+impl BodyCopier<'_> {
+    fn copy_control_region(&mut self, region: ControlRegion) -> ControlRegion {
+        let ControlRegionDef { inputs, children, outputs } = &self.from.control_regions[region];
+
+        let mut new_children = EntityList::empty();
+        for func_at_control_node in self.from.at(*children) {
+            let new_node = self.copy_control_node(func_at_control_node.position);
+            new_children.insert_last(new_node, &mut self.to.control_nodes);
+        }
+
+        let new_region = self.to.control_regions.define(
+            self.cx,
+            ControlRegionDef {
+                inputs: inputs.clone(),
+                children: new_children,
+                outputs: outputs.clone(),
+            },
+        );
+        self.remapper.control_regions.insert(region, new_region);
+        new_region
+    }
+
+    fn copy_control_node(&mut self, control_node: ControlNode) -> ControlNode {
+        let ControlNodeDef { kind, outputs } = &*self.from.control_nodes[control_node];
+
+        let kind = match kind {
+            &ControlNodeKind::Block { insts } => {
+                let mut new_insts = EntityList::empty();
+                for func_at_inst in self.from.at(insts) {
+                    let new_inst =
+                        self.to.data_insts.define(self.cx, func_at_inst.def().clone().into());
+                    new_insts.insert_last(new_inst, &mut self.to.data_insts);
+                    self.remapper.data_insts.insert(func_at_inst.position, new_inst);
+                }
+                ControlNodeKind::Block { insts: new_insts }
+            }
+            ControlNodeKind::Select { kind, scrutinee, cases } => ControlNodeKind::Select {
+                kind: kind.clone(),
+                scrutinee: *scrutinee,
+                cases: cases.iter().map(|&case| self.copy_control_region(case)).collect(),
+            },
+            ControlNodeKind::Loop { initial_inputs, body, repeat_condition } => {
+                ControlNodeKind::Loop {
+                    initial_inputs: initial_inputs.clone(),
+                    body: self.copy_control_region(*body),
+                    repeat_condition: *repeat_condition,
+                }
+            }
+        };
+
+        let new_node = self
+            .to
+            .control_nodes
+            .define(self.cx, ControlNodeDef { kind, outputs: outputs.clone() }.into());
+        self.remapper.control_nodes.insert(control_node, new_node);
+        new_node
+    }
+}
+
+/// Rewrites [`Value`]s from the original function body (of a [`BodyCopier`]),
+/// to refer to the copies, and the call arguments (instead of parameters).
+struct CopyRemapper {
+    /// The original function body, whose inputs are the function parameters.
+    params_region: ControlRegion,
+
+    /// The call arguments, to replace uses of the function parameters.
+    args: SmallVec<[Value; 2]>,
+
+    control_regions: FxHashMap<ControlRegion, ControlRegion>,
+    control_nodes: FxHashMap<ControlNode, ControlNode>,
+    data_insts: FxHashMap<DataInst, DataInst>,
+}
+
+impl Transformer for CopyRemapper {
+    fn transform_value_use(&mut self, v: &Value) -> Transformed<Value> {
+        match *v {
+            Value::Const(_) => Transformed::Unchanged,
+            Value::ControlRegionInput { region, input_idx } if region == self.params_region => {
+                Transformed::Changed(self.args[input_idx as usize])
+            }
+            Value::ControlRegionInput { region, input_idx } => {
+                Transformed::Changed(Value::ControlRegionInput {
+                    region: self.control_regions[&region],
+                    input_idx,
+                })
+            }
+            Value::ControlNodeOutput { control_node, output_idx } => {
+                Transformed::Changed(Value::ControlNodeOutput {
+                    control_node: self.control_nodes[&control_node],
+                    output_idx,
+                })
+            }
+            Value::DataInstOutput(inst) => {
+                Transformed::Changed(Value::DataInstOutput(self.data_insts[&inst]))
+            }
+        }
+    }
+}
+
+/// Replaces all uses of the output of `call_inst` with `replacement`.
+struct CallOutputReplacer {
+    call_inst: DataInst,
+    replacement: Value,
+}
+
+impl Transformer for CallOutputReplacer {
+    fn transform_value_use(&mut self, v: &Value) -> Transformed<Value> {
+        match *v {
+            Value::DataInstOutput(inst) if inst == self.call_inst => {
+                Transformed::Changed(self.replacement)
+            }
+            _ => Transformed::Unchanged,
+        }
+    }
+}
+
+#[test]
+fn inline_single_call() {
+    let spv_words = crate::spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "main" Export
+            %2 = OpTypeInt 32 0
+            %3 = OpTypeFunction %2 %2
+            %12 = OpTypeFunction %2
+            %8 = OpConstant %2 1
+            %11 = OpConstant %2 5
+            %4 = OpFunction %2 None %3
+            %5 = OpFunctionParameter %2
+            %6 = OpLabel
+            %7 = OpIAdd %2 %5 %8
+            OpReturnValue %7
+            OpFunctionEnd
+            %1 = OpFunction %2 None %12
+            %9 = OpLabel
+            %10 = OpFunctionCall %2 %4 %11
+            OpReturnValue %10
+            OpFunctionEnd
+            "#,
+    );
+    let mut module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+    super::legalize::structurize_func_cfgs(&mut module);
+
+    assert_eq!(inline_trivial(&mut module), 1);
+    assert_eq!(inline_trivial(&mut module), 0);
+    crate::print::assert_module_snapshot(&module, "tests/snapshots/inline_single_call.spirt");
+
+    // Only `main` is left (as the only function reachable from exports),
+    // and it should contain the `OpIAdd` instead of the `OpFunctionCall`.
+    let wk = &crate::spv::spec::Spec::get().well_known;
+    let spv_words = module.lift_to_spv_words().unwrap();
+    let opcodes: Vec<_> = crate::spv::read::ModuleParser::read_from_spv_words(&spv_words)
+        .unwrap()
+        .map(|inst| inst.unwrap().opcode)
+        .collect();
+    assert_eq!(opcodes.iter().filter(|&&opcode| opcode == wk.OpFunction).count(), 1);
+    assert!(!opcodes.contains(&wk.OpFunctionCall));
+    assert!(opcodes.iter().any(|opcode| opcode.name() == "OpIAdd"));
+}
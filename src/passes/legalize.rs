@@ -1,27 +1,14 @@
-use crate::visit::{InnerVisit, Visitor};
-use crate::{
-    cfg, AttrSet, Const, Context, DataInstForm, DeclDef, Func, FxIndexSet, GlobalVar, Module, Type,
-};
+use crate::visit::{InnerVisit, ReachableUseCollector, Visitor};
+use crate::{cfg, AttrSet, Const, Context, DataInstForm, DeclDef, Func, GlobalVar, Module, Type};
+use rustc_hash::FxHashSet;
 
 /// Apply the [`cfg::Structurizer`] algorithm to all function definitions in `module`.
 pub fn structurize_func_cfgs(module: &mut Module) {
     let cx = &module.cx();
 
-    // FIXME(eddyb) reuse this collection work in some kind of "pass manager".
-    let mut collector = ReachableUseCollector {
-        cx,
-        module,
-
-        seen_types: FxIndexSet::default(),
-        seen_consts: FxIndexSet::default(),
-        seen_data_inst_forms: FxIndexSet::default(),
-        seen_global_vars: FxIndexSet::default(),
-        seen_funcs: FxIndexSet::default(),
-    };
-    for (export_key, &exportee) in &module.exports {
-        export_key.inner_visit_with(&mut collector);
-        exportee.inner_visit_with(&mut collector);
-    }
+    // FIXME: reuse this collection work in some kind of "pass manager".
+    let mut collector = ReachableUseCollector::new(module);
+    collector.visit_module_exports();
 
     for &func in &collector.seen_funcs {
         if let DeclDef::Present(func_def_body) = &mut module.funcs[func].def {
@@ -30,23 +17,72 @@ pub fn structurize_func_cfgs(module: &mut Module) {
     }
 }
 
-struct ReachableUseCollector<'a> {
+/// Error produced by [`check_definition_order`], for global variables which can't
+/// be ordered wrt their initializers (see [`DefinitionCycle::global_vars`]).
+#[derive(Clone, PartialEq, Eq)]
+pub struct DefinitionCycle {
+    /// The global variables involved in the cycle, with each one's initializer
+    /// (transitively) using a pointer to the next one (and the last one's
+    /// initializer using a pointer to the first one).
+    pub global_vars: Vec<GlobalVar>,
+}
+
+/// Check (without changing `module`) that all the definitions reachable from
+/// exports (of types, constants, and global variables) can be ordered such that
+/// every use follows the definition it refers to (as required by SPIR-V outside
+/// of function bodies), i.e. that [`lift`](crate::spv::lift) will succeed in
+/// emitting them in such an order.
+///
+/// Types and constants are interned (and so can only refer to older ones), and
+/// [`lift`](crate::spv::lift) already emits definitions in a post-order
+/// (i.e. definitions first, then uses), so this can only fail for modules with
+/// global variables whose initializers refer back to them (e.g. through
+/// [`ConstKind::PtrToGlobalVar`](crate::ConstKind::PtrToGlobalVar)).
+//
+// NOTE: there is no module-wide list of definitions that could be reordered
+// in-place (lifting computes that order on the fly), so there's no separate
+// pass to legalize the order, only this check for the cycles preventing it.
+pub fn check_definition_order(module: &Module) -> Result<(), DefinitionCycle> {
+    let mut finder = DefinitionCycleFinder {
+        cx: &module.cx(),
+        module,
+
+        global_var_stack: vec![],
+        cycle: None,
+
+        seen_types: FxHashSet::default(),
+        seen_consts: FxHashSet::default(),
+        seen_data_inst_forms: FxHashSet::default(),
+        seen_global_vars: FxHashSet::default(),
+        seen_funcs: FxHashSet::default(),
+    };
+    for (export_key, &exportee) in &module.exports {
+        export_key.inner_visit_with(&mut finder);
+        exportee.inner_visit_with(&mut finder);
+    }
+    finder.cycle.map_or(Ok(()), Err)
+}
+
+struct DefinitionCycleFinder<'a> {
     cx: &'a Context,
     module: &'a Module,
 
-    // FIXME(eddyb) build some automation to avoid ever repeating these.
-    seen_types: FxIndexSet<Type>,
-    seen_consts: FxIndexSet<Const>,
-    seen_data_inst_forms: FxIndexSet<DataInstForm>,
-    seen_global_vars: FxIndexSet<GlobalVar>,
-    seen_funcs: FxIndexSet<Func>,
+    /// Global variables whose declarations are currently being visited
+    /// (i.e. the path taken through initializers to reach the current one).
+    global_var_stack: Vec<GlobalVar>,
+    cycle: Option<DefinitionCycle>,
+
+    // FIXME: build some automation to avoid ever repeating these.
+    seen_types: FxHashSet<Type>,
+    seen_consts: FxHashSet<Const>,
+    seen_data_inst_forms: FxHashSet<DataInstForm>,
+    seen_global_vars: FxHashSet<GlobalVar>,
+    seen_funcs: FxHashSet<Func>,
 }
 
-impl Visitor<'_> for ReachableUseCollector<'_> {
-    // FIXME(eddyb) build some automation to avoid ever repeating these.
-    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {
-        // FIXME(eddyb) if `AttrSet`s are ignored, why not `Type`s too?
-    }
+impl Visitor<'_> for DefinitionCycleFinder<'_> {
+    // FIXME: build some automation to avoid ever repeating these.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
     fn visit_type_use(&mut self, ty: Type) {
         if self.seen_types.insert(ty) {
             self.visit_type_def(&self.cx[ty]);
@@ -64,13 +100,67 @@ impl Visitor<'_> for ReachableUseCollector<'_> {
     }
 
     fn visit_global_var_use(&mut self, gv: GlobalVar) {
+        if self.cycle.is_some() {
+            return;
+        }
+        if let Some(i) = self.global_var_stack.iter().position(|&stack_gv| stack_gv == gv) {
+            self.cycle = Some(DefinitionCycle { global_vars: self.global_var_stack[i..].to_vec() });
+            return;
+        }
         if self.seen_global_vars.insert(gv) {
+            self.global_var_stack.push(gv);
             self.visit_global_var_decl(&self.module.global_vars[gv]);
+            self.global_var_stack.pop();
         }
     }
     fn visit_func_use(&mut self, func: Func) {
+        // NOTE: function bodies can refer to anything (including their
+        // own function), but are only visited to find all used definitions.
         if self.seen_funcs.insert(func) {
+            let global_var_stack = std::mem::take(&mut self.global_var_stack);
             self.visit_func_decl(&self.module.funcs[func]);
+            self.global_var_stack = global_var_stack;
         }
     }
 }
+
+#[test]
+fn self_referential_global_var_initializer() {
+    use crate::builder::ModuleBuilder;
+    use crate::{spv, AddrSpace, ConstDef, ConstKind, Exportee, GlobalVarDecl, GlobalVarDefBody};
+
+    let wk = &spv::spec::Spec::get().well_known;
+    let storage_class_private = 6;
+
+    let mut builder = ModuleBuilder::new(std::rc::Rc::new(Context::new()));
+    let cx = builder.cx();
+    let u32_type = builder.int_type(32, false);
+    let ptr_type = builder.spv_type(
+        spv::Inst {
+            opcode: wk.OpTypePointer,
+            imms: [spv::Imm::Short(wk.StorageClass, storage_class_private)].into_iter().collect(),
+        },
+        [crate::TypeOrConst::Type(u32_type)],
+    );
+    let gv = builder.declare_global_var(GlobalVarDecl {
+        attrs: AttrSet::default(),
+        type_of_ptr_to: ptr_type,
+        shape: None,
+        addr_space: AddrSpace::SpvStorageClass(storage_class_private),
+        def: DeclDef::Present(GlobalVarDefBody { initializer: None }),
+    });
+    builder.export_link_name("gv", Exportee::GlobalVar(gv));
+    let mut module = builder.finish();
+
+    assert!(check_definition_order(&module).is_ok());
+
+    let ptr_to_gv = cx.intern(ConstDef {
+        attrs: AttrSet::default(),
+        ty: ptr_type,
+        kind: ConstKind::PtrToGlobalVar(gv),
+    });
+    module.global_vars[gv].def =
+        DeclDef::Present(GlobalVarDefBody { initializer: Some(ptr_to_gv) });
+
+    assert!(check_definition_order(&module) == Err(DefinitionCycle { global_vars: vec![gv] }));
+}
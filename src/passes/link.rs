@@ -1,4 +1,6 @@
-use crate::transform::{InnerTransform, Transformed, Transformer};
+use crate::transform::{
+    cached_transform_interned_uses, InnerTransform, Transformed, Transformer,
+};
 use crate::visit::{InnerVisit, Visitor};
 use crate::{
     AttrSet, Const, Context, DataInstForm, DeclDef, ExportKey, Exportee, Func, FxIndexSet,
@@ -258,38 +260,7 @@ struct ImportResolver<'a> {
 }
 
 impl Transformer for ImportResolver<'_> {
-    // FIXME(eddyb) build some automation to avoid ever repeating these.
-    fn transform_type_use(&mut self, ty: Type) -> Transformed<Type> {
-        if let Some(&cached) = self.transformed_types.get(&ty) {
-            return cached;
-        }
-        let transformed =
-            self.transform_type_def(&self.cx[ty]).map(|ty_def| self.cx.intern(ty_def));
-        self.transformed_types.insert(ty, transformed);
-        transformed
-    }
-    fn transform_const_use(&mut self, ct: Const) -> Transformed<Const> {
-        if let Some(&cached) = self.transformed_consts.get(&ct) {
-            return cached;
-        }
-        let transformed =
-            self.transform_const_def(&self.cx[ct]).map(|ct_def| self.cx.intern(ct_def));
-        self.transformed_consts.insert(ct, transformed);
-        transformed
-    }
-    fn transform_data_inst_form_use(
-        &mut self,
-        data_inst_form: DataInstForm,
-    ) -> Transformed<DataInstForm> {
-        if let Some(&cached) = self.transformed_data_inst_forms.get(&data_inst_form) {
-            return cached;
-        }
-        let transformed = self
-            .transform_data_inst_form_def(&self.cx[data_inst_form])
-            .map(|data_inst_form_def| self.cx.intern(data_inst_form_def));
-        self.transformed_data_inst_forms.insert(data_inst_form, transformed);
-        transformed
-    }
+    cached_transform_interned_uses!();
 
     fn transform_global_var_use(&mut self, gv: GlobalVar) -> Transformed<GlobalVar> {
         if let Some(&cached) = self.transformed_global_vars.get(&gv) {
@@ -0,0 +1,2686 @@
+//! Merging of whole [`Module`]s (i.e. copying one module into another).
+
+use crate::func_at::FuncAt;
+use crate::passes::validate::ReferenceError;
+use crate::transform::{
+    cached_transform_interned_uses, InnerInPlaceTransform, InnerTransform, Transformed,
+    Transformer,
+};
+use crate::visit::{InnerVisit, ReachableUseCollector, Visitor};
+use crate::{
+    cfg, spv, Attr, AttrSet, AttrSetDef, Const, Context, ControlNode, ControlNodeKind,
+    ControlRegion, DataInst, DataInstForm, DataInstKind, DeclDef, ExportKey, Exportee, Func,
+    FuncDecl, FxIndexSet, GlobalVar, InternedStr, Module, ModuleDebugInfo, ModuleDialect,
+    SelectionKind, Type, TypeKind, Value,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::rc::Rc;
+
+/// Error produced when two [`Module`]s can't be merged.
+#[derive(Clone, PartialEq, Eq)]
+pub enum MergeError {
+    /// The SPIR-V versions of the two modules differ.
+    VersionMismatch { mergee: spv::SpvVersion, merged: spv::SpvVersion },
+
+    /// The SPIR-V `AddressingModel`s of the two modules differ.
+    AddressingModelMismatch { mergee: spv::AddressingModel, merged: spv::AddressingModel },
+
+    /// The SPIR-V `MemoryModel`s of the two modules differ.
+    MemoryModelMismatch { mergee: spv::MemoryModel, merged: spv::MemoryModel },
+
+    /// Unioning the capabilities of the two modules would result in the
+    /// (known to be incompatible) SPIR-V capabilities `a` (from `mergee`)
+    /// and `b` (from `merged`) being declared together (e.g. `Shader` and `Kernel`).
+    IncompatibleCapabilities { a: u32, b: u32 },
+
+    /// Both modules have an export with the same [`ExportKey`] (and the two
+    /// exports aren't identical, see [`ExportConflictPolicy`]), with `name`
+    /// being its link name or entry-point name (only used for display).
+    DuplicateExportKey { export_key: ExportKey, name: String },
+
+    /// `merged` had its memory/addressing model overridden (as per [`ModelPolicy`]),
+    /// but `func` (reachable from its exports) contains memory operations, which
+    /// could depend on the original model.
+    IncompatibleMemoryOperations { func: Func },
+
+    /// Moving `merged` to `mergee`'s [`Context`] (by lifting it to SPIR-V, and
+    /// lowering it back) failed, see [`merge_via_spv_roundtrip`].
+    ContextTransferFailed { reason: String },
+
+    /// Merging the module at index `module_idx` (in the inputs of [`merge_all`])
+    /// failed, with `error`.
+    InModule { module_idx: usize, error: Box<MergeError> },
+
+    /// [`merge_all`] requires at least one module.
+    NoModules,
+
+    /// An [`ExportKey`] passed to [`merge_selected`] isn't exported by `merged`
+    /// (with `name` like in [`MergeError::DuplicateExportKey`]).
+    UnknownExportKey { export_key: ExportKey, name: String },
+
+    /// The entry-point function `target` (after merging) has more than one
+    /// SPIR-V `OpExecutionMode` for the same `ExecutionMode` (`mode`), with
+    /// different operands (e.g. two different `LocalSize`s), either by itself,
+    /// or combined with another entry-point with the same execution model and
+    /// name (which SPIR-V requires to be unique, i.e. they can't be separate).
+    ConflictingExecutionMode { target: Func, mode: u32 },
+
+    /// `mergee` refers to undefined [`GlobalVar`]s/[`Func`]s (only checked
+    /// with [`MergeOptions::validate_inputs`] enabled).
+    InvalidMergee(Vec<ReferenceError>),
+
+    /// `merged` refers to undefined [`GlobalVar`]s/[`Func`]s (only checked
+    /// with [`MergeOptions::validate_inputs`] enabled).
+    InvalidMerged(Vec<ReferenceError>),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VersionMismatch { mergee, merged } => {
+                write!(f, "SPIR-V version mismatch: {mergee} vs {merged}")
+            }
+            Self::AddressingModelMismatch { mergee, merged } => {
+                write!(f, "SPIR-V addressing model mismatch: {mergee} vs {merged}")
+            }
+            Self::MemoryModelMismatch { mergee, merged } => {
+                write!(f, "SPIR-V memory model mismatch: {mergee} vs {merged}")
+            }
+            Self::IncompatibleCapabilities { a, b } => {
+                let name = |cap: u32| {
+                    spv::CapabilityDiff::capability_name(cap)
+                        .map_or_else(|| cap.to_string(), |name| name.to_string())
+                };
+                write!(f, "incompatible SPIR-V capabilities: {} vs {}", name(*a), name(*b))
+            }
+            Self::DuplicateExportKey { export_key, name } => match export_key {
+                ExportKey::LinkName(_) => write!(f, "duplicate export (link name `{name}`)"),
+                ExportKey::SpvEntryPoint { .. } => {
+                    write!(f, "duplicate export (entry-point `{name}`)")
+                }
+            },
+            Self::IncompatibleMemoryOperations { func: _ } => f.write_str(
+                "cannot override memory/addressing model of module using memory operations",
+            ),
+            Self::ContextTransferFailed { reason } => {
+                write!(f, "failed to move module to a different `Context`: {reason}")
+            }
+            Self::InModule { module_idx, error } => write!(f, "in module #{module_idx}: {error}"),
+            Self::NoModules => f.write_str("no modules to merge"),
+            Self::UnknownExportKey { export_key, name } => match export_key {
+                ExportKey::LinkName(_) => write!(f, "unknown export (link name `{name}`)"),
+                ExportKey::SpvEntryPoint { .. } => {
+                    write!(f, "unknown export (entry-point `{name}`)")
+                }
+            },
+            Self::ConflictingExecutionMode { target: _, mode } => {
+                write!(f, "entry-point has conflicting execution modes (for mode {mode})")
+            }
+            Self::InvalidMergee(errors) => {
+                write!(f, "`mergee` has {} undefined references", errors.len())
+            }
+            Self::InvalidMerged(errors) => {
+                write!(f, "`merged` has {} undefined references", errors.len())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl MergeError {
+    fn duplicate_export_key(cx: &Context, export_key: ExportKey) -> Self {
+        let name = export_key_name(cx, &export_key);
+        Self::DuplicateExportKey { export_key, name }
+    }
+
+    fn unknown_export_key(cx: &Context, export_key: ExportKey) -> Self {
+        let name = export_key_name(cx, &export_key);
+        Self::UnknownExportKey { export_key, name }
+    }
+}
+
+/// The link name or entry-point name of `export_key` (for [`MergeError`]s).
+fn export_key_name(cx: &Context, export_key: &ExportKey) -> String {
+    match export_key {
+        &ExportKey::LinkName(name) => cx[name].to_string(),
+        ExportKey::SpvEntryPoint { imms, .. } => spv::extract_literal_string(&imms[1..])
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()),
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// Options for [`merge_with`] (and [`make_compatible_with`]).
+#[derive(Copy, Clone, Default)]
+pub struct MergeOptions {
+    /// How to handle differing SPIR-V versions (see [`VersionPolicy`]).
+    pub version_policy: VersionPolicy,
+
+    /// How to handle differing SPIR-V memory models (see [`ModelPolicy`]).
+    pub memory_model_policy: ModelPolicy,
+
+    /// How to handle differing SPIR-V addressing models (see [`ModelPolicy`]).
+    pub addressing_model_policy: ModelPolicy,
+
+    /// Whether to reuse existing functions in `mergee` (i.e. reachable from its
+    /// exports), instead of copying structurally identical ones from `merged`.
+    ///
+    /// Functions are compared by their whole definition (including attributes,
+    /// e.g. debuginfo), after rewriting `merged`'s [`GlobalVar`]s and [`Func`]s,
+    /// so calls to already-deduplicated functions also compare equal (but uses
+    /// of `merged`'s [`GlobalVar`]s never do, as those are always copied).
+    ///
+    /// This is off by default, as it requires traversing all the functions of
+    /// both modules (potentially multiple times, to handle call chains).
+    pub dedup_functions: bool,
+
+    /// How to handle exports present in both modules (see [`ExportConflictPolicy`]).
+    pub on_export_conflict: ExportConflictPolicy,
+
+    /// Whether to rename (by adding a `.1`, `.2`, etc. suffix) the SPIR-V `OpName`s
+    /// of [`GlobalVar`]s and [`Func`]s copied from `merged`, which are already
+    /// used by definitions in `mergee` (or other copied definitions).
+    ///
+    /// Definitions exported by `merged` are always left untouched, as their
+    /// names are more likely to be intentional (e.g. entry-points all named `main`).
+    pub dedup_debug_names: bool,
+
+    /// If `Some`, prefix the SPIR-V `OpName`s of [`GlobalVar`]s and [`Func`]s
+    /// copied from `merged` with it (e.g. `lib.` to namespace a library's
+    /// definitions), applied before [`MergeOptions::dedup_debug_names`].
+    ///
+    /// Much like with [`MergeOptions::dedup_debug_names`], definitions exported
+    /// by `merged` are always left untouched, as their names (and export keys)
+    /// are its stable public interface.
+    ///
+    /// Note that `OpMemberName`s are never prefixed, as they're attached to
+    /// types, which are shared (through the [`Context`]), not copied.
+    pub debug_name_prefix: Option<InternedStr>,
+
+    /// Whether to check (using [`Module::validate_references`]) that both modules
+    /// are valid on their own, before merging them, to avoid an invalid `merged`
+    /// (e.g. a malformed object file) silently corrupting `mergee`.
+    pub validate_inputs: bool,
+
+    /// Whether to change the SPIR-V `Binding` decorations of [`GlobalVar`]s
+    /// copied from `merged`, which collide (i.e. have the same `DescriptorSet`
+    /// and `Binding`) with those of `mergee` (or other copied definitions),
+    /// to the lowest free `Binding` in the same `DescriptorSet`.
+    ///
+    /// [`GlobalVar`]s exported by `merged` are always left untouched, as they
+    /// can be intentionally shared (see also [`MergeReport::rebindings`]).
+    pub resolve_binding_conflicts: bool,
+}
+
+/// How [`merge_with`] should handle an [`ExportKey`] exported by both modules.
+///
+/// Entry-points can only conflict if they have no interface global variables
+/// (as any `merged` has are copied, and so always differ from `mergee`'s).
+///
+/// Functions exported under the same [`ExportKey`] by both modules, which have
+/// identical definitions (e.g. a helper both modules depend on and re-export),
+/// aren't considered conflicting, and `mergee`'s export is always kept.
+/// Any functions they call are compared the same way (recursively).
+//
+// FIXME: functions using global variables are never considered identical,
+// even if the global variables themselves have identical declarations.
+#[derive(Copy, Clone, Default)]
+pub enum ExportConflictPolicy {
+    /// Error (with [`MergeError::DuplicateExportKey`]).
+    #[default]
+    Error,
+
+    /// Keep `mergee`'s export, ignoring `merged`'s (and also anything only
+    /// reachable from it, which isn't copied into `mergee`).
+    KeepMergee,
+
+    /// Replace `mergee`'s export with `merged`'s (leaving anything which was only
+    /// reachable from `mergee`'s export unused, i.e. ignored by e.g. lifting).
+    KeepMerged,
+
+    /// Export `merged`'s exportee under a new key, which must not conflict with
+    /// any other exports (or [`MergeError::DuplicateExportKey`] is returned).
+    Rename(fn(&Context, &ExportKey) -> ExportKey),
+}
+
+/// Summary of the changes made by [`merge_with_report`].
+#[derive(Clone, Default)]
+pub struct MergeReport {
+    /// Number of types reachable from `merged`'s exports.
+    ///
+    /// Types are interned in the [`Context`] shared by both modules, so they're
+    /// never copied, and any also used by `mergee` are implicitly deduplicated.
+    pub reachable_types: usize,
+
+    /// Number of constants reachable from `merged`'s exports (like types,
+    /// they're interned, and therefore never copied).
+    pub reachable_consts: usize,
+
+    /// Number of [`GlobalVar`]s copied from `merged` into `mergee`.
+    pub copied_global_vars: usize,
+
+    /// Number of [`Func`]s copied from `merged` into `mergee`.
+    pub copied_funcs: usize,
+
+    /// Number of [`Func`]s from `merged` replaced with identical ones already in
+    /// `mergee` (only possible with [`MergeOptions::dedup_functions`] enabled).
+    pub deduplicated_funcs: usize,
+
+    /// SPIR-V capabilities which `mergee` didn't already have.
+    pub added_capabilities: BTreeSet<u32>,
+
+    /// SPIR-V extensions which `mergee` didn't already have.
+    pub added_extensions: BTreeSet<String>,
+
+    /// Exports added to `mergee` (in the order they were added in).
+    pub added_exports: Vec<ExportKey>,
+
+    /// [`GlobalVar`]s copied from `merged` which had their `Binding` changed
+    /// (only possible with [`MergeOptions::resolve_binding_conflicts`] enabled).
+    pub rebindings: Vec<Rebinding>,
+}
+
+/// A [`GlobalVar`] whose SPIR-V `Binding` decoration was changed, to avoid
+/// colliding with another [`GlobalVar`] (see [`MergeOptions::resolve_binding_conflicts`]).
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Rebinding {
+    /// The [`GlobalVar`] (in `mergee`, i.e. after copying).
+    pub global_var: GlobalVar,
+
+    pub descriptor_set: u32,
+    pub old_binding: u32,
+    pub new_binding: u32,
+}
+
+impl MergeReport {
+    /// Combine the changes from `other` (i.e. a later merge) into `self`.
+    fn accumulate(&mut self, other: MergeReport) {
+        let MergeReport {
+            reachable_types,
+            reachable_consts,
+            copied_global_vars,
+            copied_funcs,
+            deduplicated_funcs,
+            added_capabilities,
+            added_extensions,
+            added_exports,
+            rebindings,
+        } = other;
+
+        self.reachable_types += reachable_types;
+        self.reachable_consts += reachable_consts;
+        self.copied_global_vars += copied_global_vars;
+        self.copied_funcs += copied_funcs;
+        self.deduplicated_funcs += deduplicated_funcs;
+        self.added_capabilities.extend(added_capabilities);
+        self.added_extensions.extend(added_extensions);
+        self.added_exports.extend(added_exports);
+        self.rebindings.extend(rebindings);
+    }
+}
+
+/// How [`make_compatible_with`] should handle differing SPIR-V versions.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum VersionPolicy {
+    /// Error (with [`MergeError::VersionMismatch`]) if the versions differ.
+    #[default]
+    Strict,
+
+    /// Use the highest of the two versions.
+    ///
+    /// Newer SPIR-V versions are mostly backwards-compatible, but some features
+    /// are gated on (or were changed by) specific versions, so the caller may
+    /// have to ensure no conflicts arise from such features (e.g. the rules
+    /// for which global variables need to be listed by `OpEntryPoint`).
+    TakeMax,
+
+    /// Keep `mergee`'s version, regardless of `merged`'s version.
+    TakeMergee,
+}
+
+/// How [`make_compatible_with`] should handle differing SPIR-V memory models
+/// (or addressing models, which are handled the same way).
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum ModelPolicy {
+    /// Error (with e.g. [`MergeError::MemoryModelMismatch`]) if the models differ.
+    #[default]
+    RequireEqual,
+
+    /// Keep `mergee`'s model, overriding `merged`'s model.
+    ///
+    /// This is only sound if `merged` doesn't rely on its original model, which
+    /// [`merge_with`] (but not [`make_compatible_with`]) checks, conservatively,
+    /// by rejecting any use of pointers by the functions being merged (with
+    /// [`MergeError::IncompatibleMemoryOperations`]).
+    TakeMergee,
+}
+
+/// Make `mergee`'s dialect compatible with `merged`'s, i.e. check that all the
+/// aspects which can't be combined are equal, and combine the rest into `mergee`
+/// (e.g. capabilities and extensions are unioned).
+pub fn make_compatible(
+    mergee: &mut ModuleDialect,
+    merged: &ModuleDialect,
+) -> Result<(), MergeError> {
+    make_compatible_with(mergee, merged, &MergeOptions::default())
+}
+
+/// Like [`make_compatible`], but with some of the checks relaxed by `options`.
+pub fn make_compatible_with(
+    mergee: &mut ModuleDialect,
+    merged: &ModuleDialect,
+    options: &MergeOptions,
+) -> Result<(), MergeError> {
+    match (mergee, merged) {
+        (ModuleDialect::Spv(mergee), ModuleDialect::Spv(merged)) => {
+            make_spv_compatible(mergee, merged, options)
+        }
+    }
+}
+
+fn make_spv_compatible(
+    mergee: &mut spv::Dialect,
+    merged: &spv::Dialect,
+    options: &MergeOptions,
+) -> Result<(), MergeError> {
+    if mergee.version() != merged.version() {
+        match options.version_policy {
+            VersionPolicy::Strict => {
+                return Err(MergeError::VersionMismatch {
+                    mergee: mergee.version(),
+                    merged: merged.version(),
+                });
+            }
+            VersionPolicy::TakeMax => {
+                if merged.version() > mergee.version() {
+                    mergee.version_major = merged.version_major;
+                    mergee.version_minor = merged.version_minor;
+                }
+            }
+            VersionPolicy::TakeMergee => {}
+        }
+    }
+
+    if mergee.addressing_model != merged.addressing_model
+        && options.addressing_model_policy == ModelPolicy::RequireEqual
+    {
+        return Err(MergeError::AddressingModelMismatch {
+            mergee: mergee.addressing_model(),
+            merged: merged.addressing_model(),
+        });
+    }
+    if mergee.memory_model != merged.memory_model
+        && options.memory_model_policy == ModelPolicy::RequireEqual
+    {
+        return Err(MergeError::MemoryModelMismatch {
+            mergee: mergee.memory_model(),
+            merged: merged.memory_model(),
+        });
+    }
+
+    check_capabilities(mergee, merged)?;
+
+    mergee.capabilities.extend(merged.capabilities.iter().copied());
+    mergee.extensions.extend(merged.extensions.iter().cloned());
+
+    Ok(())
+}
+
+/// Pairs of SPIR-V capabilities (by name) which can't be declared by the same
+/// module, e.g. because they imply different execution environments.
+//
+// FIXME: this is far from exhaustive, but `Shader` vs `Kernel` (i.e.
+// Vulkan/GLSL-style vs OpenCL-style modules) is the most common mistake.
+const INCOMPATIBLE_CAPABILITIES: &[(&str, &str)] = &[("Shader", "Kernel")];
+
+/// Check that unioning the capabilities of `mergee` and `merged` wouldn't
+/// combine any of [`INCOMPATIBLE_CAPABILITIES`] (pairs already declared
+/// together by either module on its own are left alone, as merging can't
+/// make them any worse).
+fn check_capabilities(mergee: &spv::Dialect, merged: &spv::Dialect) -> Result<(), MergeError> {
+    let by_name = spv::CapabilityDiff::capability_by_name;
+    for &(x, y) in INCOMPATIBLE_CAPABILITIES {
+        let (Some(x), Some(y)) = (by_name(x), by_name(y)) else {
+            continue;
+        };
+        let has = |dialect: &spv::Dialect, cap| dialect.capabilities.contains(&cap);
+        if has(mergee, x) && has(mergee, y) || has(merged, x) && has(merged, y) {
+            continue;
+        }
+        for (a, b) in [(x, y), (y, x)] {
+            if has(mergee, a) && has(merged, b) {
+                return Err(MergeError::IncompatibleCapabilities { a, b });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn merge_debug_info(mergee: &mut ModuleDebugInfo, merged: ModuleDebugInfo) {
+    match (mergee, merged) {
+        (ModuleDebugInfo::Spv(mergee), ModuleDebugInfo::Spv(merged)) => {
+            // NOTE: `original_generator_magic` and `original_id_bound` are
+            // kept from `mergee`, as it's the module being "continued" by the merge.
+            let spv::ModuleDebugInfo {
+                original_generator_magic: _,
+                original_id_bound: _,
+                source_languages,
+                source_extensions,
+                module_processes,
+            } = merged;
+
+            // NOTE: records from different source languages (or versions)
+            // are all kept, but a file can only have one source text (per
+            // language), so for any such collisions, `mergee`'s text is kept.
+            for (lang, sources) in source_languages {
+                let mergee_file_contents =
+                    &mut mergee.source_languages.entry(lang).or_default().file_contents;
+                for (file, contents) in sources.file_contents {
+                    mergee_file_contents.entry(file).or_insert(contents);
+                }
+            }
+            for ext in source_extensions {
+                if !mergee.source_extensions.contains(&ext) {
+                    mergee.source_extensions.push(ext);
+                }
+            }
+            for proc in module_processes {
+                if !mergee.module_processes.contains(&proc) {
+                    mergee.module_processes.push(proc);
+                }
+            }
+        }
+    }
+}
+
+/// Merge `merged` into `mergee`, copying all of the [`GlobalVar`]s and [`Func`]s
+/// reachable from `merged`'s exports, and adding its exports to `mergee`'s.
+///
+/// Both modules must share the same [`Context`] (see [`merge_via_spv_roundtrip`]
+/// for merging modules with different ones), which also means that any
+/// interned definitions (types, constants, etc.) are automatically shared.
+/// This includes extended instruction sets (e.g. `GLSL.std.450`), which are
+/// referred to by name (see [`DataInstKind::SpvExtInst`](crate::DataInstKind::SpvExtInst)),
+/// so even if the two modules originally used different SPIR-V IDs to import
+/// the same set, only one `OpExtInstImport` will be emitted when lifting.
+///
+/// Attributes (e.g. SPIR-V decorations) are part of the identity of interned
+/// definitions, so two structurally identical types which are decorated
+/// differently (e.g. a struct with `Block`, and the same struct without it)
+/// always remain distinct types after merging, and are never conflicting
+/// (i.e. the merge can't change the decorations of either module's types).
+///
+/// No import resolution is performed, i.e. any [`Import::LinkName`](crate::Import::LinkName)
+/// on one side, matching an export from the other side, will remain an import
+/// (using [`link::resolve_imports`](super::link::resolve_imports) afterwards is
+/// recommended, to connect the imports to their definitions).
+pub fn merge(mergee: &mut Module, merged: Module) -> Result<(), MergeError> {
+    merge_with(mergee, merged, &MergeOptions::default())
+}
+
+/// Like [`merge`], but only for the `wanted` exports of `merged` (i.e. only
+/// those exports, and whatever is reachable from them, are added to `mergee`).
+pub fn merge_selected(
+    mergee: &mut Module,
+    merged: Module,
+    wanted: &[ExportKey],
+) -> Result<(), MergeError> {
+    let mut merged = merged;
+    if let Some(unknown) = wanted.iter().find(|&key| !merged.exports.contains_key(key)) {
+        return Err(MergeError::unknown_export_key(merged.cx_ref(), unknown.clone()));
+    }
+    // FIXME: this is quadratic in the number of `wanted` exports.
+    merged.exports.retain(|export_key, _| wanted.contains(export_key));
+    merge(mergee, merged)
+}
+
+/// Merge all of `modules` together, in order, i.e. by merging each module into
+/// the first one (see [`merge`]), which is returned.
+pub fn merge_all(modules: impl IntoIterator<Item = Module>) -> Result<Module, MergeError> {
+    merge_all_with(modules, &MergeOptions::default()).map(|(module, _)| module)
+}
+
+/// Like [`merge_all`], but with `options` (see [`merge_with`]), and also
+/// returning a [`MergeReport`], combining the changes from every merge.
+pub fn merge_all_with(
+    modules: impl IntoIterator<Item = Module>,
+    options: &MergeOptions,
+) -> Result<(Module, MergeReport), MergeError> {
+    let mut modules = modules.into_iter();
+    let mut mergee = modules.next().ok_or(MergeError::NoModules)?;
+    let mut report = MergeReport::default();
+    for (i, merged) in modules.enumerate() {
+        let module_idx = i + 1;
+        let merge_report = merge_with_report(&mut mergee, merged, options)
+            .map_err(|error| MergeError::InModule { module_idx, error: Box::new(error) })?;
+        report.accumulate(merge_report);
+    }
+    Ok((mergee, report))
+}
+
+/// Like [`merge`], but `merged` doesn't have to share `mergee`'s [`Context`].
+///
+/// If the two modules don't already share the same [`Context`], `merged` is
+/// first moved to `mergee`'s, by lifting it to SPIR-V and lowering it back,
+/// which requires `merged` to be liftable (e.g. [`qptr`](crate::qptr) types
+/// must have been lifted already), and loses any SPIR-T-only information
+/// (e.g. structured control-flow will need to be structurized again).
+//
+// FIXME: replace this with a `merge_into_context(mergee, merged, src_cx)`
+// which re-interns `Type`s/`Const`s/`DataInstForm`s (etc.) from `src_cx`
+// while copying, but that also requires redefining all the entities in each
+// function body (`ControlRegion`s/`ControlNode`s/`DataInst`s), as their
+// handles are allocated from (and so only unique within) the `Context`.
+pub fn merge_via_spv_roundtrip(mergee: &mut Module, merged: Module) -> Result<(), MergeError> {
+    if Rc::ptr_eq(mergee.cx_ref(), merged.cx_ref()) {
+        return merge(mergee, merged);
+    }
+
+    let transfer_failed =
+        |e: std::io::Error| MergeError::ContextTransferFailed { reason: e.to_string() };
+    let spv_words = merged.lift_to_spv_words().map_err(transfer_failed)?;
+    let merged = Module::lower_from_spv_words(mergee.cx(), &spv_words).map_err(transfer_failed)?;
+    merge(mergee, merged)
+}
+
+/// Like [`merge`], but with the compatibility checks relaxed by `options`
+/// (see also [`make_compatible_with`]).
+pub fn merge_with(
+    mergee: &mut Module,
+    merged: Module,
+    options: &MergeOptions,
+) -> Result<(), MergeError> {
+    merge_with_report(mergee, merged, options).map(|_| ())
+}
+
+/// Like [`merge_with`], but also returning a [`MergeReport`] on success.
+pub fn merge_with_report(
+    mergee: &mut Module,
+    merged: Module,
+    options: &MergeOptions,
+) -> Result<MergeReport, MergeError> {
+    merge_with_report_and_reachable(mergee, merged, options, None)
+}
+
+/// A module (e.g. a library) prepared for being merged into many others, by
+/// finding everything reachable from its exports only once (in [`analyze`]),
+/// instead of on every merge (as e.g. [`merge`] has to).
+///
+/// Note that any exports of the library not merged (due to conflicts, and
+/// [`ExportConflictPolicy::KeepMergee`]) still have their definitions copied,
+/// but nothing refers to those copies (so they will be ignored by e.g. lifting).
+///
+/// [`analyze`]: PreparedMerge::analyze
+//
+// FIXME: avoid cloning the whole library (including anything unreachable)
+// for every merge, by only cloning the reachable definitions.
+pub struct PreparedMerge {
+    library: Module,
+    reachable: ReachableDefs,
+}
+
+impl PreparedMerge {
+    pub fn analyze(library: &Module) -> Self {
+        let reachable = ReachableDefs::from_exports(library);
+        Self { library: library.clone(), reachable }
+    }
+
+    /// Merge the prepared module into `mergee` (see [`merge`]).
+    pub fn merge_into(&self, mergee: &mut Module) -> Result<(), MergeError> {
+        self.merge_into_with_report(mergee, &MergeOptions::default()).map(|_| ())
+    }
+
+    /// Like [`PreparedMerge::merge_into`], but with `options` (see [`merge_with_report`]).
+    pub fn merge_into_with_report(
+        &self,
+        mergee: &mut Module,
+        options: &MergeOptions,
+    ) -> Result<MergeReport, MergeError> {
+        merge_with_report_and_reachable(
+            mergee,
+            self.library.clone(),
+            options,
+            Some(self.reachable.clone()),
+        )
+    }
+}
+
+/// Like [`merge_with_report`], but able to use already collected `reachable`
+/// definitions of `merged` (see [`PreparedMerge`]).
+fn merge_with_report_and_reachable(
+    mergee: &mut Module,
+    mut merged: Module,
+    options: &MergeOptions,
+    reachable: Option<ReachableDefs>,
+) -> Result<MergeReport, MergeError> {
+    let cx = mergee.cx();
+    assert!(Rc::ptr_eq(&cx, merged.cx_ref()), "merge: modules must share the same `Context`");
+
+    if options.validate_inputs {
+        mergee.validate_references().map_err(MergeError::InvalidMergee)?;
+        merged.validate_references().map_err(MergeError::InvalidMerged)?;
+    }
+
+    // Resolve export conflicts before changing anything.
+    // NOTE: entry-points include their interface `GlobalVar`s, which are always
+    // copied (i.e. can't already be used by `mergee`), so only entry-points
+    // without any interface `GlobalVar`s can conflict (see also below).
+    let conflicting_export_keys: Vec<_> = merged
+        .exports
+        .keys()
+        .filter(|export_key| {
+            let can_conflict = match export_key {
+                ExportKey::LinkName(_) => true,
+                ExportKey::SpvEntryPoint { interface_global_vars, .. } => {
+                    interface_global_vars.is_empty()
+                }
+            };
+            can_conflict && mergee.exports.contains_key(*export_key)
+        })
+        .cloned()
+        .collect();
+    // NOTE: any `reachable` definitions have to be collected again if any
+    // exports are removed, to avoid copying what's only reachable from them.
+    let mut reachable = reachable;
+    for export_key in conflicting_export_keys {
+        if exports_are_identical(
+            &cx,
+            (mergee, mergee.exports[&export_key]),
+            (&merged, merged.exports[&export_key]),
+        ) {
+            merged.exports.shift_remove(&export_key);
+            reachable = None;
+            continue;
+        }
+        match options.on_export_conflict {
+            ExportConflictPolicy::Error => {
+                return Err(MergeError::duplicate_export_key(&cx, export_key));
+            }
+            // NOTE: this happens before collecting everything reachable
+            // from `merged`'s exports, so nothing only used here gets copied.
+            ExportConflictPolicy::KeepMergee => {
+                merged.exports.shift_remove(&export_key);
+                reachable = None;
+            }
+            // NOTE: `mergee`'s export is replaced when adding `merged`'s
+            // exports (see below), as `mergee` can't be changed yet.
+            ExportConflictPolicy::KeepMerged => {}
+            ExportConflictPolicy::Rename(rename) => {
+                let new_export_key = rename(&cx, &export_key);
+                if mergee.exports.contains_key(&new_export_key)
+                    || merged.exports.contains_key(&new_export_key)
+                {
+                    return Err(MergeError::duplicate_export_key(&cx, new_export_key));
+                }
+                // FIXME: this is quadratic in the number of conflicts.
+                merged.exports = merged
+                    .exports
+                    .into_iter()
+                    .map(|(k, v)| (if k == export_key { new_export_key.clone() } else { k }, v))
+                    .collect();
+            }
+        }
+    }
+
+    // NOTE: without any exports (e.g. a fresh `Module::new_empty`), nothing
+    // in `mergee` can rely on its models, so `merged`'s can be adopted instead,
+    // unless `mergee`'s models were explicitly requested to be kept.
+    if mergee.exports.is_empty() {
+        match (&mut mergee.dialect, &merged.dialect) {
+            (ModuleDialect::Spv(mergee), ModuleDialect::Spv(merged)) => {
+                if options.addressing_model_policy == ModelPolicy::RequireEqual {
+                    mergee.addressing_model = merged.addressing_model;
+                }
+                if options.memory_model_policy == ModelPolicy::RequireEqual {
+                    mergee.memory_model = merged.memory_model;
+                }
+            }
+        }
+    }
+
+    // NOTE: `make_compatible_with` only allows differing models if
+    // `merged`'s are being overridden (i.e. by keeping `mergee`'s models).
+    let overrides_models = match (&mergee.dialect, &merged.dialect) {
+        (ModuleDialect::Spv(mergee), ModuleDialect::Spv(merged)) => {
+            mergee.memory_model != merged.memory_model
+                || mergee.addressing_model != merged.addressing_model
+        }
+    };
+
+    let mut report = MergeReport::default();
+
+    match (&mergee.dialect, &merged.dialect) {
+        (ModuleDialect::Spv(mergee), ModuleDialect::Spv(merged)) => {
+            report.added_capabilities =
+                merged.capabilities.difference(&mergee.capabilities).copied().collect();
+            report.added_extensions =
+                merged.extensions.difference(&mergee.extensions).cloned().collect();
+        }
+    }
+    make_compatible_with(&mut mergee.dialect, &merged.dialect, options)?;
+
+    // NOTE: this has to be collected before any exports are added, but
+    // only the `OpName`s (see `DebugNameDeduper`) and bindings (see `Rebinder`)
+    // are needed later.
+    let renames_debug_names = options.dedup_debug_names || options.debug_name_prefix.is_some();
+    let existing_defs = (renames_debug_names || options.resolve_binding_conflicts).then(|| {
+        let ReachableDefs { global_vars, funcs, .. } = ReachableDefs::from_exports(mergee);
+        (global_vars, funcs)
+    });
+
+    let ReachableDefs { global_vars, funcs, type_count, const_count } =
+        reachable.unwrap_or_else(|| ReachableDefs::from_exports(&merged));
+    report.reachable_types = type_count;
+    report.reachable_consts = const_count;
+    report.copied_global_vars = global_vars.len();
+    report.copied_funcs = funcs.len();
+
+    if overrides_models {
+        let mut finder = MemoryOpFinder { cx: &cx, found: false };
+        for &func in &funcs {
+            if let DeclDef::Present(func_def_body) = &merged.funcs[func].def {
+                func_def_body.inner_visit_with(&mut finder);
+                if finder.found {
+                    return Err(MergeError::IncompatibleMemoryOperations { func });
+                }
+            }
+        }
+    }
+
+    // Fast path: if `mergee` has no definitions at all (e.g. a fresh
+    // `Module::new_empty`), `merged`'s definitions can be moved over wholesale,
+    // without any copying or rewriting (and `MergeOptions::dedup_functions`
+    // is a noop, as there are no existing functions to deduplicate against).
+    //
+    // NOTE: this can't be done if `mergee` has any definitions (even
+    // unexported ones), as they would be dropped, and `merged`'s entities may
+    // even share handles with them (e.g. if both modules were cloned from one).
+    if mergee.exports.is_empty()
+        && mergee.global_vars.is_empty()
+        && mergee.funcs.is_empty()
+        && !renames_debug_names
+    {
+        let Module { dialect: _, debug_info, global_vars, funcs, exports, .. } = merged;
+        mergee.global_vars = global_vars;
+        mergee.funcs = funcs;
+
+        report.added_exports = exports.keys().cloned().collect();
+        mergee.exports = exports;
+        check_added_entry_points(&cx, mergee, &report.added_exports)?;
+
+        merge_debug_info(&mut mergee.debug_info, debug_info);
+
+        return Ok(report);
+    }
+
+    // Copy all the declarations first (without any changes), so that all the
+    // new `GlobalVar`s/`Func`s are known before any references are rewritten.
+    let mut rewriter = EntityRewriter {
+        cx: &cx,
+
+        new_global_vars: FxHashMap::default(),
+        new_funcs: FxHashMap::default(),
+
+        transformed_attr_sets: FxHashMap::default(),
+        transformed_types: FxHashMap::default(),
+        transformed_consts: FxHashMap::default(),
+        transformed_data_inst_forms: FxHashMap::default(),
+    };
+    for &gv in &global_vars {
+        let new_gv = mergee.global_vars.define(&cx, merged.global_vars[gv].clone());
+        rewriter.new_global_vars.insert(gv, new_gv);
+    }
+    for &func in &funcs {
+        let new_func = mergee.funcs.define(&cx, merged.funcs[func].clone());
+        rewriter.new_funcs.insert(func, new_func);
+    }
+
+    for &gv in &global_vars {
+        let new_gv = rewriter.new_global_vars[&gv];
+        mergee.global_vars[new_gv].inner_in_place_transform_with(&mut rewriter);
+    }
+    for &func in &funcs {
+        let new_func = rewriter.new_funcs[&func];
+        mergee.funcs[new_func].inner_in_place_transform_with(&mut rewriter);
+    }
+
+    if options.dedup_functions {
+        // NOTE: this leaves the deduplicated copies in `mergee.funcs`,
+        // but nothing will refer to them, so they will be ignored by e.g. lifting.
+        let deduped_funcs = dedup_funcs(&cx, mergee, funcs.iter().map(|f| rewriter.new_funcs[f]));
+        if !deduped_funcs.is_empty() {
+            let mut dedup_rewriter = EntityRewriter {
+                cx: &cx,
+
+                new_global_vars: FxHashMap::default(),
+                new_funcs: deduped_funcs,
+
+                transformed_attr_sets: FxHashMap::default(),
+                transformed_types: FxHashMap::default(),
+                transformed_consts: FxHashMap::default(),
+                transformed_data_inst_forms: FxHashMap::default(),
+            };
+            // NOTE: this iterates `funcs` (instead of `rewriter.new_funcs`),
+            // to keep the order deterministic (see also `ReachableDefs`).
+            for func in &funcs {
+                let new_func = rewriter.new_funcs.get_mut(func).unwrap();
+                match dedup_rewriter.new_funcs.get(new_func) {
+                    Some(&existing_func) => *new_func = existing_func,
+                    None => {
+                        mergee.funcs[*new_func].inner_in_place_transform_with(&mut dedup_rewriter);
+                    }
+                }
+            }
+            report.deduplicated_funcs = dedup_rewriter.new_funcs.len();
+            report.copied_funcs -= report.deduplicated_funcs;
+        }
+    }
+
+    if options.resolve_binding_conflicts {
+        let (existing_global_vars, _) = existing_defs.as_ref().unwrap();
+
+        let mut rebinder = Rebinder { cx: &cx, taken: FxHashSet::default() };
+        for &gv in existing_global_vars {
+            rebinder.claim(mergee.global_vars[gv].attrs);
+        }
+
+        let exported_global_vars: FxHashSet<_> = merged
+            .exports
+            .values()
+            .filter_map(|&exportee| match exportee {
+                Exportee::GlobalVar(gv) => Some(gv),
+                Exportee::Func(_) => None,
+            })
+            .collect();
+        for &gv in &global_vars {
+            let new_gv = rewriter.new_global_vars[&gv];
+            let gv_decl = &mut mergee.global_vars[new_gv];
+            if exported_global_vars.contains(&gv) {
+                rebinder.claim(gv_decl.attrs);
+            } else if let Some((new_attrs, descriptor_set, old_binding, new_binding)) =
+                rebinder.rebind(gv_decl.attrs)
+            {
+                gv_decl.attrs = new_attrs;
+                report.rebindings.push(Rebinding {
+                    global_var: new_gv,
+                    descriptor_set,
+                    old_binding,
+                    new_binding,
+                });
+            }
+        }
+    }
+
+    let Module { dialect: _, debug_info, global_vars: _, funcs: _, exports, .. } = merged;
+
+    for (export_key, exportee) in exports {
+        let mut export_key = export_key;
+        let mut exportee = exportee;
+        export_key.inner_transform_with(&mut rewriter).apply_to(&mut export_key);
+        exportee.inner_transform_with(&mut rewriter).apply_to(&mut exportee);
+
+        // NOTE: all conflicts were already resolved above (as the rewritten
+        // interface `GlobalVar`s of entry-points are always new copies), other
+        // than `KeepMerged` replacing `mergee`'s export here, but this is still
+        // checked, because it's too late to apply any other policy.
+        if mergee.exports.contains_key(&export_key)
+            && !matches!(options.on_export_conflict, ExportConflictPolicy::KeepMerged)
+        {
+            return Err(MergeError::duplicate_export_key(&cx, export_key));
+        }
+        report.added_exports.push(export_key.clone());
+        mergee.exports.insert(export_key, exportee);
+    }
+
+    check_added_entry_points(&cx, mergee, &report.added_exports)?;
+
+    if let Some((existing_global_vars, existing_funcs)) =
+        existing_defs.filter(|_| renames_debug_names)
+    {
+        let mut exported_global_vars = FxHashSet::default();
+        let mut exported_funcs = FxHashSet::default();
+        for export_key in &report.added_exports {
+            match mergee.exports[export_key] {
+                Exportee::GlobalVar(gv) => exported_global_vars.insert(gv),
+                Exportee::Func(func) => exported_funcs.insert(func),
+            };
+        }
+
+        let mut deduper = DebugNameDeduper {
+            cx: &cx,
+            prefix: options.debug_name_prefix.map(|prefix| &cx[prefix]),
+            dedup: options.dedup_debug_names,
+            taken: FxHashSet::default(),
+        };
+        for &gv in &existing_global_vars {
+            deduper.claim(mergee.global_vars[gv].attrs);
+        }
+        for &func in &existing_funcs {
+            deduper.claim(mergee.funcs[func].attrs);
+        }
+
+        for &gv in &global_vars {
+            let new_gv = rewriter.new_global_vars[&gv];
+            let gv_decl = &mut mergee.global_vars[new_gv];
+            if exported_global_vars.contains(&new_gv) {
+                deduper.claim(gv_decl.attrs);
+            } else {
+                gv_decl.attrs = deduper.rename(gv_decl.attrs);
+            }
+        }
+        for &func in &funcs {
+            let new_func = rewriter.new_funcs[&func];
+            if existing_funcs.contains(&new_func) {
+                // Deduplicated (see `MergeOptions::dedup_functions`).
+                continue;
+            }
+            let func_decl = &mut mergee.funcs[new_func];
+            if exported_funcs.contains(&new_func) {
+                deduper.claim(func_decl.attrs);
+            } else {
+                func_decl.attrs = deduper.rename(func_decl.attrs);
+            }
+        }
+    }
+
+    merge_debug_info(&mut mergee.debug_info, debug_info);
+
+    Ok(report)
+}
+
+/// Tracks which SPIR-V `OpName`s are in use, to make new ones unique, and
+/// also applies [`MergeOptions::debug_name_prefix`] (if any) to new ones.
+struct DebugNameDeduper<'a> {
+    cx: &'a Context,
+
+    prefix: Option<&'a str>,
+
+    /// Whether to make new names unique at all (see [`MergeOptions::dedup_debug_names`]).
+    dedup: bool,
+
+    taken: FxHashSet<String>,
+}
+
+/// Check every entry-point in `added_exports` with [`check_execution_modes`],
+/// combined with any other entry-points in `module` with the same execution
+/// model and name (i.e. only differing in their interface global variables).
+fn check_added_entry_points(
+    cx: &Context,
+    module: &Module,
+    added_exports: &[ExportKey],
+) -> Result<(), MergeError> {
+    for export_key in added_exports {
+        if let (ExportKey::SpvEntryPoint { imms, .. }, Exportee::Func(func)) =
+            (export_key, module.exports[export_key])
+        {
+            let same_entry_points =
+                module.exports.iter().filter_map(|(other_key, exportee)| {
+                    match (other_key, exportee) {
+                        (
+                            ExportKey::SpvEntryPoint { imms: other_imms, .. },
+                            &Exportee::Func(other),
+                        ) if other_imms == imms => Some(module.funcs[other].attrs),
+                        _ => None,
+                    }
+                });
+            check_execution_modes(cx, func, same_entry_points)?;
+        }
+    }
+    Ok(())
+}
+
+/// Error out if `attr_sets` (of the entry-point function `func`, and of any
+/// other functions used for the same entry-point) contain more than one
+/// `OpExecutionMode` (or `OpExecutionModeId`) for the same `ExecutionMode`.
+///
+/// Identical duplicates are ignored, so any remaining ones must differ in
+/// their operands (which drivers would reject), except for those execution
+/// modes which can be repeated for different first operands (e.g.
+/// `DenormPreserve 16` and `DenormPreserve 32`, for different float widths).
+fn check_execution_modes(
+    cx: &Context,
+    func: Func,
+    attr_sets: impl IntoIterator<Item = AttrSet>,
+) -> Result<(), MergeError> {
+    let spv_spec = spv::spec::Spec::get();
+    let wk = &spv_spec.well_known;
+
+    let repeatable_modes: FxHashSet<u32> =
+        match &spv_spec.operand_kinds[spv_spec.operand_kinds.lookup("ExecutionMode").unwrap()] {
+            spv::spec::OperandKindDef::ValueEnum { variants } => [
+                "DenormPreserve",
+                "DenormFlushToZero",
+                "SignedZeroInfNanPreserve",
+                "RoundingModeRTE",
+                "RoundingModeRTZ",
+                "RoundingModeRTPINTEL",
+                "RoundingModeRTNINTEL",
+                "FloatingPointModeALTINTEL",
+                "FloatingPointModeIEEEINTEL",
+                "FPFastMathDefault",
+            ]
+            .into_iter()
+            .filter_map(|name| variants.lookup(name).map(u32::from))
+            .collect(),
+            _ => unreachable!(),
+        };
+
+    // NOTE: this can't be a `BTreeSet`, as `Attr::SpvAnnotationWithConstInputs`
+    // only supports comparing equal `const_inputs` (see `OrdAssertEq`).
+    let attrs: FxHashSet<&Attr> =
+        attr_sets.into_iter().flat_map(|attrs| &cx[attrs].attrs).collect();
+
+    let mut seen_modes = FxHashSet::default();
+    for attr in attrs {
+        let (spv_inst, const_inputs) = match attr {
+            Attr::SpvAnnotation(spv_inst) => (spv_inst, &[][..]),
+            Attr::SpvAnnotationWithConstInputs { spv_inst, const_inputs } => {
+                (spv_inst, &const_inputs.0[..])
+            }
+            _ => continue,
+        };
+        if ![wk.OpExecutionMode, wk.OpExecutionModeId].contains(&spv_inst.opcode) {
+            continue;
+        }
+        if let Some(&spv::Imm::Short(_, mode)) = spv_inst.imms.first() {
+            // NOTE: the first operand (after the mode itself) is either
+            // an immediate (`OpExecutionMode`) or an ID (`OpExecutionModeId`).
+            let first_operand = if repeatable_modes.contains(&mode) {
+                (spv_inst.imms.get(1).copied(), const_inputs.first().copied())
+            } else {
+                (None, None)
+            };
+            if !seen_modes.insert((mode, first_operand)) {
+                return Err(MergeError::ConflictingExecutionMode { target: func, mode });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the SPIR-V `DescriptorSet` (defaulting to `0`) and `Binding` decorations
+/// from `attrs`, along with the `Binding` one (as an [`Attr::SpvAnnotation`]),
+/// if there is a `Binding` decoration at all.
+fn spv_descriptor_set_and_binding(cx: &Context, attrs: AttrSet) -> Option<(u32, &Attr, u32)> {
+    let mut descriptor_set = 0;
+    let mut binding_attr_and_binding = None;
+    for attr in &cx[attrs].attrs {
+        match attr.spv_decoration() {
+            Some(spv::Decoration::DescriptorSet(value)) => descriptor_set = value,
+            Some(spv::Decoration::Binding(value)) => binding_attr_and_binding = Some((attr, value)),
+            _ => {}
+        }
+    }
+    let (binding_attr, binding) = binding_attr_and_binding?;
+    Some((descriptor_set, binding_attr, binding))
+}
+
+/// Tracks the SPIR-V `(DescriptorSet, Binding)` pairs already used, to find
+/// collisions (see [`MergeOptions::resolve_binding_conflicts`]).
+struct Rebinder<'a> {
+    cx: &'a Context,
+
+    taken: FxHashSet<(u32, u32)>,
+}
+
+impl Rebinder<'_> {
+    fn claim(&mut self, attrs: AttrSet) {
+        if let Some((descriptor_set, _, binding)) = spv_descriptor_set_and_binding(self.cx, attrs) {
+            self.taken.insert((descriptor_set, binding));
+        }
+    }
+
+    /// Claim the SPIR-V `Binding` from `attrs`, or if it's already taken, the
+    /// lowest free one (in the same `DescriptorSet`), returning the updated
+    /// `attrs` (along with the `DescriptorSet`, and old and new `Binding`s).
+    fn rebind(&mut self, attrs: AttrSet) -> Option<(AttrSet, u32, u32, u32)> {
+        let cx = self.cx;
+
+        let (descriptor_set, binding_attr, binding) = spv_descriptor_set_and_binding(cx, attrs)?;
+        if self.taken.insert((descriptor_set, binding)) {
+            return None;
+        }
+
+        let new_binding = (0..).find(|&b| !self.taken.contains(&(descriptor_set, b))).unwrap();
+        self.taken.insert((descriptor_set, new_binding));
+
+        let new_binding_attr = match binding_attr {
+            Attr::SpvAnnotation(spv_inst) => {
+                let mut spv_inst = spv_inst.clone();
+                if let spv::Imm::Short(_, value) = &mut spv_inst.imms[1] {
+                    *value = new_binding;
+                }
+                Attr::SpvAnnotation(spv_inst)
+            }
+            _ => unreachable!(),
+        };
+
+        let mut attrs_def = AttrSetDef { attrs: cx[attrs].attrs.clone() };
+        attrs_def.attrs.remove(binding_attr);
+        attrs_def.attrs.insert(new_binding_attr);
+        Some((cx.intern(attrs_def), descriptor_set, binding, new_binding))
+    }
+}
+
+/// Get the SPIR-V `OpName` (as an [`Attr::SpvAnnotation`]) from `attrs`, if any.
+fn spv_name_attr(cx: &Context, attrs: AttrSet) -> Option<(&Attr, String)> {
+    let wk = &spv::spec::Spec::get().well_known;
+    cx[attrs].attrs.iter().find_map(|attr| match attr {
+        Attr::SpvAnnotation(spv_inst) if spv_inst.opcode == wk.OpName => {
+            Some((attr, spv::extract_literal_string(&spv_inst.imms).ok()?))
+        }
+        _ => None,
+    })
+}
+
+impl DebugNameDeduper<'_> {
+    fn claim(&mut self, attrs: AttrSet) {
+        if let Some((_, name)) = spv_name_attr(self.cx, attrs) {
+            self.taken.insert(name);
+        }
+    }
+
+    /// Claim the SPIR-V `OpName` from `attrs` (after adding `self.prefix`), or
+    /// a new one (with a `.N` suffix), if it's already taken (and `self.dedup`
+    /// is set), returning the updated `attrs`.
+    fn rename(&mut self, attrs: AttrSet) -> AttrSet {
+        let wk = &spv::spec::Spec::get().well_known;
+        let cx = self.cx;
+
+        let (name_attr, name) = match spv_name_attr(cx, attrs) {
+            Some(name_attr_and_name) => name_attr_and_name,
+            None => return attrs,
+        };
+        let prefixed_name = match self.prefix {
+            Some(prefix) => format!("{prefix}{name}"),
+            None => name.clone(),
+        };
+        let new_name = if self.dedup && self.taken.contains(&prefixed_name) {
+            (1..).map(|i| format!("{prefixed_name}.{i}")).find(|n| !self.taken.contains(n)).unwrap()
+        } else {
+            prefixed_name
+        };
+        if new_name == name {
+            self.taken.insert(name);
+            return attrs;
+        }
+
+        let new_name_attr = Attr::SpvAnnotation(spv::Inst {
+            opcode: wk.OpName,
+            imms: spv::encode_literal_string(&new_name).collect(),
+        });
+
+        let mut attrs_def = AttrSetDef { attrs: cx[attrs].attrs.clone() };
+        attrs_def.attrs.remove(name_attr);
+        attrs_def.attrs.insert(new_name_attr);
+        self.taken.insert(new_name);
+        cx.intern(attrs_def)
+    }
+}
+
+/// Find which of `new_funcs` (already in `module`) can be replaced by existing
+/// (i.e. reachable from `module`'s exports) functions, with identical definitions.
+fn dedup_funcs(
+    cx: &Context,
+    module: &Module,
+    new_funcs: impl Iterator<Item = Func> + Clone,
+) -> FxHashMap<Func, Func> {
+    let reachable = ReachableDefs::from_exports(module);
+
+    let mut deduped_funcs = FxHashMap::default();
+
+    let mut existing_funcs = FxHashMap::default();
+    for &func in &reachable.funcs {
+        if let Some(fingerprint) = func_fingerprint(cx, &module.funcs[func], &deduped_funcs) {
+            existing_funcs.entry(fingerprint).or_insert(func);
+        }
+    }
+
+    // NOTE: deduplicating a function can make its callers identical to
+    // existing functions as well, so this is repeated until nothing changes.
+    loop {
+        let mut changed = false;
+        for new_func in new_funcs.clone() {
+            if deduped_funcs.contains_key(&new_func) {
+                continue;
+            }
+            let existing_func = func_fingerprint(cx, &module.funcs[new_func], &deduped_funcs)
+                .and_then(|fingerprint| existing_funcs.get(&fingerprint));
+            if let Some(&existing_func) = existing_func {
+                deduped_funcs.insert(new_func, existing_func);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    deduped_funcs
+}
+
+/// Check whether `merged`'s exportee is identical to `mergee`'s (for the same
+/// [`ExportKey`]), i.e. whether they're functions with identical definitions.
+fn exports_are_identical(
+    cx: &Context,
+    (mergee, mergee_exportee): (&Module, Exportee),
+    (merged, merged_exportee): (&Module, Exportee),
+) -> bool {
+    match (mergee_exportee, merged_exportee) {
+        (Exportee::Func(mergee_func), Exportee::Func(merged_func)) => funcs_are_identical(
+            cx,
+            (mergee, mergee_func),
+            (merged, merged_func),
+            &mut FxHashSet::default(),
+        ),
+        _ => false,
+    }
+}
+
+/// Check whether `merged_func` (in `merged`) has an identical definition to
+/// `mergee_func` (in `mergee`), comparing their callees the same way (instead
+/// of by their handles, which differ between modules).
+///
+/// Pairs of functions in `assumed_identical` are considered identical, which
+/// allows comparing (mutually) recursive functions (as any difference found
+/// while comparing them makes the whole comparison fail).
+fn funcs_are_identical(
+    cx: &Context,
+    (mergee, mergee_func): (&Module, Func),
+    (merged, merged_func): (&Module, Func),
+    assumed_identical: &mut FxHashSet<(Func, Func)>,
+) -> bool {
+    if !assumed_identical.insert((mergee_func, merged_func)) {
+        return true;
+    }
+
+    let no_deduped_funcs = FxHashMap::default();
+    let (Some(mergee_fingerprint), Some(merged_fingerprint)) = (
+        func_fingerprint(cx, &mergee.funcs[mergee_func], &no_deduped_funcs),
+        func_fingerprint(cx, &merged.funcs[merged_func], &no_deduped_funcs),
+    ) else {
+        return false;
+    };
+    mergee_fingerprint.len() == merged_fingerprint.len()
+        && mergee_fingerprint.iter().zip(&merged_fingerprint).all(|tokens| match tokens {
+            (&FingerprintToken::Func(mergee_callee), &FingerprintToken::Func(merged_callee)) => {
+                funcs_are_identical(
+                    cx,
+                    (mergee, mergee_callee),
+                    (merged, merged_callee),
+                    assumed_identical,
+                )
+            }
+            (mergee_token, merged_token) => mergee_token == merged_token,
+        })
+}
+
+/// Compute a "fingerprint" for the definition of a [`Func`] (if it has one),
+/// which is equal between two functions iff their definitions are identical,
+/// modulo the choice of (function-local) entities, and any [`Func`]s being
+/// replaced according to `deduped_funcs`.
+fn func_fingerprint(
+    cx: &Context,
+    func_decl: &FuncDecl,
+    deduped_funcs: &FxHashMap<Func, Func>,
+) -> Option<Vec<FingerprintToken>> {
+    let func_def_body = match &func_decl.def {
+        DeclDef::Imported(_) => return None,
+        DeclDef::Present(func_def_body) => func_def_body,
+    };
+
+    let mut fingerprinter = FuncFingerprinter {
+        cx,
+        deduped_funcs,
+
+        regions: FxIndexSet::default(),
+        control_nodes: FxIndexSet::default(),
+        data_insts: FxIndexSet::default(),
+
+        tokens: vec![],
+    };
+
+    fingerprinter.visit_attr_set_use(func_decl.attrs);
+    fingerprinter.visit_type_use(func_decl.ret_type);
+    fingerprinter.tokens.push(FingerprintToken::Marker("params", func_decl.params.len()));
+    for param in &func_decl.params {
+        param.inner_visit_with(&mut fingerprinter);
+    }
+
+    match &func_def_body.unstructured_cfg {
+        None => fingerprinter.visit_control_region_def(func_def_body.at_body()),
+        Some(cfg) => {
+            for region in cfg.rev_post_order(func_def_body) {
+                fingerprinter.visit_control_region_def(func_def_body.at(region));
+
+                match cfg.control_inst_on_exit_from.get(region) {
+                    Some(control_inst) => fingerprinter.fingerprint_control_inst(control_inst),
+                    None => fingerprinter.tokens.push(FingerprintToken::Marker("no_exit", 0)),
+                }
+            }
+            for (&merge, &header) in &cfg.loop_merge_to_loop_header {
+                let merge = fingerprinter.region(merge);
+                let header = fingerprinter.region(header);
+                fingerprinter.tokens.extend([merge, header]);
+            }
+        }
+    }
+
+    Some(fingerprinter.tokens)
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum FingerprintToken {
+    AttrSet(AttrSet),
+    Type(Type),
+    Const(Const),
+    DataInstForm(DataInstForm),
+    GlobalVar(GlobalVar),
+    Func(Func),
+    SpvInst(spv::Inst),
+
+    /// Function-local entities, numbered in the order they were first seen in.
+    ControlRegion(usize),
+    ControlNode(usize),
+    DataInst(usize),
+
+    /// Structural information (e.g. the number of elements in a list).
+    Marker(&'static str, usize),
+}
+
+struct FuncFingerprinter<'a> {
+    cx: &'a Context,
+    deduped_funcs: &'a FxHashMap<Func, Func>,
+
+    regions: FxIndexSet<ControlRegion>,
+    control_nodes: FxIndexSet<ControlNode>,
+    data_insts: FxIndexSet<DataInst>,
+
+    tokens: Vec<FingerprintToken>,
+}
+
+impl FuncFingerprinter<'_> {
+    fn region(&mut self, region: ControlRegion) -> FingerprintToken {
+        FingerprintToken::ControlRegion(self.regions.insert_full(region).0)
+    }
+
+    fn control_node(&mut self, control_node: ControlNode) -> FingerprintToken {
+        FingerprintToken::ControlNode(self.control_nodes.insert_full(control_node).0)
+    }
+
+    fn data_inst(&mut self, data_inst: DataInst) -> FingerprintToken {
+        FingerprintToken::DataInst(self.data_insts.insert_full(data_inst).0)
+    }
+
+    fn fingerprint_selection_kind(&mut self, kind: &SelectionKind) {
+        self.tokens.push(match kind {
+            SelectionKind::BoolCond => FingerprintToken::Marker("bool_cond", 0),
+            SelectionKind::SpvInst(spv_inst) => FingerprintToken::SpvInst(spv_inst.clone()),
+        });
+    }
+
+    fn fingerprint_values<'b>(&mut self, values: impl ExactSizeIterator<Item = &'b Value>) {
+        self.tokens.push(FingerprintToken::Marker("values", values.len()));
+        for v in values {
+            self.visit_value_use(v);
+        }
+    }
+
+    fn fingerprint_control_inst(&mut self, control_inst: &cfg::ControlInst) {
+        let cfg::ControlInst { attrs, kind, inputs, targets, target_inputs } = control_inst;
+
+        self.visit_attr_set_use(*attrs);
+        match kind {
+            cfg::ControlInstKind::Unreachable => {
+                self.tokens.push(FingerprintToken::Marker("unreachable", 0));
+            }
+            cfg::ControlInstKind::Return => self.tokens.push(FingerprintToken::Marker("return", 0)),
+            cfg::ControlInstKind::ExitInvocation(cfg::ExitInvocationKind::SpvInst(spv_inst)) => {
+                self.tokens.push(FingerprintToken::Marker("exit_invocation", 0));
+                self.tokens.push(FingerprintToken::SpvInst(spv_inst.clone()));
+            }
+            cfg::ControlInstKind::Branch => self.tokens.push(FingerprintToken::Marker("branch", 0)),
+            cfg::ControlInstKind::SelectBranch(kind) => {
+                self.tokens.push(FingerprintToken::Marker("select_branch", 0));
+                self.fingerprint_selection_kind(kind);
+            }
+        }
+        self.fingerprint_values(inputs.iter());
+        self.tokens.push(FingerprintToken::Marker("targets", targets.len()));
+        for &target in targets {
+            let target = self.region(target);
+            self.tokens.push(target);
+        }
+        self.tokens.push(FingerprintToken::Marker("target_inputs", target_inputs.len()));
+        for (&target, inputs) in target_inputs {
+            let target = self.region(target);
+            self.tokens.push(target);
+            self.fingerprint_values(inputs.iter());
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for FuncFingerprinter<'_> {
+    fn visit_attr_set_use(&mut self, attrs: AttrSet) {
+        self.tokens.push(FingerprintToken::AttrSet(attrs));
+    }
+    fn visit_type_use(&mut self, ty: Type) {
+        self.tokens.push(FingerprintToken::Type(ty));
+    }
+    fn visit_const_use(&mut self, ct: Const) {
+        self.tokens.push(FingerprintToken::Const(ct));
+    }
+    fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
+        let data_inst_form_def = &self.cx[data_inst_form];
+        match data_inst_form_def.kind {
+            // NOTE: calls need to account for `deduped_funcs`, so they
+            // can't be compared as interned `DataInstForm`s.
+            DataInstKind::FuncCall(callee) => {
+                self.tokens.push(FingerprintToken::Marker("call", 0));
+                self.visit_func_use(callee);
+                self.tokens.push(FingerprintToken::Marker(
+                    "output_type",
+                    data_inst_form_def.output_type.is_some() as usize,
+                ));
+                if let Some(ty) = data_inst_form_def.output_type {
+                    self.visit_type_use(ty);
+                }
+            }
+            _ => self.tokens.push(FingerprintToken::DataInstForm(data_inst_form)),
+        }
+    }
+
+    fn visit_global_var_use(&mut self, gv: GlobalVar) {
+        self.tokens.push(FingerprintToken::GlobalVar(gv));
+    }
+    fn visit_func_use(&mut self, func: Func) {
+        let func = self.deduped_funcs.get(&func).copied().unwrap_or(func);
+        self.tokens.push(FingerprintToken::Func(func));
+    }
+
+    fn visit_control_region_def(&mut self, func_at_control_region: FuncAt<'a, ControlRegion>) {
+        let region_def = func_at_control_region.def();
+        let region = self.region(func_at_control_region.position);
+        self.tokens.push(region);
+        self.tokens.push(FingerprintToken::Marker("inputs", region_def.inputs.len()));
+        for input in &region_def.inputs {
+            input.inner_visit_with(self);
+        }
+        for func_at_control_node in func_at_control_region.at(region_def.children) {
+            self.visit_control_node_def(func_at_control_node);
+        }
+        self.tokens.push(FingerprintToken::Marker("children_end", 0));
+        self.fingerprint_values(region_def.outputs.iter());
+    }
+
+    fn visit_control_node_def(&mut self, func_at_control_node: FuncAt<'a, ControlNode>) {
+        let control_node_def = func_at_control_node.def();
+        let control_node = self.control_node(func_at_control_node.position);
+        self.tokens.push(control_node);
+        self.tokens.push(FingerprintToken::Marker("outputs", control_node_def.outputs.len()));
+        for output in &control_node_def.outputs {
+            output.inner_visit_with(self);
+        }
+
+        match &control_node_def.kind {
+            &ControlNodeKind::Block { insts } => {
+                self.tokens.push(FingerprintToken::Marker("block", 0));
+                for func_at_inst in func_at_control_node.at(insts) {
+                    let data_inst = self.data_inst(func_at_inst.position);
+                    self.tokens.push(data_inst);
+                    let data_inst_def = func_at_inst.def();
+                    self.visit_attr_set_use(data_inst_def.attrs);
+                    self.visit_data_inst_form_use(data_inst_def.form);
+                    self.fingerprint_values(data_inst_def.inputs.iter());
+                }
+                self.tokens.push(FingerprintToken::Marker("block_end", 0));
+            }
+            ControlNodeKind::Select { kind, scrutinee, cases } => {
+                self.tokens.push(FingerprintToken::Marker("select", cases.len()));
+                self.fingerprint_selection_kind(kind);
+                self.visit_value_use(scrutinee);
+                for &case in cases {
+                    self.visit_control_region_def(func_at_control_node.at(case));
+                }
+            }
+            ControlNodeKind::Loop { initial_inputs, body, repeat_condition } => {
+                self.tokens.push(FingerprintToken::Marker("loop", 0));
+                self.fingerprint_values(initial_inputs.iter());
+                self.visit_control_region_def(func_at_control_node.at(*body));
+                self.visit_value_use(repeat_condition);
+            }
+        }
+    }
+
+    fn visit_value_use(&mut self, v: &'a Value) {
+        match *v {
+            Value::Const(ct) => self.visit_const_use(ct),
+            Value::ControlRegionInput { region, input_idx } => {
+                let region = self.region(region);
+                self.tokens.push(region);
+                self.tokens.push(FingerprintToken::Marker("input", input_idx as usize));
+            }
+            Value::ControlNodeOutput { control_node, output_idx } => {
+                let control_node = self.control_node(control_node);
+                self.tokens.push(control_node);
+                self.tokens.push(FingerprintToken::Marker("output", output_idx as usize));
+            }
+            Value::DataInstOutput(inst) => {
+                let inst = self.data_inst(inst);
+                self.tokens.push(inst);
+            }
+        }
+    }
+}
+
+/// All the [`GlobalVar`]s and [`Func`]s (transitively) used by the exports
+/// of a module (in the order they were first found in, by [`ReachableUseCollector`]),
+/// with only the counts of the [`Type`]s and [`Const`]s found, which are
+/// shared through the [`Context`].
+///
+/// This order is what makes merging deterministic (i.e. the same inputs always
+/// produce the same output), so only `FxIndexSet`s are used for iteration.
+#[derive(Clone)]
+struct ReachableDefs {
+    global_vars: FxIndexSet<GlobalVar>,
+    funcs: FxIndexSet<Func>,
+
+    type_count: usize,
+    const_count: usize,
+}
+
+impl ReachableDefs {
+    fn from_exports(module: &Module) -> Self {
+        let mut collector = ReachableUseCollector::new(module);
+        // NOTE: `AttrSet`s can't be ignored here, as they can refer to `Const`s
+        // (e.g. `OpDecorateId %buf CounterBuffer %counter`, where `%counter`
+        // is a `ConstKind::PtrToGlobalVar`, and may not be used anywhere else).
+        collector.visit_attr_sets = true;
+        collector.visit_module_exports();
+        ReachableDefs {
+            global_vars: collector.seen_global_vars,
+            funcs: collector.seen_funcs,
+
+            type_count: collector.seen_types.len(),
+            const_count: collector.seen_consts.len(),
+        }
+    }
+}
+
+/// Finds any instructions (in a function body) which take or produce pointers,
+/// i.e. anything that could depend on the memory/addressing model.
+struct MemoryOpFinder<'a> {
+    cx: &'a Context,
+
+    found: bool,
+}
+
+impl MemoryOpFinder<'_> {
+    fn is_ptr_type(&self, ty: Type) -> bool {
+        let wk = &spv::spec::Spec::get().well_known;
+        match &self.cx[ty].kind {
+            TypeKind::QPtr => true,
+            TypeKind::SpvInst { spv_inst, .. } => spv_inst.opcode == wk.OpTypePointer,
+            TypeKind::SpvStringLiteralForExtInst => false,
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for MemoryOpFinder<'_> {
+    // NOTE: uses of interned/module-stored definitions are irrelevant
+    // here, as only the instructions in a single function body are inspected.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, _data_inst_form: DataInstForm) {}
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+
+    fn visit_control_node_def(&mut self, func_at_control_node: FuncAt<'a, ControlNode>) {
+        if let ControlNodeKind::Block { insts } = func_at_control_node.def().kind {
+            for func_at_inst in func_at_control_node.at(insts) {
+                let inst_def = func_at_inst.def();
+                let output_type = self.cx[inst_def.form].output_type;
+                self.found |= output_type.is_some_and(|ty| self.is_ptr_type(ty))
+                    || inst_def
+                        .inputs
+                        .iter()
+                        .any(|&v| self.is_ptr_type(func_at_inst.at(v).type_of(self.cx)));
+            }
+        }
+        func_at_control_node.inner_visit_with(self);
+    }
+}
+
+/// Rewrites all uses of [`GlobalVar`]s and [`Func`]s to their replacements
+/// (i.e. copies, or identical existing definitions), leaving the rest unchanged.
+struct EntityRewriter<'a> {
+    cx: &'a Context,
+
+    new_global_vars: FxHashMap<GlobalVar, GlobalVar>,
+    new_funcs: FxHashMap<Func, Func>,
+
+    // FIXME: build some automation to avoid ever repeating these.
+    transformed_attr_sets: FxHashMap<AttrSet, Transformed<AttrSet>>,
+    transformed_types: FxHashMap<Type, Transformed<Type>>,
+    transformed_consts: FxHashMap<Const, Transformed<Const>>,
+    transformed_data_inst_forms: FxHashMap<DataInstForm, Transformed<DataInstForm>>,
+}
+
+impl Transformer for EntityRewriter<'_> {
+    cached_transform_interned_uses!();
+
+    // NOTE: unlike other passes, `AttrSet`s can't be ignored here, as they can
+    // refer to `Const`s (which can refer to `GlobalVar`s, see `ReachableDefs`).
+    fn transform_attr_set_use(&mut self, attrs: AttrSet) -> Transformed<AttrSet> {
+        if let Some(&cached) = self.transformed_attr_sets.get(&attrs) {
+            return cached;
+        }
+        let transformed =
+            self.transform_attr_set_def(&self.cx[attrs]).map(|attrs_def| self.cx.intern(attrs_def));
+        self.transformed_attr_sets.insert(attrs, transformed);
+        transformed
+    }
+
+    fn transform_global_var_use(&mut self, gv: GlobalVar) -> Transformed<GlobalVar> {
+        match self.new_global_vars.get(&gv) {
+            Some(&new_gv) => Transformed::Changed(new_gv),
+            None => Transformed::Unchanged,
+        }
+    }
+    fn transform_func_use(&mut self, func: Func) -> Transformed<Func> {
+        match self.new_funcs.get(&func) {
+            Some(&new_func) => Transformed::Changed(new_func),
+            None => Transformed::Unchanged,
+        }
+    }
+}
+
+/// Minimal library module, only exporting an empty function named `link_name`.
+#[cfg(test)]
+fn lower_export_module(cx: Rc<Context>, link_name: &str) -> Module {
+    let spv_words = spv::test_asm::assemble(&format!(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "{link_name}" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %1 = OpFunction %2 None %3
+            %4 = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#
+    ));
+    Module::lower_from_spv_words(cx, &spv_words).unwrap()
+}
+
+#[test]
+fn merge_independently_lowered_modules() {
+    let mut mergee = lower_export_module(Rc::new(Context::new()), "foo");
+    let merged = lower_export_module(Rc::new(Context::new()), "bar");
+    assert!(!Rc::ptr_eq(mergee.cx_ref(), merged.cx_ref()));
+
+    merge_via_spv_roundtrip(&mut mergee, merged).unwrap();
+
+    let cx = mergee.cx();
+    let mut link_names: Vec<_> = mergee
+        .exports
+        .keys()
+        .map(|export_key| match export_key {
+            &ExportKey::LinkName(name) => &cx[name],
+            ExportKey::SpvEntryPoint { .. } => unreachable!(),
+        })
+        .collect();
+    link_names.sort();
+    assert_eq!(link_names, ["bar", "foo"]);
+}
+
+/// Library module exporting a function named `link_name`, which computes
+/// `FAbs(1.0)`, importing `GLSL.std.450` with the SPIR-V ID `ext_inst_import_id`.
+#[cfg(test)]
+fn lower_glsl_std_450_export_module(
+    cx: Rc<Context>,
+    link_name: &str,
+    ext_inst_import_id: u32,
+) -> Module {
+    let spv_words = spv::test_asm::assemble(&format!(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            %{ext_inst_import_id} = OpExtInstImport "GLSL.std.450"
+            OpMemoryModel Logical GLSL450
+            OpDecorate %func LinkageAttributes "{link_name}" Export
+            %void = OpTypeVoid
+            %float = OpTypeFloat 32
+            %one = OpConstant %float 0x3f800000
+            %func_type = OpTypeFunction %void
+            %func = OpFunction %void None %func_type
+            %entry = OpLabel
+            %abs = OpExtInst %float %{ext_inst_import_id} 4 %one
+            OpReturn
+            OpFunctionEnd
+            "#
+    ));
+    Module::lower_from_spv_words(cx, &spv_words).unwrap()
+}
+
+#[test]
+fn merge_dedups_ext_inst_imports() {
+    let wk = &spv::spec::Spec::get().well_known;
+
+    let cx = Rc::new(Context::new());
+    let mut mergee = lower_glsl_std_450_export_module(cx.clone(), "foo", 1);
+    let merged = lower_glsl_std_450_export_module(cx, "bar", 20);
+    merge(&mut mergee, merged).unwrap();
+    assert_eq!(mergee.exports.len(), 2);
+
+    let lifted_words = mergee.lift_to_spv_words().unwrap();
+    let insts: Vec<_> = spv::read::ModuleParser::read_from_spv_words(&lifted_words)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let ext_inst_imports: Vec<_> =
+        insts.iter().filter(|inst| inst.opcode == wk.OpExtInstImport).collect();
+    assert_eq!(ext_inst_imports.len(), 1);
+
+    // Both functions' `OpExtInst`s must refer to the one remaining import.
+    let ext_inst_import_id = ext_inst_imports[0].result_id.unwrap();
+    let ext_insts: Vec<_> = insts.iter().filter(|inst| inst.opcode == wk.OpExtInst).collect();
+    assert_eq!(ext_insts.len(), 2);
+    assert!(ext_insts.iter().all(|inst| inst.ids[0] == ext_inst_import_id));
+}
+
+#[test]
+fn merge_into_empty_module() {
+    let wk = &spv::spec::Spec::get().well_known;
+
+    let merged = lower_export_module(Rc::new(Context::new()), "foo");
+    let mut mergee = Module::new_empty(
+        merged.cx(),
+        ModuleDialect::Spv(spv::Dialect {
+            version_major: 1,
+            version_minor: 0,
+
+            capabilities: BTreeSet::new(),
+            extensions: BTreeSet::new(),
+
+            addressing_model: wk.Logical,
+            memory_model: wk.Simple,
+        }),
+    );
+    merge(&mut mergee, merged).unwrap();
+
+    let ModuleDialect::Spv(dialect) = &mergee.dialect;
+    assert_eq!(dialect.memory_model, wk.GLSL450);
+    assert_eq!(mergee.exports.len(), 1);
+}
+
+#[test]
+fn merge_into_empty_module_keeping_its_model() {
+    let wk = &spv::spec::Spec::get().well_known;
+
+    let merged = lower_export_module(Rc::new(Context::new()), "foo");
+    let mut mergee = Module::new_empty(
+        merged.cx(),
+        ModuleDialect::Spv(spv::Dialect {
+            version_major: 1,
+            version_minor: 0,
+
+            capabilities: BTreeSet::new(),
+            extensions: BTreeSet::new(),
+
+            addressing_model: wk.Logical,
+            memory_model: wk.Simple,
+        }),
+    );
+    let options =
+        MergeOptions { memory_model_policy: ModelPolicy::TakeMergee, ..Default::default() };
+    merge_with(&mut mergee, merged, &options).unwrap();
+
+    // `merged`'s model is overridden, instead of being adopted.
+    let ModuleDialect::Spv(dialect) = &mergee.dialect;
+    assert_eq!(dialect.memory_model, wk.Simple);
+    assert_eq!(mergee.exports.len(), 1);
+}
+
+#[test]
+fn merge_into_module_with_unexported_definitions() {
+    let cx = Rc::new(Context::new());
+
+    // `mergee` has no exports, but still has a (unexported) function.
+    let helper = lower_export_module(cx.clone(), "helper");
+    let helper_decl = match helper.exports.values().next() {
+        Some(&Exportee::Func(func)) => helper.funcs[func].clone(),
+        _ => unreachable!(),
+    };
+    let mut mergee = Module::new_empty(cx.clone(), helper.dialect.clone());
+    let unexported_func = mergee.funcs.define(&cx, helper_decl);
+
+    let merged = lower_export_module(cx, "foo");
+    merge(&mut mergee, merged).unwrap();
+
+    assert_eq!(mergee.exports.len(), 1);
+    let exported_func = match mergee.exports.values().next() {
+        Some(&Exportee::Func(func)) => func,
+        _ => unreachable!(),
+    };
+    assert!(mergee.funcs.get(exported_func).is_some());
+    assert!(mergee.funcs.get(unexported_func).is_some());
+}
+
+#[test]
+fn merge_rejects_shader_and_kernel() {
+    let wk = &spv::spec::Spec::get().well_known;
+    let cap = |name| spv::CapabilityDiff::capability_by_name(name).unwrap();
+    let dialect = |caps: &[u32]| {
+        ModuleDialect::Spv(spv::Dialect {
+            version_major: 1,
+            version_minor: 0,
+
+            capabilities: caps.iter().copied().collect(),
+            extensions: BTreeSet::new(),
+
+            addressing_model: wk.Logical,
+            memory_model: wk.GLSL450,
+        })
+    };
+
+    let mut mergee = dialect(&[cap("Shader")]);
+    assert!(
+        make_compatible(&mut mergee, &dialect(&[cap("Kernel")]))
+            == Err(MergeError::IncompatibleCapabilities { a: cap("Shader"), b: cap("Kernel") })
+    );
+    let capabilities = |dialect: &ModuleDialect| {
+        let ModuleDialect::Spv(dialect) = dialect;
+        dialect.capabilities.clone()
+    };
+    assert_eq!(capabilities(&mergee), [cap("Shader")].into());
+
+    make_compatible(&mut mergee, &dialect(&[cap("Linkage")])).unwrap();
+    assert_eq!(capabilities(&mergee), [cap("Shader"), cap("Linkage")].into());
+}
+
+#[test]
+fn prepared_merge_into_many_modules() {
+    let library = lower_export_module(Rc::new(Context::new()), "lib");
+    let prepared = PreparedMerge::analyze(&library);
+    for _ in 0..2 {
+        let mut client = Module::new_empty(library.cx(), library.dialect.clone());
+        prepared.merge_into(&mut client).unwrap();
+        assert_eq!(client.exports.len(), 1);
+    }
+}
+
+/// Minimal library module, only exporting a `Uniform` global variable named
+/// `link_name` (see also [`lower_export_module`]), whose type is a struct
+/// with one `u32` field, which is decorated with `Block` iff `block` is set.
+#[cfg(test)]
+fn lower_struct_var_export_module(cx: Rc<Context>, link_name: &str, block: bool) -> Module {
+    let block_decoration = if block { "OpDecorate %2 Block" } else { "" };
+    let spv_words = spv::test_asm::assemble(&format!(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %4 LinkageAttributes "{link_name}" Export
+            {block_decoration}
+            %1 = OpTypeInt 32 0
+            %2 = OpTypeStruct %1
+            %3 = OpTypePointer Uniform %2
+            %4 = OpVariable %3 Uniform
+            "#
+    ));
+    Module::lower_from_spv_words(cx, &spv_words).unwrap()
+}
+
+#[test]
+fn merge_keeps_differently_decorated_types_distinct() {
+    let cx = Rc::new(Context::new());
+    let exported_type = |module: &Module, link_name: &str| {
+        let name = cx.intern(link_name);
+        match module.exports[&ExportKey::LinkName(name)] {
+            Exportee::GlobalVar(gv) => module.global_vars[gv].type_of_ptr_to,
+            Exportee::Func(_) => unreachable!(),
+        }
+    };
+
+    for (foo_block, bar_block) in [(false, false), (true, true), (true, false), (false, true)] {
+        let mut mergee = lower_struct_var_export_module(cx.clone(), "foo", foo_block);
+        let merged = lower_struct_var_export_module(cx.clone(), "bar", bar_block);
+        merge(&mut mergee, merged).unwrap();
+
+        // NOTE: the pointer types are only equal if the struct types are.
+        let same_type = exported_type(&mergee, "foo") == exported_type(&mergee, "bar");
+        assert_eq!(same_type, foo_block == bar_block, "Block: {foo_block} vs {bar_block}");
+
+        // Lifting must keep the decoration on (only) the `Block` struct.
+        let spv_words = mergee.lift_to_spv_words().unwrap();
+        let relowered = Module::lower_from_spv_words(cx.clone(), &spv_words).unwrap();
+        assert!(exported_type(&relowered, "foo") == exported_type(&mergee, "foo"));
+        assert!(exported_type(&relowered, "bar") == exported_type(&mergee, "bar"));
+    }
+}
+
+#[test]
+fn merge_copies_global_vars_only_used_by_decorations() {
+    let cx = Rc::new(Context::new());
+    let mut mergee = lower_export_module(cx.clone(), "foo");
+
+    // `%counter` is only referenced by the `OpDecorateId` of `%buf`.
+    let mut spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %buf LinkageAttributes "buf" Export
+            OpDecorateId %buf CounterBuffer %counter
+            %uint = OpTypeInt 32 0
+            %uint_ptr = OpTypePointer Private %uint
+            %counter = OpVariable %uint_ptr Private
+            %buf = OpVariable %uint_ptr Private
+            "#,
+    );
+    // SPIR-V 1.4 (for `CounterBuffer`).
+    spv_words[1] = 0x0001_0400;
+    let merged = Module::lower_from_spv_words(cx, &spv_words).unwrap();
+
+    let options = MergeOptions { version_policy: VersionPolicy::TakeMax, ..Default::default() };
+    let report = merge_with_report(&mut mergee, merged, &options).unwrap();
+    assert_eq!(report.copied_global_vars, 2);
+    assert!(mergee.validate_references().is_ok());
+
+    // The decoration still refers to the (copied) `%counter` after lifting.
+    let lifted = Module::lower_from_spv_words(mergee.cx(), &mergee.lift_to_spv_words().unwrap());
+    assert_eq!(ReachableDefs::from_exports(&lifted.unwrap()).global_vars.len(), 2);
+}
+
+#[test]
+fn merge_keeps_all_source_records() {
+    let cx = Rc::new(Context::new());
+    let with_source = |link_name, lang, file: &str, contents: &str| {
+        let mut module = lower_export_module(cx.clone(), link_name);
+        let ModuleDebugInfo::Spv(debug_info) = &mut module.debug_info;
+        let mut sources = spv::DebugSources::default();
+        sources.file_contents.insert(cx.intern(file), contents.to_string());
+        debug_info.source_languages.insert(spv::DebugSourceLang { lang, version: 450 }, sources);
+        module
+    };
+
+    // NOTE: the languages are `GLSL` and `HLSL`, respectively.
+    let mut mergee = with_source("foo", 2, "a.glsl", "// GLSL");
+    merge(&mut mergee, with_source("bar", 5, "a.hlsl", "// HLSL")).unwrap();
+    merge(&mut mergee, with_source("baz", 2, "a.glsl", "// other GLSL")).unwrap();
+
+    let records: Vec<_> =
+        mergee.source_records().map(|r| (r.lang.lang, &cx[r.file.unwrap()], r.contents)).collect();
+    assert_eq!(records, [(2, "a.glsl", "// GLSL"), (5, "a.hlsl", "// HLSL")]);
+}
+
+#[test]
+fn merge_prefixes_debug_names() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpName %1 "bar"
+            OpName %4 "h"
+            OpDecorate %1 LinkageAttributes "bar" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %4 = OpFunction %2 None %3
+            %5 = OpLabel
+            OpReturn
+            OpFunctionEnd
+            %1 = OpFunction %2 None %3
+            %6 = OpLabel
+            %7 = OpFunctionCall %2 %4
+            OpReturn
+            OpFunctionEnd
+            "#,
+    );
+    let cx = Rc::new(Context::new());
+    let merged = Module::lower_from_spv_words(cx.clone(), &spv_words).unwrap();
+
+    let mut mergee = lower_export_module(cx.clone(), "foo");
+
+    let options = MergeOptions { debug_name_prefix: Some(cx.intern("lib.")), ..Default::default() };
+    merge_with(&mut mergee, merged, &options).unwrap();
+
+    let bar = match mergee.exports[&ExportKey::LinkName(cx.intern("bar"))] {
+        Exportee::Func(func) => func,
+        Exportee::GlobalVar(_) => unreachable!(),
+    };
+    let name = |func| spv_name_attr(&cx, mergee.funcs[func].attrs).map(|(_, name)| name);
+    let callees: Vec<_> = mergee.call_graph().callees_of(bar).collect();
+    assert_eq!(callees.len(), 1);
+    assert_eq!(name(bar).as_deref(), Some("bar"));
+    assert_eq!(name(callees[0]).as_deref(), Some("lib.h"));
+}
+
+#[test]
+fn merge_identical_exports() {
+    let cx = Rc::new(Context::new());
+    let lower_in_cx = |link_name| lower_export_module(cx.clone(), link_name);
+    let foo = ExportKey::LinkName(cx.intern("foo"));
+
+    let mut mergee = lower_in_cx("foo");
+    merge(&mut mergee, lower_in_cx("foo")).unwrap();
+    merge(&mut mergee, lower_in_cx("bar")).unwrap();
+    assert_eq!(mergee.exports.len(), 2);
+
+    // Importing (instead of defining) the same function makes it divergent.
+    let mut merged = lower_in_cx("foo");
+    let Exportee::Func(merged_foo) = merged.exports[&foo] else { unreachable!() };
+    merged.funcs[merged_foo].def = DeclDef::Imported(crate::Import::LinkName(cx.intern("foo")));
+    let err = merge(&mut mergee, merged).unwrap_err();
+    assert_eq!(err.to_string(), "duplicate export (link name `foo`)");
+    assert!(matches!(err, MergeError::DuplicateExportKey { export_key, .. } if export_key == foo));
+}
+
+/// Minimal library module, only exporting a function named `link_name` (both
+/// as its link name, and its debug name), which calls an (unexported) helper
+/// function named `helper_name`.
+#[cfg(test)]
+fn lower_export_calling_helper_module(
+    cx: Rc<Context>,
+    link_name: &str,
+    helper_name: &str,
+) -> Module {
+    let spv_words = spv::test_asm::assemble(&format!(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpName %helper "{helper_name}"
+            OpName %main "{link_name}"
+            OpDecorate %main LinkageAttributes "{link_name}" Export
+            %void = OpTypeVoid
+            %func_type = OpTypeFunction %void
+            %helper = OpFunction %void None %func_type
+            %helper_entry = OpLabel
+            OpReturn
+            OpFunctionEnd
+            %main = OpFunction %void None %func_type
+            %main_entry = OpLabel
+            %call = OpFunctionCall %void %helper
+            OpReturn
+            OpFunctionEnd
+            "#
+    ));
+    Module::lower_from_spv_words(cx, &spv_words).unwrap()
+}
+
+#[test]
+fn merge_is_deterministic() {
+    // NOTE: deduplication (of the identical helpers) is the main source of
+    // potential nondeterminism, as it has to search for identical functions.
+    let merge_and_lift = || {
+        let cx = Rc::new(Context::new());
+        let mut mergee = lower_export_calling_helper_module(cx.clone(), "foo", "helper");
+        let options = MergeOptions { dedup_functions: true, ..Default::default() };
+        for link_name in ["bar", "baz", "qux"] {
+            let merged = lower_export_calling_helper_module(cx.clone(), link_name, "helper");
+            let report = merge_with_report(&mut mergee, merged, &options).unwrap();
+            assert_ne!(report.deduplicated_funcs, 0);
+        }
+        mergee.lift_to_spv_words().unwrap()
+    };
+    assert!(merge_and_lift() == merge_and_lift());
+}
+
+#[test]
+fn merge_identical_exports_calling_helpers() {
+    let cx = Rc::new(Context::new());
+    let lower_in_cx =
+        |helper_name| lower_export_calling_helper_module(cx.clone(), "foo", helper_name);
+    let foo = ExportKey::LinkName(cx.intern("foo"));
+
+    let mut mergee = lower_in_cx("h");
+    let Exportee::Func(mergee_foo) = mergee.exports[&foo] else { unreachable!() };
+    merge(&mut mergee, lower_in_cx("h")).unwrap();
+
+    // `mergee`'s export (and its helper) is kept, without copying `merged`'s.
+    assert_eq!(mergee.exports.len(), 1);
+    assert!(matches!(mergee.exports[&foo], Exportee::Func(func) if func == mergee_foo));
+    assert_eq!(mergee.call_graph().callees_of(mergee_foo).count(), 1);
+    assert_eq!(ReachableDefs::from_exports(&mergee).funcs.len(), 2);
+
+    // A divergent helper (here, only by its name) makes the exports divergent.
+    assert!(matches!(
+        merge(&mut mergee, lower_in_cx("h2")),
+        Err(MergeError::DuplicateExportKey { export_key, .. }) if export_key == foo
+    ));
+}
+
+#[test]
+fn merge_dedups_functions() {
+    let wk = &spv::spec::Spec::get().well_known;
+
+    // Returns the `MergeReport`, and the number of `OpFunction`s after lifting.
+    let merge_with_dedup = |dedup_functions| {
+        let cx = Rc::new(Context::new());
+        let mut mergee = lower_export_calling_helper_module(cx.clone(), "foo", "helper");
+        let merged = lower_export_calling_helper_module(cx, "bar", "helper");
+        let options = MergeOptions { dedup_functions, ..Default::default() };
+        let report = merge_with_report(&mut mergee, merged, &options).unwrap();
+        let spv_words = mergee.lift_to_spv_words().unwrap();
+        let func_count = spv::read::ModuleParser::read_from_spv_words(&spv_words)
+            .unwrap()
+            .filter(|inst| inst.as_ref().unwrap().opcode == wk.OpFunction)
+            .count();
+        (report, func_count)
+    };
+
+    let (report, func_count) = merge_with_dedup(false);
+    assert_eq!((report.copied_funcs, report.deduplicated_funcs), (2, 0));
+    assert_eq!(func_count, 4);
+
+    // `bar`'s `helper` is replaced by `foo`'s (identical) one.
+    let (report, deduped_func_count) = merge_with_dedup(true);
+    assert_eq!((report.copied_funcs, report.deduplicated_funcs), (1, 1));
+    assert_eq!(deduped_func_count, func_count - 1);
+}
+
+/// Compute shader module, with the `OpExecutionMode(Id)`s in `execution_modes`.
+#[cfg(test)]
+fn lower_entry_point_module(cx: Rc<Context>, execution_modes: &str) -> Module {
+    let mut spv_words = spv::test_asm::assemble(&format!(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpCapability DenormPreserve
+            OpMemoryModel Logical GLSL450
+            OpEntryPoint GLCompute %main "main"
+            {execution_modes}
+            %void = OpTypeVoid
+            %uint = OpTypeInt 32 0
+            %one = OpConstant %uint 1
+            %two = OpConstant %uint 2
+            %func_type = OpTypeFunction %void
+            %main = OpFunction %void None %func_type
+            %entry = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#
+    ));
+    // SPIR-V 1.4 (for `OpExecutionModeId` and `DenormPreserve`).
+    spv_words[1] = 0x0001_0400;
+    Module::lower_from_spv_words(cx, &spv_words).unwrap()
+}
+
+/// The SPIR-V `ExecutionMode` named `name`.
+#[cfg(test)]
+fn execution_mode(name: &str) -> u32 {
+    let spv_spec = spv::spec::Spec::get();
+    match &spv_spec.operand_kinds[spv_spec.operand_kinds.lookup("ExecutionMode").unwrap()] {
+        spv::spec::OperandKindDef::ValueEnum { variants } => {
+            u32::from(variants.lookup(name).unwrap())
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn merge_checks_execution_modes() {
+    let merge_entry_point = |execution_modes| {
+        let cx = Rc::new(Context::new());
+        let mut mergee = lower_export_module(cx.clone(), "foo");
+        let merged = lower_entry_point_module(cx, execution_modes);
+        // NOTE: `mergee` is SPIR-V 1.0, while `merged` is SPIR-V 1.4.
+        let options = MergeOptions { version_policy: VersionPolicy::TakeMax, ..Default::default() };
+        merge_with(&mut mergee, merged, &options)
+    };
+    let conflicting_mode = |result| match result {
+        Err(MergeError::ConflictingExecutionMode { target: _, mode }) => Some(mode),
+        _ => None,
+    };
+
+    // Different execution modes never conflict.
+    merge_entry_point(
+        "
+        OpExecutionMode %main LocalSize 1 1 1
+        OpExecutionMode %main DenormPreserve 32
+        ",
+    )
+    .unwrap();
+
+    // The same execution mode, with different operands, conflicts.
+    let result = merge_entry_point(
+        "
+        OpExecutionMode %main LocalSize 1 1 1
+        OpExecutionMode %main LocalSize 2 1 1
+        ",
+    );
+    assert_eq!(conflicting_mode(result), Some(execution_mode("LocalSize")));
+
+    // NOTE: conflicting `OpExecutionModeId`s can't be lowered from the same
+    // module (see `OrdAssertEq`), and so they're only tested across modules,
+    // by `merge_checks_execution_modes_of_same_entry_points`.
+
+    // Some execution modes can be repeated, for different first operands.
+    merge_entry_point(
+        "
+        OpExecutionMode %main DenormPreserve 16
+        OpExecutionMode %main DenormPreserve 32
+        ",
+    )
+    .unwrap();
+}
+
+#[test]
+fn merge_checks_execution_modes_of_same_entry_points() {
+    // Compute shader `main` entry-point, with its own `Input` interface variable
+    // (so entry-points from different modules are different `ExportKey`s).
+    let lower_in_cx = |cx: &Rc<Context>, execution_mode: &str| {
+        let mut spv_words = spv::test_asm::assemble(&format!(
+            r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            OpEntryPoint GLCompute %main "main" %id
+            {execution_mode}
+            OpDecorate %id BuiltIn GlobalInvocationId
+            %void = OpTypeVoid
+            %uint = OpTypeInt 32 0
+            %one = OpConstant %uint 1
+            %two = OpConstant %uint 2
+            %uint3 = OpTypeVector %uint 3
+            %uint3_ptr = OpTypePointer Input %uint3
+            %id = OpVariable %uint3_ptr Input
+            %func_type = OpTypeFunction %void
+            %main = OpFunction %void None %func_type
+            %entry = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#
+        ));
+        // SPIR-V 1.2 (for `OpExecutionModeId`).
+        spv_words[1] = 0x0001_0200;
+        Module::lower_from_spv_words(cx.clone(), &spv_words).unwrap()
+    };
+    let cx = Rc::new(Context::new());
+    let conflicting_mode = |a, b| {
+        let mut mergee = lower_in_cx(&cx, a);
+        match merge(&mut mergee, lower_in_cx(&cx, b)) {
+            Err(MergeError::ConflictingExecutionMode { target: _, mode }) => Some(mode),
+            _ => None,
+        }
+    };
+
+    // Identical execution modes don't conflict (the entry-points themselves
+    // are kept as separate exports, as their interfaces differ).
+    let local_size_1 = "OpExecutionMode %main LocalSize 1 1 1";
+    let mut mergee = lower_in_cx(&cx, local_size_1);
+    merge(&mut mergee, lower_in_cx(&cx, local_size_1)).unwrap();
+    assert_eq!(mergee.exports.len(), 2);
+
+    // Different `LocalSize`s for the same (merged) entry-point conflict,
+    // even if each function on its own has only one `LocalSize`.
+    assert_eq!(
+        conflicting_mode(local_size_1, "OpExecutionMode %main LocalSize 2 1 1"),
+        Some(execution_mode("LocalSize"))
+    );
+
+    // Same as above, but with `OpExecutionModeId`.
+    assert_eq!(
+        conflicting_mode(
+            "OpExecutionModeId %main LocalSizeId %one %one %one",
+            "OpExecutionModeId %main LocalSizeId %two %one %one",
+        ),
+        Some(execution_mode("LocalSizeId"))
+    );
+}
+
+/// Merge two conflicting (i.e. without any interface variables) compute shader
+/// `main` entry-points, with `LocalSize 1 1 1` for `mergee`'s, and `LocalSize
+/// 2 1 1` for `merged`'s, returning `mergee` and its original `main` function.
+#[cfg(test)]
+fn merge_conflicting_entry_points(
+    on_export_conflict: ExportConflictPolicy,
+) -> (Module, Func, MergeReport) {
+    let cx = Rc::new(Context::new());
+    let mut mergee = lower_entry_point_module(cx.clone(), "OpExecutionMode %main LocalSize 1 1 1");
+    let merged = lower_entry_point_module(cx, "OpExecutionMode %main LocalSize 2 1 1");
+    let Some(&Exportee::Func(mergee_main)) = mergee.exports.values().next() else { unreachable!() };
+
+    let options = MergeOptions { on_export_conflict, ..Default::default() };
+    let report = merge_with_report(&mut mergee, merged, &options).unwrap();
+    (mergee, mergee_main, report)
+}
+
+#[test]
+fn merge_conflicting_entry_points_keeping_mergee() {
+    let (mergee, mergee_main, report) =
+        merge_conflicting_entry_points(ExportConflictPolicy::KeepMergee);
+
+    // Nothing is imported from `merged`, as its only export lost.
+    assert_eq!((report.copied_global_vars, report.copied_funcs), (0, 0));
+    assert!(report.added_exports.is_empty());
+    assert_eq!(mergee.exports.len(), 1);
+    assert!(matches!(mergee.exports.values().next(), Some(&Exportee::Func(f)) if f == mergee_main));
+    assert_eq!(ReachableDefs::from_exports(&mergee).funcs.len(), 1);
+}
+
+#[test]
+fn merge_conflicting_entry_points_keeping_merged() {
+    let (mergee, mergee_main, report) =
+        merge_conflicting_entry_points(ExportConflictPolicy::KeepMerged);
+
+    assert_eq!(report.copied_funcs, 1);
+    assert_eq!(report.added_exports.len(), 1);
+    assert_eq!(mergee.exports.len(), 1);
+    let entry_point = mergee.entry_points().next().unwrap();
+    assert!(entry_point.func != mergee_main);
+    assert_eq!(entry_point.name, "main");
+}
+
+#[test]
+fn merge_conflicting_entry_points_renaming_merged() {
+    fn rename(_cx: &Context, export_key: &ExportKey) -> ExportKey {
+        match export_key {
+            ExportKey::SpvEntryPoint { imms, interface_global_vars } => ExportKey::SpvEntryPoint {
+                imms: [imms[0]].into_iter().chain(spv::encode_literal_string("main2")).collect(),
+                interface_global_vars: interface_global_vars.clone(),
+            },
+            ExportKey::LinkName(_) => unreachable!(),
+        }
+    }
+    let (mergee, mergee_main, report) =
+        merge_conflicting_entry_points(ExportConflictPolicy::Rename(rename));
+
+    assert_eq!(report.copied_funcs, 1);
+    let entry_points: Vec<_> = mergee.entry_points().map(|e| (e.name, e.func)).collect();
+    assert_eq!(entry_points.len(), 2);
+    assert!(entry_points[0] == ("main".to_string(), mergee_main));
+    assert!(entry_points[1].0 == "main2" && entry_points[1].1 != mergee_main);
+}
+
+/// Library module exporting a function named `link_name`, which uses a sampler
+/// variable bound to `DescriptorSet 0 Binding 0` (only used by that function),
+/// and exporting another sampler variable (as `{link_name}.shared`), bound to
+/// `DescriptorSet 0 Binding 1`.
+#[cfg(test)]
+fn lower_bound_vars_module(cx: Rc<Context>, link_name: &str) -> Module {
+    let spv_words = spv::test_asm::assemble(&format!(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %main LinkageAttributes "{link_name}" Export
+            OpDecorate %shared LinkageAttributes "{link_name}.shared" Export
+            OpDecorate %private DescriptorSet 0
+            OpDecorate %private Binding 0
+            OpDecorate %shared DescriptorSet 0
+            OpDecorate %shared Binding 1
+            %void = OpTypeVoid
+            %sampler = OpTypeSampler
+            %sampler_ptr = OpTypePointer UniformConstant %sampler
+            %private = OpVariable %sampler_ptr UniformConstant
+            %shared = OpVariable %sampler_ptr UniformConstant
+            %func_type = OpTypeFunction %void
+            %main = OpFunction %void None %func_type
+            %entry = OpLabel
+            %loaded = OpLoad %sampler %private
+            OpReturn
+            OpFunctionEnd
+            "#
+    ));
+    Module::lower_from_spv_words(cx, &spv_words).unwrap()
+}
+
+#[test]
+fn merge_resolves_binding_conflicts() {
+    let cx = Rc::new(Context::new());
+    let mut mergee = lower_bound_vars_module(cx.clone(), "foo");
+    let merged = lower_bound_vars_module(cx.clone(), "bar");
+    let options = MergeOptions { resolve_binding_conflicts: true, ..Default::default() };
+    let report = merge_with_report(&mut mergee, merged, &options).unwrap();
+
+    let binding_of = |gv| {
+        let (descriptor_set, _, binding) =
+            spv_descriptor_set_and_binding(&cx, mergee.global_vars[gv].attrs).unwrap();
+        (descriptor_set, binding)
+    };
+    let exported_global_var =
+        |link_name: &str| match mergee.exports[&ExportKey::LinkName(cx.intern(link_name))] {
+            Exportee::GlobalVar(gv) => gv,
+            Exportee::Func(_) => unreachable!(),
+        };
+
+    // `bar`'s unexported variable is moved to the lowest free `Binding`.
+    assert_eq!(report.rebindings.len(), 1);
+    let rebinding = report.rebindings[0];
+    assert_eq!((rebinding.descriptor_set, rebinding.old_binding, rebinding.new_binding), (0, 0, 2));
+    assert_eq!(binding_of(rebinding.global_var), (0, 2));
+
+    // Exported variables are left alone, even if they alias each other.
+    assert_eq!(binding_of(exported_global_var("foo.shared")), (0, 1));
+    assert_eq!(binding_of(exported_global_var("bar.shared")), (0, 1));
+}
+
+#[test]
+fn merge_selected_exports() {
+    let cx = Rc::new(Context::new());
+    let mut mergee = lower_export_module(cx.clone(), "foo");
+    let bar = ExportKey::LinkName(cx.intern("bar"));
+
+    // Only `bar` (and the variable it uses) is imported, not `bar.shared`.
+    let merged = lower_bound_vars_module(cx.clone(), "bar");
+    merge_selected(&mut mergee, merged, std::slice::from_ref(&bar)).unwrap();
+    assert_eq!(mergee.exports.len(), 2);
+    assert!(mergee.exports.contains_key(&bar));
+    let reachable = ReachableDefs::from_exports(&mergee);
+    assert_eq!((reachable.global_vars.len(), reachable.funcs.len()), (1, 2));
+
+    let unknown = ExportKey::LinkName(cx.intern("baz"));
+    let merged = lower_bound_vars_module(cx.clone(), "bar");
+    assert!(matches!(
+        merge_selected(&mut mergee, merged, std::slice::from_ref(&unknown)),
+        Err(MergeError::UnknownExportKey { export_key, name })
+            if export_key == unknown && name == "baz"
+    ));
+}
+
+#[test]
+fn merge_all_modules() {
+    let cx = Rc::new(Context::new());
+    let lower_in_cx = |link_name| lower_export_module(cx.clone(), link_name);
+
+    let (module, report) =
+        merge_all_with(["foo", "bar", "baz"].map(lower_in_cx), &MergeOptions::default()).unwrap();
+    assert_eq!(module.exports.len(), 3);
+    assert_eq!(report.added_exports.len(), 2);
+    assert_eq!(report.copied_funcs, 2);
+
+    assert!(matches!(merge_all([]), Err(MergeError::NoModules)));
+
+    // The third module's `foo` differs from the first one's.
+    let modules = [
+        lower_in_cx("foo"),
+        lower_in_cx("bar"),
+        lower_export_calling_helper_module(cx, "foo", "h"),
+    ];
+    assert!(matches!(
+        merge_all(modules),
+        Err(MergeError::InModule { module_idx: 2, error })
+            if matches!(*error, MergeError::DuplicateExportKey { .. })
+    ));
+}
+
+#[test]
+fn merge_dedups_debug_names() {
+    let cx = Rc::new(Context::new());
+    let export_and_helper = |module: &Module, link_name| {
+        let Exportee::Func(export) = module.exports[&ExportKey::LinkName(cx.intern(link_name))]
+        else {
+            unreachable!()
+        };
+        let helper = module.call_graph().callees_of(export).next().unwrap();
+        (export, helper)
+    };
+    let name = |module: &Module, func| spv_name_attr(&cx, module.funcs[func].attrs).unwrap().1;
+
+    // Both helpers are named `main`, as is `merged`'s export.
+    let mut mergee = lower_export_calling_helper_module(cx.clone(), "foo", "main");
+    let merged = lower_export_calling_helper_module(cx.clone(), "main", "main");
+    let options = MergeOptions { dedup_debug_names: true, ..Default::default() };
+    merge_with(&mut mergee, merged, &options).unwrap();
+
+    let (foo, foo_helper) = export_and_helper(&mergee, "foo");
+    let (main, main_helper) = export_and_helper(&mergee, "main");
+    assert_eq!(name(&mergee, foo), "foo");
+    assert_eq!(name(&mergee, foo_helper), "main");
+
+    // Only the (unexported) helper from `merged` is renamed.
+    assert_eq!(name(&mergee, main), "main");
+    assert_eq!(name(&mergee, main_helper), "main.1");
+}
+
+#[test]
+fn merge_with_version_policies() {
+    let cx = Rc::new(Context::new());
+    let lower_with_version = |link_name, version_minor: u8| {
+        let mut module = lower_export_module(cx.clone(), link_name);
+        let ModuleDialect::Spv(dialect) = &mut module.dialect;
+        dialect.version_minor = version_minor;
+        module
+    };
+    // Merge a SPIR-V `1.{merged_minor}` module into a `1.{mergee_minor}` one.
+    let merge_versions = |version_policy, mergee_minor, merged_minor| {
+        let mut mergee = lower_with_version("foo", mergee_minor);
+        let options = MergeOptions { version_policy, ..Default::default() };
+        merge_with(&mut mergee, lower_with_version("bar", merged_minor), &options).map(|()| {
+            let ModuleDialect::Spv(dialect) = &mergee.dialect;
+            dialect.version()
+        })
+    };
+
+    assert!(
+        merge_versions(VersionPolicy::Strict, 5, 3)
+            == Err(MergeError::VersionMismatch {
+                mergee: spv::SpvVersion(1, 5),
+                merged: spv::SpvVersion(1, 3),
+            })
+    );
+    assert!(merge_versions(VersionPolicy::TakeMax, 5, 3) == Ok(spv::SpvVersion(1, 5)));
+    assert!(merge_versions(VersionPolicy::TakeMergee, 5, 3) == Ok(spv::SpvVersion(1, 5)));
+
+    // The other way around, only `TakeMax` upgrades `mergee`.
+    assert!(merge_versions(VersionPolicy::TakeMax, 3, 5) == Ok(spv::SpvVersion(1, 5)));
+    assert!(merge_versions(VersionPolicy::TakeMergee, 3, 5) == Ok(spv::SpvVersion(1, 3)));
+}
+
+#[test]
+fn merge_with_report_summarizes_changes() {
+    let cx = Rc::new(Context::new());
+    let mut mergee = lower_export_module(cx.clone(), "foo");
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpCapability Int64
+            OpExtension "SPV_KHR_storage_buffer_storage_class"
+            OpMemoryModel Logical GLSL450
+            OpDecorate %bar LinkageAttributes "bar" Export
+            OpDecorate %buf LinkageAttributes "buf" Export
+            %void = OpTypeVoid
+            %ulong = OpTypeInt 64 0
+            %ulong_ptr = OpTypePointer Private %ulong
+            %buf = OpVariable %ulong_ptr Private
+            %func_type = OpTypeFunction %void
+            %bar = OpFunction %void None %func_type
+            %entry = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#,
+    );
+    let merged = Module::lower_from_spv_words(cx.clone(), &spv_words).unwrap();
+    let report = merge_with_report(&mut mergee, merged, &MergeOptions::default()).unwrap();
+
+    let int64 = spv::CapabilityDiff::capability_by_name("Int64").unwrap();
+    assert_eq!(report.added_capabilities.into_iter().collect::<Vec<_>>(), [int64]);
+    assert_eq!(
+        report.added_extensions.into_iter().collect::<Vec<_>>(),
+        ["SPV_KHR_storage_buffer_storage_class"]
+    );
+
+    // `void`, `ulong`, and the pointer to `ulong` (but not the function type,
+    // as function signatures aren't `Type`s in SPIR-T).
+    assert_eq!(report.reachable_types, 3);
+    assert_eq!((report.copied_global_vars, report.copied_funcs), (1, 1));
+    assert_eq!(report.deduplicated_funcs, 0);
+    assert!(report.rebindings.is_empty());
+
+    let added_exports: Vec<_> = report
+        .added_exports
+        .iter()
+        .map(|export_key| match export_key {
+            &ExportKey::LinkName(name) => &cx[name],
+            ExportKey::SpvEntryPoint { .. } => unreachable!(),
+        })
+        .collect();
+    assert_eq!(added_exports, ["bar", "buf"]);
+}
+
+#[test]
+fn merge_validates_inputs() {
+    use crate::passes::validate::Referrer;
+
+    let cx = Rc::new(Context::new());
+    let mut mergee = lower_export_module(cx.clone(), "foo");
+
+    // Export a function from another module (sharing the same `Context`),
+    // which isn't defined in `merged`.
+    let other = lower_export_module(cx.clone(), "other");
+    let Some(&Exportee::Func(other_func)) = other.exports.values().next() else { unreachable!() };
+    let mut merged = lower_export_module(cx.clone(), "bar");
+    merged.exports.insert(ExportKey::LinkName(cx.intern("dangling")), Exportee::Func(other_func));
+
+    let options = MergeOptions { validate_inputs: true, ..Default::default() };
+    let errors = match merge_with(&mut mergee, merged, &options) {
+        Err(MergeError::InvalidMerged(errors)) => errors,
+        _ => unreachable!(),
+    };
+    assert!(matches!(
+        errors[..],
+        [ReferenceError::UndefinedFunc { referrer: Referrer::Export(_), func }] if func == other_func
+    ));
+
+    // Nothing was changed in `mergee`.
+    assert_eq!(mergee.exports.len(), 1);
+}
+
+#[test]
+fn merged_estimated_spv_word_count_is_upper_bound() {
+    let cx = Rc::new(Context::new());
+    let assert_upper_bound = |module: &Module| {
+        let lifted_words = module.lift_to_spv_words().unwrap();
+        assert!(module.estimated_spv_word_count() >= lifted_words.len());
+    };
+
+    let modules = [
+        lower_export_module(cx.clone(), "foo"),
+        lower_glsl_std_450_export_module(cx.clone(), "abs", 1),
+        lower_struct_var_export_module(cx.clone(), "buf", true),
+        lower_export_calling_helper_module(cx.clone(), "bar", "helper"),
+        lower_entry_point_module(cx.clone(), "OpExecutionModeId %main LocalSizeId %one %two %one"),
+        lower_bound_vars_module(cx.clone(), "baz"),
+    ];
+    for module in &modules {
+        assert_upper_bound(module);
+    }
+
+    // NOTE: merging can leave behind definitions which aren't reachable from
+    // the exports anymore (and so aren't lifted).
+    let options = MergeOptions { version_policy: VersionPolicy::TakeMax, ..Default::default() };
+    let (merged, _) = merge_all_with(modules, &options).unwrap();
+    assert_upper_bound(&merged);
+}
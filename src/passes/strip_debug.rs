@@ -0,0 +1,219 @@
+//! Removal of (non-semantic) debuginfo, e.g. for shipping release shaders.
+
+use crate::transform::{cached_transform_interned_uses, Transformed, Transformer};
+use crate::{
+    spv, Attr, AttrSet, AttrSetDef, Const, Context, DataInstForm, Func, GlobalVar, Module, Type,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::VecDeque;
+use std::mem;
+
+/// Statistics about the debuginfo removed by [`strip_debug_info`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StripDebugInfoStats {
+    /// Number of [`Attr`]s removed, counted for every (reachable) use of each
+    /// [`AttrSet`] they were removed from.
+    pub removed_attrs: usize,
+
+    /// Number of module-level debuginfo entries removed (from [`spv::ModuleDebugInfo`]),
+    /// i.e. source languages (`OpSource`), source files (`OpString`/`OpSource`),
+    /// source extensions (`OpSourceExtension`) and module processes (`OpModuleProcessed`).
+    pub removed_module_debug_entries: usize,
+}
+
+/// Remove all debuginfo from `module` (and everything reachable from its exports),
+/// i.e. `OpName`/`OpMemberName` annotations, [`Attr::SpvDebugLine`] (`OpLine`),
+/// and module-level debuginfo (see [`StripDebugInfoStats`] for details).
+///
+/// Semantically significant annotations (i.e. decorations and execution modes),
+/// and exports (including entry-point names), are left intact, and `OpString`s
+/// only used by the removed debuginfo simply won't be emitted anymore by lifting.
+pub fn strip_debug_info(module: &mut Module) -> StripDebugInfoStats {
+    let mut stripper = DebugInfoStripper {
+        cx: &module.cx(),
+
+        stats: StripDebugInfoStats::default(),
+
+        transformed_attr_sets: FxHashMap::default(),
+        transformed_types: FxHashMap::default(),
+        transformed_consts: FxHashMap::default(),
+        transformed_data_inst_forms: FxHashMap::default(),
+        seen_global_vars: FxHashSet::default(),
+        global_var_queue: VecDeque::new(),
+        seen_funcs: FxHashSet::default(),
+        func_queue: VecDeque::new(),
+    };
+
+    // Seed the queues starting from the module exports (also stripping the
+    // module-level debuginfo, along the way).
+    stripper.in_place_transform_module(module);
+
+    // Process the queues until they're all empty.
+    while !stripper.global_var_queue.is_empty() || !stripper.func_queue.is_empty() {
+        while let Some(gv) = stripper.global_var_queue.pop_front() {
+            stripper.in_place_transform_global_var_decl(&mut module.global_vars[gv]);
+        }
+        while let Some(func) = stripper.func_queue.pop_front() {
+            stripper.in_place_transform_func_decl(&mut module.funcs[func]);
+        }
+    }
+
+    stripper.stats
+}
+
+struct DebugInfoStripper<'a> {
+    cx: &'a Context,
+
+    stats: StripDebugInfoStats,
+
+    /// Cached results of `transform_attr_set_use`, along with how many `Attr`s
+    /// were removed (to be able to count them for every use).
+    transformed_attr_sets: FxHashMap<AttrSet, (Transformed<AttrSet>, usize)>,
+
+    // FIXME: build some automation to avoid ever repeating these.
+    transformed_types: FxHashMap<Type, Transformed<Type>>,
+    transformed_consts: FxHashMap<Const, Transformed<Const>>,
+    transformed_data_inst_forms: FxHashMap<DataInstForm, Transformed<DataInstForm>>,
+    seen_global_vars: FxHashSet<GlobalVar>,
+    global_var_queue: VecDeque<GlobalVar>,
+    seen_funcs: FxHashSet<Func>,
+    func_queue: VecDeque<Func>,
+}
+
+impl DebugInfoStripper<'_> {
+    fn is_debuginfo(attr: &Attr) -> bool {
+        let wk = &spv::spec::Spec::get().well_known;
+        match attr {
+            Attr::SpvAnnotation(spv_inst) => {
+                [wk.OpName, wk.OpMemberName].contains(&spv_inst.opcode)
+            }
+            Attr::SpvDebugLine { .. } => true,
+
+            Attr::QPtr(_)
+            | Attr::SpvAnnotationWithConstInputs { .. }
+            | Attr::SpvBitflagsOperand(_)
+            | Attr::Diagnostics(_) => false,
+        }
+    }
+}
+
+impl Transformer for DebugInfoStripper<'_> {
+    cached_transform_interned_uses!();
+
+    fn transform_attr_set_use(&mut self, attrs: AttrSet) -> Transformed<AttrSet> {
+        let (transformed, removed_attrs) = match self.transformed_attr_sets.get(&attrs) {
+            Some(&cached) => cached,
+            None => {
+                let attrs_def = &self.cx[attrs];
+                let removed_attrs =
+                    attrs_def.attrs.iter().filter(|attr| Self::is_debuginfo(attr)).count();
+
+                // NOTE: the remaining `Attr`s still need to be transformed,
+                // as they can refer to e.g. `Const`s (with debuginfo of their own).
+                let transformed = if removed_attrs == 0 {
+                    self.transform_attr_set_def(attrs_def)
+                } else {
+                    let mut attrs_def = AttrSetDef {
+                        attrs: attrs_def
+                            .attrs
+                            .iter()
+                            .filter(|attr| !Self::is_debuginfo(attr))
+                            .cloned()
+                            .collect(),
+                    };
+                    self.transform_attr_set_def(&attrs_def).apply_to(&mut attrs_def);
+                    Transformed::Changed(attrs_def)
+                }
+                .map(|attrs_def| self.cx.intern(attrs_def));
+
+                self.transformed_attr_sets.insert(attrs, (transformed, removed_attrs));
+                (transformed, removed_attrs)
+            }
+        };
+        self.stats.removed_attrs += removed_attrs;
+        transformed
+    }
+
+    fn transform_global_var_use(&mut self, gv: GlobalVar) -> Transformed<GlobalVar> {
+        if self.seen_global_vars.insert(gv) {
+            self.global_var_queue.push_back(gv);
+        }
+        Transformed::Unchanged
+    }
+    fn transform_func_use(&mut self, func: Func) -> Transformed<Func> {
+        if self.seen_funcs.insert(func) {
+            self.func_queue.push_back(func);
+        }
+        Transformed::Unchanged
+    }
+
+    fn in_place_transform_spv_module_debug_info(&mut self, debug_info: &mut spv::ModuleDebugInfo) {
+        let spv::ModuleDebugInfo {
+            original_generator_magic: _,
+            original_id_bound: _,
+            source_languages,
+            source_extensions,
+            module_processes,
+        } = debug_info;
+
+        self.stats.removed_module_debug_entries += mem::take(source_languages)
+            .into_values()
+            .map(|sources| 1 + sources.file_contents.len())
+            .sum::<usize>()
+            + mem::take(source_extensions).len()
+            + mem::take(module_processes).len();
+    }
+}
+
+#[test]
+fn strip_debug_info_keeps_semantics() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpMemoryModel Logical GLSL450
+        OpEntryPoint GLCompute %main "main"
+        OpExecutionMode %main LocalSize 1 1 1
+        %file = OpString "foo.glsl"
+        OpSource GLSL 450 %file "void main() {}"
+        OpName %main "main"
+        OpName %S "S"
+        OpMemberName %S 0 "x"
+        OpName %buf "buf"
+        OpDecorate %S Block
+        OpMemberDecorate %S 0 Offset 0
+        %void = OpTypeVoid
+        %uint = OpTypeInt 32 0
+        %S = OpTypeStruct %uint
+        %S_ptr = OpTypePointer Uniform %S
+        %buf = OpVariable %S_ptr Uniform
+        %func_type = OpTypeFunction %void
+        %main = OpFunction %void None %func_type
+        %entry = OpLabel
+        OpLine %file 1 1
+        %loaded = OpLoad %S %buf
+        OpNoLine
+        OpReturn
+        OpFunctionEnd
+        "#,
+    );
+    let mut module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+
+    // The 4 `OpName`/`OpMemberName`s and the `OpLine`, and the `OpSource`
+    // (both its source language, and its source file).
+    let stats = strip_debug_info(&mut module);
+    assert_eq!(stats, StripDebugInfoStats { removed_attrs: 5, removed_module_debug_entries: 2 });
+
+    let wk = &spv::spec::Spec::get().well_known;
+    let lifted_words = module.lift_to_spv_words().unwrap();
+    let opcodes: Vec<_> = spv::read::ModuleParser::read_from_spv_words(&lifted_words)
+        .unwrap()
+        .map(|inst| inst.unwrap().opcode)
+        .collect();
+    for removed in [wk.OpName, wk.OpMemberName, wk.OpLine, wk.OpSource, wk.OpString] {
+        assert!(!opcodes.contains(&removed), "{} wasn't removed", removed.name());
+    }
+    for kept in [wk.OpEntryPoint, wk.OpExecutionMode, wk.OpDecorate, wk.OpMemberDecorate] {
+        assert!(opcodes.contains(&kept), "{} was removed", kept.name());
+    }
+}
@@ -0,0 +1,664 @@
+//! Validation (of invariants not already enforced by the IR itself).
+
+use crate::func_at::FuncAt;
+use crate::passes::{legalize, version};
+use crate::visit::{InnerVisit, ReachableUseCollector, Visitor};
+use crate::{
+    spv, AttrSet, Const, Context, ControlNode, ControlNodeKind, DataInst, DataInstForm,
+    DataInstKind, DeclDef, ExportKey, Func, FuncDefBody, GlobalVar, Module, ModuleDialect, Type,
+    Value,
+};
+use rustc_hash::FxHashSet;
+use std::{fmt, mem};
+
+/// Error produced by [`Module::validate`], summarizing (as a human-readable
+/// `message`) one of the errors produced by the more specific checks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    pub category: ValidationCategory,
+    pub message: String,
+}
+
+/// Which check (used by [`Module::validate`]) produced a [`ValidationError`],
+/// with the order of the variants being the order the checks are run in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ValidationCategory {
+    /// See [`check_references`] (and [`ReferenceError`]).
+    References,
+
+    /// See [`Module::validate_calls`] (and [`CallError`]).
+    Calls,
+
+    /// See [`legalize::check_definition_order`] (and [`legalize::DefinitionCycle`]).
+    DefinitionOrder,
+
+    /// Declared capabilities depending on undeclared ones (only checked with
+    /// [`ValidationOptions::require_capability_dependencies`], see also
+    /// [`spv::Dialect::missing_capability_dependencies`]).
+    CapabilityDependencies,
+
+    /// Instructions not part of the SPIR-V version the module declares
+    /// (see [`version::set_version`], and [`version::VersionError`]).
+    Version,
+}
+
+impl fmt::Display for ValidationCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::References => "references",
+            Self::Calls => "calls",
+            Self::DefinitionOrder => "definition order",
+            Self::CapabilityDependencies => "capability dependencies",
+            Self::Version => "version",
+        })
+    }
+}
+
+/// Options for [`Module::validate_with`].
+#[derive(Clone, Default)]
+pub struct ValidationOptions {
+    /// Whether to require every capability that other declared capabilities
+    /// depend on to also be declared (like
+    /// [`LowerOptions::require_capability_dependencies`]), reporting any missing
+    /// ones as [`ValidationCategory::CapabilityDependencies`] errors.
+    ///
+    /// [`LowerOptions::require_capability_dependencies`]: crate::spv::lower::LowerOptions::require_capability_dependencies
+    pub require_capability_dependencies: bool,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.category, self.message)
+    }
+}
+
+/// Error produced by [`Module::validate_calls`], for a call which doesn't
+/// match the signature (i.e. parameters and return type) of its callee.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct CallError {
+    /// The function containing the call.
+    pub caller: Func,
+
+    /// The call itself (within `caller`), i.e. a [`DataInstKind::FuncCall`].
+    pub call_inst: DataInst,
+
+    pub callee: Func,
+
+    pub kind: CallErrorKind,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum CallErrorKind {
+    ArityMismatch { expected: usize, found: usize },
+    ArgTypeMismatch { arg_idx: usize, expected: Type, found: Type },
+    ReturnTypeMismatch { expected: Type, found: Option<Type> },
+}
+
+/// Error produced by [`Module::validate_references`], for a reference to a
+/// [`GlobalVar`] or [`Func`] which isn't defined in the module (e.g. because
+/// it was copied from another module, without also copying its definition),
+/// or to a [`Value`] which isn't defined in the function using it (i.e. the
+/// [`DataInst`], [`ControlNode`] output, or region input, it refers to).
+#[derive(Clone, PartialEq, Eq)]
+pub enum ReferenceError {
+    UndefinedGlobalVar { referrer: Referrer, gv: GlobalVar },
+    UndefinedFunc { referrer: Referrer, func: Func },
+    UndefinedValue { referrer: Referrer, value: Value },
+}
+
+/// The first place (found by [`Module::validate_references`]) referring to
+/// an undefined [`GlobalVar`] or [`Func`] (or any place using an undefined [`Value`]).
+#[derive(Clone, PartialEq, Eq)]
+pub enum Referrer {
+    Export(ExportKey),
+    GlobalVar(GlobalVar),
+
+    /// The function `func`, and specifically the instruction `inst` in its
+    /// body (if the reference isn't in e.g. the function's signature).
+    Func {
+        func: Func,
+        inst: Option<DataInst>,
+    },
+}
+
+/// Check that every [`GlobalVar`] and [`Func`] (reachable from exports) is
+/// defined in the module, and that every [`Value`] (in a function body) is
+/// defined in that function, returning all the errors (if any), each with
+/// where the undefined entity or value was referenced (see [`Referrer`]).
+///
+/// This is [`Module::validate_references`], but summarizing its errors
+/// (as [`ValidationError`]s, like [`Module::validate`]).
+pub fn check_references(module: &Module) -> Result<(), Vec<ValidationError>> {
+    let errors = match module.validate_references() {
+        Ok(()) => return Ok(()),
+        Err(errors) => errors,
+    };
+    Err(errors
+        .into_iter()
+        .map(|e| {
+            let (entity, referrer) = match e {
+                ReferenceError::UndefinedGlobalVar { referrer, gv: _ } => {
+                    ("global variable", referrer)
+                }
+                ReferenceError::UndefinedFunc { referrer, func: _ } => ("function", referrer),
+                ReferenceError::UndefinedValue { referrer, value: _ } => ("value", referrer),
+            };
+            let referrer = match referrer {
+                Referrer::Export(_) => "an export",
+                Referrer::GlobalVar(_) => "a global variable",
+                Referrer::Func { inst: None, .. } => "a function declaration",
+                Referrer::Func { inst: Some(_), .. } => "an instruction in a function body",
+            };
+            ValidationError {
+                category: ValidationCategory::References,
+                message: format!("undefined {entity} referenced by {referrer}"),
+            }
+        })
+        .collect())
+}
+
+impl Module {
+    /// Run all the checks available for a whole module (other than those only
+    /// enabled through [`Module::validate_with`]), returning all of their
+    /// errors (if any), grouped by [`ValidationCategory`] (in its order), and
+    /// otherwise in the order each check found them in.
+    ///
+    /// If any references are invalid (see [`check_references`]), only
+    /// those errors are returned, as the other checks assume they're valid.
+    //
+    // FIXME: also check the immediates of all SPIR-V instructions
+    // (see `spv::validate::check_inst_imms`).
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        self.validate_with(&ValidationOptions::default())
+    }
+
+    /// Like [`Module::validate`], but with some additional checks enabled by `options`.
+    pub fn validate_with(&self, options: &ValidationOptions) -> Result<(), Vec<ValidationError>> {
+        let error = |category, message| ValidationError { category, message };
+
+        check_references(self)?;
+
+        let mut errors = vec![];
+
+        let call_errors = self.validate_calls().err().into_iter().flatten();
+        errors.extend(call_errors.map(|e| {
+            let message = match e.kind {
+                CallErrorKind::ArityMismatch { expected, found } => {
+                    format!("call passes {found} arguments, but callee has {expected} parameters")
+                }
+                CallErrorKind::ArgTypeMismatch { arg_idx, .. } => {
+                    format!("call argument #{arg_idx} doesn't match callee's parameter type")
+                }
+                CallErrorKind::ReturnTypeMismatch { .. } => {
+                    "call output type doesn't match callee's return type".to_string()
+                }
+            };
+            error(ValidationCategory::Calls, message)
+        }));
+
+        if let Err(cycle) = legalize::check_definition_order(self) {
+            let message = format!(
+                "initializers of {} global variable(s) form a cycle",
+                cycle.global_vars.len()
+            );
+            errors.push(error(ValidationCategory::DefinitionOrder, message));
+        }
+
+        let ModuleDialect::Spv(dialect) = &self.dialect;
+        if options.require_capability_dependencies {
+            let name = |cap| {
+                spv::CapabilityDiff::capability_name(cap)
+                    .map_or_else(|| cap.to_string(), |name| name.to_string())
+            };
+            errors.extend(dialect.missing_capability_dependencies().map(|(implied_by, needed)| {
+                let message = format!(
+                    "capability {} requires capability {} to also be declared",
+                    name(implied_by),
+                    name(needed)
+                );
+                error(ValidationCategory::CapabilityDependencies, message)
+            }));
+        }
+
+        let version_errors = version::check_version(self, dialect.version()).err();
+        errors.extend(
+            version_errors
+                .into_iter()
+                .flatten()
+                .map(|e| error(ValidationCategory::Version, e.to_string())),
+        );
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Check that every [`GlobalVar`] and [`Func`] (reachable from exports)
+    /// is defined in `self.global_vars`/`self.funcs`, returning all of the
+    /// undefined ones (if any), along with where they were first referenced,
+    /// and also that every [`Value`] used in a function is defined in it.
+    pub fn validate_references(&self) -> Result<(), Vec<ReferenceError>> {
+        let mut checker = ReferenceChecker {
+            cx: &self.cx(),
+            module: self,
+
+            referrer: None,
+            func_def_body: None,
+            errors: vec![],
+
+            seen_attr_sets: FxHashSet::default(),
+            seen_types: FxHashSet::default(),
+            seen_consts: FxHashSet::default(),
+            seen_data_inst_forms: FxHashSet::default(),
+            seen_global_vars: FxHashSet::default(),
+            seen_funcs: FxHashSet::default(),
+        };
+        for (export_key, exportee) in &self.exports {
+            checker.referrer = Some(Referrer::Export(export_key.clone()));
+            export_key.inner_visit_with(&mut checker);
+            exportee.inner_visit_with(&mut checker);
+        }
+
+        if checker.errors.is_empty() { Ok(()) } else { Err(checker.errors) }
+    }
+
+    /// Check every call (in functions reachable from exports) against the
+    /// signature of its callee, returning all the mismatches found (if any).
+    pub fn validate_calls(&self) -> Result<(), Vec<CallError>> {
+        let cx = &self.cx();
+
+        // FIXME: reuse this collection work in some kind of "pass manager".
+        let mut collector = ReachableUseCollector::new(self);
+        collector.visit_module_exports();
+
+        let mut call_checker = CallChecker { cx, module: self, caller: None, errors: vec![] };
+        for &func in &collector.seen_funcs {
+            if let DeclDef::Present(func_def_body) = &self.funcs[func].def {
+                call_checker.caller = Some(func);
+                func_def_body.inner_visit_with(&mut call_checker);
+            }
+        }
+
+        if call_checker.errors.is_empty() { Ok(()) } else { Err(call_checker.errors) }
+    }
+}
+
+struct CallChecker<'a> {
+    cx: &'a Context,
+    module: &'a Module,
+
+    /// The function whose body is currently being visited.
+    caller: Option<Func>,
+
+    errors: Vec<CallError>,
+}
+
+impl CallChecker<'_> {
+    fn check_call(&mut self, func_at_inst: FuncAt<'_, DataInst>) {
+        let cx = self.cx;
+
+        let inst_def = func_at_inst.def();
+        let form_def = &cx[inst_def.form];
+        let callee = match form_def.kind {
+            DataInstKind::FuncCall(callee) => callee,
+            _ => return,
+        };
+        let callee_decl = &self.module.funcs[callee];
+
+        let mut error = |kind| {
+            self.errors.push(CallError {
+                caller: self.caller.unwrap(),
+                call_inst: func_at_inst.position,
+                callee,
+                kind,
+            });
+        };
+
+        if inst_def.inputs.len() != callee_decl.params.len() {
+            error(CallErrorKind::ArityMismatch {
+                expected: callee_decl.params.len(),
+                found: inst_def.inputs.len(),
+            });
+        }
+        for (arg_idx, (&arg, param)) in inst_def.inputs.iter().zip(&callee_decl.params).enumerate()
+        {
+            let arg_type = func_at_inst.at(arg).type_of(cx);
+            if arg_type != param.ty {
+                error(CallErrorKind::ArgTypeMismatch {
+                    arg_idx,
+                    expected: param.ty,
+                    found: arg_type,
+                });
+            }
+        }
+
+        if form_def.output_type != Some(callee_decl.ret_type) {
+            error(CallErrorKind::ReturnTypeMismatch {
+                expected: callee_decl.ret_type,
+                found: form_def.output_type,
+            });
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for CallChecker<'_> {
+    // NOTE: uses of interned/module-stored definitions are irrelevant
+    // here, as only the instructions in a single function body are inspected.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, _data_inst_form: DataInstForm) {}
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+
+    fn visit_control_node_def(&mut self, func_at_control_node: FuncAt<'a, ControlNode>) {
+        if let ControlNodeKind::Block { insts } = func_at_control_node.def().kind {
+            for func_at_inst in func_at_control_node.at(insts) {
+                self.check_call(func_at_inst);
+            }
+        }
+        func_at_control_node.inner_visit_with(self);
+    }
+}
+
+struct ReferenceChecker<'a> {
+    cx: &'a Context,
+    module: &'a Module,
+
+    /// The definition currently being visited (only `None` before visiting
+    /// the first export).
+    referrer: Option<Referrer>,
+
+    /// The body of the function currently being visited (if any), which all
+    /// the [`Value`]s being visited must be defined in.
+    func_def_body: Option<&'a FuncDefBody>,
+
+    errors: Vec<ReferenceError>,
+
+    // FIXME: build some automation to avoid ever repeating these.
+    seen_attr_sets: FxHashSet<AttrSet>,
+    seen_types: FxHashSet<Type>,
+    seen_consts: FxHashSet<Const>,
+    seen_data_inst_forms: FxHashSet<DataInstForm>,
+    seen_global_vars: FxHashSet<GlobalVar>,
+    seen_funcs: FxHashSet<Func>,
+}
+
+impl<'a> Visitor<'a> for ReferenceChecker<'a> {
+    // FIXME: build some automation to avoid ever repeating these.
+    fn visit_attr_set_use(&mut self, attrs: AttrSet) {
+        // NOTE: `AttrSet`s can't be ignored here, as they can refer to
+        // `Const`s (which can refer to `GlobalVar`s).
+        if self.seen_attr_sets.insert(attrs) {
+            self.visit_attr_set_def(&self.cx[attrs]);
+        }
+    }
+    fn visit_type_use(&mut self, ty: Type) {
+        if self.seen_types.insert(ty) {
+            self.visit_type_def(&self.cx[ty]);
+        }
+    }
+    fn visit_const_use(&mut self, ct: Const) {
+        if self.seen_consts.insert(ct) {
+            self.visit_const_def(&self.cx[ct]);
+        }
+    }
+    fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
+        if self.seen_data_inst_forms.insert(data_inst_form) {
+            self.visit_data_inst_form_def(&self.cx[data_inst_form]);
+        }
+    }
+
+    fn visit_global_var_use(&mut self, gv: GlobalVar) {
+        if !self.seen_global_vars.insert(gv) {
+            return;
+        }
+        match self.module.global_vars.get(gv) {
+            Some(gv_decl) => {
+                let outer_referrer = self.referrer.replace(Referrer::GlobalVar(gv));
+                self.visit_global_var_decl(gv_decl);
+                self.referrer = outer_referrer;
+            }
+            None => self.errors.push(ReferenceError::UndefinedGlobalVar {
+                referrer: self.referrer.clone().unwrap(),
+                gv,
+            }),
+        }
+    }
+    fn visit_func_use(&mut self, func: Func) {
+        if !self.seen_funcs.insert(func) {
+            return;
+        }
+        match self.module.funcs.get(func) {
+            Some(func_decl) => {
+                let outer_referrer = self.referrer.replace(Referrer::Func { func, inst: None });
+                let outer_func_def_body = mem::replace(
+                    &mut self.func_def_body,
+                    match &func_decl.def {
+                        DeclDef::Imported(_) => None,
+                        DeclDef::Present(func_def_body) => Some(func_def_body),
+                    },
+                );
+                self.visit_func_decl(func_decl);
+                self.referrer = outer_referrer;
+                self.func_def_body = outer_func_def_body;
+            }
+            None => self.errors.push(ReferenceError::UndefinedFunc {
+                referrer: self.referrer.clone().unwrap(),
+                func,
+            }),
+        }
+    }
+
+    fn visit_control_node_def(&mut self, func_at_control_node: FuncAt<'a, ControlNode>) {
+        let insts = match func_at_control_node.def().kind {
+            ControlNodeKind::Block { insts } => insts,
+            _ => return func_at_control_node.inner_visit_with(self),
+        };
+
+        // NOTE: this replicates `FuncAt<ControlNode>::inner_visit_with`,
+        // but tracking the current instruction (to use it in `Referrer::Func`).
+        for func_at_inst in func_at_control_node.at(insts) {
+            if let Some(Referrer::Func { inst, .. }) = &mut self.referrer {
+                *inst = Some(func_at_inst.position);
+            }
+            self.visit_data_inst_def(func_at_inst.def());
+        }
+        if let Some(Referrer::Func { inst, .. }) = &mut self.referrer {
+            *inst = None;
+        }
+        for output in &func_at_control_node.def().outputs {
+            output.inner_visit_with(self);
+        }
+    }
+
+    fn visit_value_use(&mut self, v: &'a Value) {
+        if let Some(func_def_body) = self.func_def_body {
+            let is_defined = match *v {
+                Value::Const(_) => true,
+                Value::ControlRegionInput { region, input_idx } => func_def_body
+                    .control_regions
+                    .get(region)
+                    .is_some_and(|region_def| (input_idx as usize) < region_def.inputs.len()),
+                Value::ControlNodeOutput { control_node, output_idx } => func_def_body
+                    .control_nodes
+                    .get(control_node)
+                    .is_some_and(|node_def| (output_idx as usize) < node_def.outputs.len()),
+                Value::DataInstOutput(inst) => func_def_body.data_insts.get(inst).is_some(),
+            };
+            if !is_defined {
+                self.errors.push(ReferenceError::UndefinedValue {
+                    referrer: self.referrer.clone().unwrap(),
+                    value: *v,
+                });
+            }
+        }
+        v.inner_visit_with(self);
+    }
+}
+
+/// Module exporting a function which calls a function with one `i32` parameter
+/// (taking the arguments to pass, e.g. `" %int_one"`, as `call_args`).
+#[cfg(test)]
+fn lower_call_module(call_args: &str) -> Module {
+    lower_call_module_in(std::rc::Rc::new(Context::new()), call_args)
+}
+
+#[cfg(test)]
+fn lower_call_module_in(cx: std::rc::Rc<Context>, call_args: &str) -> Module {
+    let spv_words = crate::spv::test_asm::assemble(&format!(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpDecorate %caller LinkageAttributes "caller" Export
+        %void = OpTypeVoid
+        %int = OpTypeInt 32 1
+        %float = OpTypeFloat 32
+        %int_one = OpConstant %int 1
+        %float_one = OpConstant %float 0x3f800000
+        %callee_type = OpTypeFunction %void %int
+        %caller_type = OpTypeFunction %void
+        %callee = OpFunction %void None %callee_type
+        %param = OpFunctionParameter %int
+        %callee_entry = OpLabel
+        OpReturn
+        OpFunctionEnd
+        %caller = OpFunction %void None %caller_type
+        %caller_entry = OpLabel
+        %call = OpFunctionCall %void %callee{call_args}
+        OpReturn
+        OpFunctionEnd
+        "#
+    ));
+    Module::lower_from_spv_words(cx, &spv_words).unwrap()
+}
+
+#[test]
+fn validate_valid_call() {
+    let module = lower_call_module(" %int_one");
+    assert!(module.validate_calls().is_ok());
+    assert!(module.validate().is_ok());
+}
+
+#[test]
+fn validate_call_arity_mismatch() {
+    let module = lower_call_module("");
+    let errors = module.validate_calls().err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].kind == CallErrorKind::ArityMismatch { expected: 1, found: 0 });
+
+    // The error is reported with the location of the call.
+    let caller = match module.exports.values().next() {
+        Some(&crate::Exportee::Func(func)) => func,
+        _ => unreachable!(),
+    };
+    assert!(errors[0].caller == caller);
+    let DeclDef::Present(caller_body) = &module.funcs[caller].def else {
+        unreachable!();
+    };
+    let cx = module.cx();
+    let call_inst_def = caller_body.at(errors[0].call_inst).def();
+    assert!(cx[call_inst_def.form].kind == DataInstKind::FuncCall(errors[0].callee));
+
+    let errors = module.validate().err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].category, ValidationCategory::Calls);
+}
+
+#[test]
+fn validate_call_arg_type_mismatch() {
+    let module = lower_call_module(" %float_one");
+    let errors = module.validate_calls().err().unwrap();
+    assert_eq!(errors.len(), 1);
+    match errors[0].kind {
+        CallErrorKind::ArgTypeMismatch { arg_idx, expected, found } => {
+            let cx = module.cx();
+            assert_eq!(arg_idx, 0);
+            assert!(cx[expected].kind != cx[found].kind);
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn validate_aggregates_errors_in_order() {
+    // NOTE: `Shader` depends on `Matrix`, which isn't declared (but that's only
+    // an error if explicitly requested), while `Linkage` has no dependencies.
+    let module = lower_call_module("");
+    let options = ValidationOptions { require_capability_dependencies: true };
+    let errors = module.validate_with(&options).err().unwrap();
+    let categories: Vec<_> = errors.iter().map(|e| e.category).collect();
+    assert_eq!(categories, [ValidationCategory::Calls, ValidationCategory::CapabilityDependencies]);
+    assert_eq!(
+        errors[1].message,
+        "capability Shader requires capability Matrix to also be declared"
+    );
+
+    assert!(module.validate().err().unwrap() == errors[..1]);
+}
+
+#[test]
+fn check_references_in_function_bodies() {
+    let module = lower_call_module(" %int_one");
+    assert!(check_references(&module).is_ok());
+
+    // Make the call in one module refer to the callee (and call result) of
+    // another module (sharing the same `Context`), neither defined in the first.
+    let cx = module.cx();
+    let other_module = lower_call_module_in(cx.clone(), " %int_one");
+    let find_call = |module: &Module| {
+        let caller = match module.exports.values().next() {
+            Some(&crate::Exportee::Func(func)) => func,
+            _ => unreachable!(),
+        };
+        let DeclDef::Present(caller_body) = &module.funcs[caller].def else {
+            unreachable!();
+        };
+        let call_inst = caller_body
+            .at_body()
+            .at_children()
+            .into_iter()
+            .flat_map(|func_at_node| match &func_at_node.def().kind {
+                ControlNodeKind::Block { insts } => func_at_node.at(*insts).into_iter(),
+                _ => unreachable!(),
+            })
+            .find(|func_at_inst| {
+                matches!(cx[func_at_inst.def().form].kind, DataInstKind::FuncCall(_))
+            })
+            .unwrap();
+        (caller, call_inst.position, call_inst.def().form)
+    };
+    let (_, other_call_inst, other_call_form) = find_call(&other_module);
+
+    let mut module = module;
+    let (caller, call_inst, _) = find_call(&module);
+    let DeclDef::Present(caller_body) = &mut module.funcs[caller].def else {
+        unreachable!();
+    };
+    let call_inst_def = &mut caller_body.data_insts[call_inst];
+    call_inst_def.form = other_call_form;
+    call_inst_def.inputs[0] = Value::DataInstOutput(other_call_inst);
+
+    // Both errors are reported, with the instruction referring to them.
+    let errors = module.validate_references().err().unwrap();
+    assert_eq!(errors.len(), 2);
+    for e in &errors {
+        let referrer = match e {
+            ReferenceError::UndefinedFunc { referrer, .. }
+            | ReferenceError::UndefinedValue { referrer, .. } => referrer,
+            ReferenceError::UndefinedGlobalVar { .. } => unreachable!(),
+        };
+        assert!(*referrer == Referrer::Func { func: caller, inst: Some(call_inst) });
+    }
+
+    let errors = check_references(&module).err().unwrap();
+    let messages: Vec<_> = errors.iter().map(|e| &e.message[..]).collect();
+    assert_eq!(
+        messages,
+        [
+            "undefined function referenced by an instruction in a function body",
+            "undefined value referenced by an instruction in a function body",
+        ]
+    );
+    assert!(errors.iter().all(|e| e.category == ValidationCategory::References));
+    assert!(module.validate().err().unwrap() == errors);
+}
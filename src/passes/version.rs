@@ -0,0 +1,227 @@
+//! SPIR-V version changes (i.e. upgrading/downgrading the dialect of a module).
+
+use crate::func_at::FuncAt;
+use crate::visit::{InnerVisit, ReachableUseCollector, Visitor};
+use crate::{
+    cfg, spv, Attr, AttrSet, Const, ConstKind, ControlNode, ControlNodeKind, DataInstForm,
+    DataInstKind, DeclDef, Func, FuncDecl, FxIndexSet, GlobalVar, Module, ModuleDialect,
+    SelectionKind, Type, TypeKind,
+};
+use std::fmt;
+
+/// Error produced by [`set_version`], for an instruction (used by the module)
+/// which isn't part of the "core" spec of the target SPIR-V version.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum VersionError {
+    /// The instruction was only introduced in a later SPIR-V version.
+    TooNew { opcode: spv::spec::Opcode, version: spv::SpvVersion },
+
+    /// The instruction was removed after an earlier SPIR-V version.
+    Removed { opcode: spv::spec::Opcode, last_version: spv::SpvVersion },
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::TooNew { opcode, version } => {
+                write!(f, "{} requires SPIR-V {version}", opcode.name())
+            }
+            Self::Removed { opcode, last_version } => {
+                write!(f, "{} was removed after SPIR-V {last_version}", opcode.name())
+            }
+        }
+    }
+}
+
+// HACK: `spv::spec::Opcode` isn't `Debug`, and the message is more useful anyway.
+impl fmt::Debug for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Change the SPIR-V version of `module` to `version`, after checking that
+/// every instruction used by `module` (and reachable from its exports) is
+/// part of the "core" spec of `version`, and returning all the instructions
+/// which aren't (if any), in which case `module` is left unchanged.
+///
+/// Instructions only available through extensions (i.e. not in any version of
+/// the "core" spec) are always allowed, as they depend on `OpExtension`s instead.
+//
+// FIXME: also check operands (e.g. decorations, or bitflags), whose
+// enumerants also have version information in the SPIR-V grammar.
+pub fn set_version(module: &mut Module, version: spv::SpvVersion) -> Result<(), Vec<VersionError>> {
+    let ModuleDialect::Spv(dialect) = &module.dialect;
+    if dialect.version() != version {
+        check_version(module, version)?;
+    }
+
+    let ModuleDialect::Spv(dialect) = &mut module.dialect;
+    let spv::SpvVersion(major, minor) = version;
+    dialect.version_major = major;
+    dialect.version_minor = minor;
+
+    Ok(())
+}
+
+pub(crate) fn check_version(
+    module: &Module,
+    version: spv::SpvVersion,
+) -> Result<(), Vec<VersionError>> {
+    let cx = &module.cx();
+
+    // NOTE: `AttrSet`s can't be ignored here, as annotations
+    // (i.e. decorations and execution modes) are SPIR-V instructions too.
+    let mut reachable = ReachableUseCollector::new(module);
+    reachable.visit_attr_sets = true;
+    reachable.visit_module_exports();
+
+    let mut collector = OpcodeCollector { opcodes: FxIndexSet::default() };
+    for &attrs in &reachable.seen_attr_sets {
+        for attr in &cx[attrs].attrs {
+            match attr {
+                Attr::SpvAnnotation(spv_inst)
+                | Attr::SpvAnnotationWithConstInputs { spv_inst, .. } => {
+                    collector.opcodes.insert(spv_inst.opcode);
+                }
+                _ => {}
+            }
+        }
+    }
+    for &ty in &reachable.seen_types {
+        if let TypeKind::SpvInst { spv_inst, .. } = &cx[ty].kind {
+            collector.opcodes.insert(spv_inst.opcode);
+        }
+    }
+    for &ct in &reachable.seen_consts {
+        if let ConstKind::SpvInst { spv_inst_and_const_inputs } = &cx[ct].kind {
+            collector.opcodes.insert(spv_inst_and_const_inputs.0.opcode);
+        }
+    }
+    for &data_inst_form in &reachable.seen_data_inst_forms {
+        if let DataInstKind::SpvInst(spv_inst) = &cx[data_inst_form].kind {
+            collector.opcodes.insert(spv_inst.opcode);
+        }
+    }
+    for &func in &reachable.seen_funcs {
+        collector.visit_func_decl(&module.funcs[func]);
+    }
+
+    let errors: Vec<_> = collector
+        .opcodes
+        .into_iter()
+        .filter_map(|opcode| {
+            let def = opcode.def();
+            match (def.version, def.last_version) {
+                (Some(introduced), _) if introduced > version => {
+                    Some(VersionError::TooNew { opcode, version: introduced })
+                }
+                (_, Some(last_version)) if last_version < version => {
+                    Some(VersionError::Removed { opcode, last_version })
+                }
+                _ => None,
+            }
+        })
+        .collect();
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Collects the opcodes of the SPIR-V instructions in function bodies which
+/// aren't definitions of their own (i.e. control-flow instructions), as all the
+/// other opcodes can be found through [`ReachableUseCollector`] instead.
+struct OpcodeCollector {
+    /// All the opcodes used, in the order they were first found in.
+    opcodes: FxIndexSet<spv::spec::Opcode>,
+}
+
+impl<'a> Visitor<'a> for OpcodeCollector {
+    // NOTE: uses of interned/module-stored definitions are irrelevant
+    // here, as only the instructions in a single function body are inspected.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, _data_inst_form: DataInstForm) {}
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+
+    fn visit_func_decl(&mut self, func_decl: &'a FuncDecl) {
+        // NOTE: `cfg::ControlInst`s have no `Visitor` method of their own.
+        if let DeclDef::Present(func_def_body) = &func_decl.def {
+            if let Some(cfg) = &func_def_body.unstructured_cfg {
+                for region in cfg.rev_post_order(func_def_body) {
+                    let control_inst_kind =
+                        cfg.control_inst_on_exit_from.get(region).map(|inst| &inst.kind);
+                    if let Some(
+                        cfg::ControlInstKind::ExitInvocation(cfg::ExitInvocationKind::SpvInst(
+                            spv_inst,
+                        ))
+                        | cfg::ControlInstKind::SelectBranch(SelectionKind::SpvInst(spv_inst)),
+                    ) = control_inst_kind
+                    {
+                        self.opcodes.insert(spv_inst.opcode);
+                    }
+                }
+            }
+        }
+        func_decl.inner_visit_with(self);
+    }
+    fn visit_control_node_def(&mut self, func_at_control_node: FuncAt<'a, ControlNode>) {
+        if let ControlNodeKind::Select { kind: SelectionKind::SpvInst(spv_inst), .. } =
+            &func_at_control_node.def().kind
+        {
+            self.opcodes.insert(spv_inst.opcode);
+        }
+        func_at_control_node.inner_visit_with(self);
+    }
+}
+
+/// SPIR-V 1.4 module exporting a variable decorated with `OpDecorateString`
+/// (which was introduced in SPIR-V 1.4).
+#[cfg(test)]
+fn lower_decorate_string_module() -> Module {
+    let mut spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpDecorate %var LinkageAttributes "var" Export
+        OpDecorateString %var UserSemantic "foo"
+        %uint = OpTypeInt 32 0
+        %uint_ptr = OpTypePointer Private %uint
+        %var = OpVariable %uint_ptr Private
+        "#,
+    );
+    spv_words[1] = 0x0001_0400;
+    Module::lower_from_spv_words(std::rc::Rc::new(crate::Context::new()), &spv_words).unwrap()
+}
+
+#[test]
+fn set_version_rejects_too_new_instructions() {
+    let wk = &spv::spec::Spec::get().well_known;
+
+    let mut module = lower_decorate_string_module();
+    let before = module.clone();
+    let errors = set_version(&mut module, spv::SpvVersion(1, 3)).unwrap_err();
+    assert!(
+        errors
+            == [VersionError::TooNew {
+                opcode: wk.OpDecorateString,
+                version: spv::SpvVersion(1, 4)
+            }]
+    );
+    assert_eq!(errors[0].to_string(), "OpDecorateString requires SPIR-V 1.4");
+    assert!(module.structurally_eq(&before));
+}
+
+#[test]
+fn set_version_upgrades() {
+    let mut module = lower_decorate_string_module();
+    set_version(&mut module, spv::SpvVersion(1, 6)).unwrap();
+    let ModuleDialect::Spv(dialect) = &module.dialect;
+    assert!(dialect.version() == spv::SpvVersion(1, 6));
+
+    // Lifting now emits the new version.
+    let spv_words = module.lift_to_spv_words().unwrap();
+    assert_eq!(spv_words[1], 0x0001_0600);
+}
@@ -40,6 +40,7 @@ use std::borrow::Cow;
 use std::collections::hash_map::Entry;
 use std::fmt::{self, Write as _};
 use std::hash::Hash;
+use std::iter;
 use std::mem;
 
 mod multiversion;
@@ -636,6 +637,131 @@ impl Plan<'_> {
     }
 }
 
+/// Plain text pretty-printing of a whole [`Module`], shorthand for
+/// `Plan::for_module(module).pretty_print()` (see also the [module-level docs](self)).
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Plan::for_module(self).pretty_print().fmt(f)
+    }
+}
+
+/// First divergence between the pretty-printed forms of two [`Module`]s,
+/// as found by [`Module::diff`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleDiff {
+    /// Line number (1-based) in the pretty-printed forms, where they diverge.
+    pub line: usize,
+
+    /// The closest preceding top-level (i.e. unindented) line, shared by both
+    /// pretty-printed forms, if any (e.g. the start of a function definition).
+    pub context: Option<String>,
+
+    /// The diverging line in each module, or `None` if that module's
+    /// pretty-printed form ended before `line`, while the other's didn't.
+    pub lines: (Option<String>, Option<String>),
+}
+
+impl fmt::Display for ModuleDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { line, context, lines: (a, b) } = self;
+        write!(f, "modules diverge at line {line}")?;
+        if let Some(context) = context {
+            write!(f, " (in `{}`)", context.trim())?;
+        }
+        let show = |line: &Option<String>| match line {
+            Some(line) => format!("`{}`", line.trim()),
+            None => "end of module".to_string(),
+        };
+        write!(f, ": {} vs {}", show(a), show(b))
+    }
+}
+
+impl Module {
+    /// Compare `self` and `other` semantically, i.e. ignoring the identity of
+    /// interned values and entities (which can't be compared across modules,
+    /// especially when using different [`Context`]s), see [`Module::diff`].
+    pub fn structurally_eq(&self, other: &Module) -> bool {
+        self.diff(other).is_none()
+    }
+
+    /// Find the first divergence (if any) between `self` and `other`, treating
+    /// them as equal iff they pretty-print identically.
+    ///
+    /// This works because pretty-printing only includes what's reachable from
+    /// the exports of each module, and names (anonymous) definitions based on
+    /// the order they're first used in, not their (interning/entity) identity.
+    //
+    // FIXME: compare the IR directly, without allocating whole `String`s.
+    pub fn diff(&self, other: &Module) -> Option<ModuleDiff> {
+        let [a, b] = [self, other].map(|module| module.to_string());
+        ModuleDiff::between(&a, &b)
+    }
+}
+
+impl ModuleDiff {
+    /// Find the first divergence between two pretty-printed forms `a` and `b`.
+    fn between(a: &str, b: &str) -> Option<ModuleDiff> {
+        if a == b {
+            return None;
+        }
+
+        let mut context = None;
+        let (mut a_lines, mut b_lines) = (a.lines(), b.lines());
+        for line in 1.. {
+            match (a_lines.next(), b_lines.next()) {
+                (Some(a_line), Some(b_line)) if a_line == b_line => {
+                    if !a_line.is_empty() && !a_line.starts_with(char::is_whitespace) {
+                        context = Some(a_line);
+                    }
+                }
+                (a_line, b_line) => {
+                    return Some(ModuleDiff {
+                        line,
+                        context: context.map(|s| s.to_string()),
+                        lines: (a_line.map(|s| s.to_string()), b_line.map(|s| s.to_string())),
+                    });
+                }
+            }
+        }
+        unreachable!()
+    }
+}
+
+/// Check that the pretty-printed form of `module` matches the snapshot file
+/// at `path` (relative to the crate root, e.g. `tests/snapshots/foo.spirt`).
+///
+/// If the `SPIRT_BLESS_SNAPSHOTS` environment variable is set, the snapshot
+/// file is (over)written instead (and the check passes), which is also the only
+/// way to create it (i.e. a missing snapshot file is an error, not a new one).
+///
+/// This relies on pretty-printing being deterministic (see [`Module::diff`]).
+#[cfg(test)]
+#[track_caller]
+pub(crate) fn assert_module_snapshot(module: &Module, path: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+    let printed = module.to_string();
+
+    if std::env::var_os("SPIRT_BLESS_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, printed).unwrap();
+        return;
+    }
+
+    let snapshot = match std::fs::read_to_string(&path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => panic!(
+            "failed to read {} (rerun with `SPIRT_BLESS_SNAPSHOTS=1` to create it): {e}",
+            path.display()
+        ),
+    };
+    if let Some(diff) = ModuleDiff::between(&snapshot, &printed) {
+        panic!(
+            "{} doesn't match (rerun with `SPIRT_BLESS_SNAPSHOTS=1` to update it): {diff}",
+            path.display()
+        );
+    }
+}
+
 pub struct Printer<'a> {
     cx: &'a Context,
     use_styles: FxIndexMap<Use, UseStyle>,
@@ -1887,6 +2013,8 @@ impl Print for spv::ModuleDebugInfo {
     fn print(&self, printer: &Printer<'_>) -> pretty::Fragment {
         let Self {
             original_generator_magic,
+            // NOTE: this is only meaningful wrt the original module's IDs.
+            original_id_bound: _,
             source_languages,
             source_extensions,
             module_processes,
@@ -2263,6 +2391,14 @@ impl Print for Attr {
                     printer.pretty_spv_inst(printer.attr_style(), *opcode, imms, [None])
                 }
             }
+            Attr::SpvAnnotationWithConstInputs { spv_inst, const_inputs } => printer
+                .pretty_spv_inst(
+                    printer.attr_style(),
+                    spv_inst.opcode,
+                    &spv_inst.imms,
+                    // NOTE: the first ID operand is the annotation target.
+                    iter::once(None).chain(const_inputs.0.iter().map(|ct| Some(ct.print(printer)))),
+                ),
             &Attr::SpvDebugLine { file_path, line, col } => {
                 // HACK(eddyb) Rust-GPU's column numbers seem
                 // off-by-one wrt what e.g. VSCode expects
@@ -3596,3 +3732,54 @@ impl Print for Value {
         Use::from(*self).print(printer)
     }
 }
+
+/// Lower a module exporting a function (as `"foo"`) returning `value`.
+#[cfg(test)]
+fn lower_export_returning_module(cx: std::rc::Rc<crate::Context>, value: u32) -> Module {
+    let spv_words = crate::spv::test_asm::assemble(&format!(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpDecorate %foo LinkageAttributes "foo" Export
+        %int = OpTypeInt 32 0
+        %value = OpConstant %int {value}
+        %func_type = OpTypeFunction %int
+        %foo = OpFunction %int None %func_type
+        %entry = OpLabel
+        OpReturnValue %value
+        OpFunctionEnd
+        "#
+    ));
+    Module::lower_from_spv_words(cx, &spv_words).unwrap()
+}
+
+#[test]
+fn structurally_eq_across_contexts() {
+    let [a, b] =
+        [(); 2].map(|()| lower_export_returning_module(std::rc::Rc::new(crate::Context::new()), 1));
+    assert!(!std::rc::Rc::ptr_eq(&a.cx(), &b.cx()));
+    assert!(a.structurally_eq(&b));
+    assert_eq!(a.diff(&b), None);
+}
+
+#[test]
+fn diff_finds_first_divergence() {
+    let cx = std::rc::Rc::new(crate::Context::new());
+    let a = lower_export_returning_module(cx.clone(), 1);
+    let b = lower_export_returning_module(cx, 2);
+    assert!(!a.structurally_eq(&b));
+    let diff = a.diff(&b).unwrap();
+    assert_eq!(
+        diff,
+        ModuleDiff {
+            line: 6,
+            context: Some("func F0() -> u32 {".into()),
+            lines: (Some("  return 1u32".into()), Some("  return 2u32".into())),
+        }
+    );
+    assert_eq!(
+        diff.to_string(),
+        "modules diverge at line 6 (in `func F0() -> u32 {`): `return 1u32` vs `return 2u32`"
+    );
+}
@@ -0,0 +1,161 @@
+//! Summarizing which parts of a module SPIR-T doesn't (yet) model specifically,
+//! i.e. which were only kept as generic SPIR-V instructions by [`lower`](super::lower).
+
+use crate::visit::{ReachableUseCollector, Visitor};
+use crate::{
+    AttrSet, Const, ConstKind, Context, DataInstForm, DataInstKind, Func, GlobalVar, Module, Type,
+    TypeKind,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Summary (produced by [`Module::lowering_coverage_report`]) of how many of
+/// the types, constants, and instructions (in function bodies) used by a module
+/// are modeled by SPIR-T itself, vs kept as generic SPIR-V instructions
+/// (i.e. [`TypeKind::SpvInst`], [`ConstKind::SpvInst`], [`DataInstKind::SpvInst`]
+/// and [`DataInstKind::SpvExtInst`]).
+///
+/// Types and constants (being interned) are counted once per unique definition,
+/// while instructions are counted once per occurrence in a function body.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// Number of definitions modeled by SPIR-T itself (e.g. function calls,
+    /// pointers to global variables, or anything using [`qptr`](crate::qptr)).
+    pub modeled: usize,
+
+    /// Number of definitions kept as generic SPIR-V instructions, per opcode name.
+    pub spv_insts: BTreeMap<&'static str, usize>,
+
+    /// Number of `OpExtInst`s, per extended instruction set name, and instruction number.
+    pub spv_ext_insts: BTreeMap<(String, u32), usize>,
+}
+
+impl CoverageReport {
+    /// Total number of definitions kept as generic SPIR-V instructions.
+    pub fn generic_count(&self) -> usize {
+        self.spv_insts.values().chain(self.spv_ext_insts.values()).sum()
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "modeled by SPIR-T: {}", self.modeled)?;
+        writeln!(f, "generic SPIR-V instructions: {}", self.generic_count())?;
+
+        // NOTE: most frequent first, so the list is easier to skim.
+        let mut spv_insts: Vec<_> = self.spv_insts.iter().collect();
+        spv_insts.sort_by_key(|&(_, &count)| std::cmp::Reverse(count));
+        for (name, count) in spv_insts {
+            writeln!(f, "  {count:>6} {name}")?;
+        }
+        for ((ext_set, inst), count) in &self.spv_ext_insts {
+            writeln!(f, "  {count:>6} OpExtInst {ext_set:?} {inst}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Module {
+    /// Summarize (see [`CoverageReport`]) everything reachable from exports,
+    /// wrt how much of it is modeled by SPIR-T, vs only kept as generic SPIR-V.
+    pub fn lowering_coverage_report(&self) -> CoverageReport {
+        let cx = &self.cx();
+
+        let mut reachable = ReachableUseCollector::new(self);
+        reachable.visit_module_exports();
+
+        let mut collector = CoverageCollector { cx, report: CoverageReport::default() };
+        for &ty in &reachable.seen_types {
+            match &cx[ty].kind {
+                TypeKind::SpvInst { spv_inst, .. } => collector.count_spv_inst(spv_inst.opcode),
+                _ => collector.report.modeled += 1,
+            }
+        }
+        for &ct in &reachable.seen_consts {
+            match &cx[ct].kind {
+                ConstKind::SpvInst { spv_inst_and_const_inputs } => {
+                    collector.count_spv_inst(spv_inst_and_const_inputs.0.opcode);
+                }
+                _ => collector.report.modeled += 1,
+            }
+        }
+        for &func in &reachable.seen_funcs {
+            collector.visit_func_decl(&self.funcs[func]);
+        }
+        collector.report
+    }
+}
+
+/// Counts the instructions in function bodies (see [`CoverageReport`]), with
+/// the types and constants being counted separately (once per definition).
+struct CoverageCollector<'a> {
+    cx: &'a Context,
+
+    report: CoverageReport,
+}
+
+impl CoverageCollector<'_> {
+    fn count_spv_inst(&mut self, opcode: super::spec::Opcode) {
+        *self.report.spv_insts.entry(opcode.name()).or_default() += 1;
+    }
+}
+
+impl Visitor<'_> for CoverageCollector<'_> {
+    // NOTE: only the instructions in function bodies are counted here,
+    // so the only interesting uses are of `DataInstForm`s.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
+        // NOTE: unlike types and constants, instructions are counted
+        // for every use of their `DataInstForm` (i.e. every `DataInst`).
+        match &self.cx[data_inst_form].kind {
+            DataInstKind::SpvInst(spv_inst) => self.count_spv_inst(spv_inst.opcode),
+            &DataInstKind::SpvExtInst { ext_set, inst } => {
+                let ext_set = self.cx[ext_set].to_string();
+                *self.report.spv_ext_insts.entry((ext_set, inst)).or_default() += 1;
+            }
+            DataInstKind::FuncCall(_) | DataInstKind::QPtr(_) => self.report.modeled += 1,
+        }
+    }
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+}
+
+#[test]
+fn coverage_of_call_and_add() {
+    let spv_words = super::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "main" Export
+            %2 = OpTypeInt 32 0
+            %3 = OpTypeFunction %2 %2
+            %12 = OpTypeFunction %2
+            %8 = OpConstant %2 1
+            %11 = OpConstant %2 5
+            %4 = OpFunction %2 None %3
+            %5 = OpFunctionParameter %2
+            %6 = OpLabel
+            %7 = OpIAdd %2 %5 %8
+            OpReturnValue %7
+            OpFunctionEnd
+            %1 = OpFunction %2 None %12
+            %9 = OpLabel
+            %10 = OpFunctionCall %2 %4 %11
+            OpReturnValue %10
+            OpFunctionEnd
+            "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+    let report = module.lowering_coverage_report();
+
+    // Only the `OpFunctionCall` is modeled by SPIR-T (as `DataInstKind::FuncCall`).
+    assert_eq!(report.modeled, 1);
+    assert_eq!(report.spv_insts.get("OpTypeInt"), Some(&1));
+    assert_eq!(report.spv_insts.get("OpConstant"), Some(&2));
+    assert_eq!(report.spv_insts.get("OpIAdd"), Some(&1));
+    assert!(report.spv_ext_insts.is_empty());
+}
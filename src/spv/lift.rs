@@ -1,20 +1,22 @@
 //! SPIR-T to SPIR-V lifting.
 
 use crate::func_at::FuncAt;
+use crate::passes::canonicalize::ContentHasher;
 use crate::spv::{self, spec};
 use crate::visit::{InnerVisit, Visitor};
 use crate::{
     cfg, AddrSpace, Attr, AttrSet, Const, ConstDef, ConstKind, Context, ControlNode,
     ControlNodeKind, ControlNodeOutputDecl, ControlRegion, ControlRegionInputDecl, DataInst,
     DataInstDef, DataInstForm, DataInstFormDef, DataInstKind, DeclDef, EntityList, ExportKey,
-    Exportee, Func, FuncDecl, FuncParam, FxIndexMap, FxIndexSet, GlobalVar, GlobalVarDefBody,
-    Import, Module, ModuleDebugInfo, ModuleDialect, SelectionKind, Type, TypeDef, TypeKind,
-    TypeOrConst, Value,
+    Exportee, Func, FuncDecl, FuncDefBody, FuncParam, FxIndexMap, FxIndexSet, GlobalVar,
+    GlobalVarDefBody, Import, Module, ModuleDebugInfo, ModuleDialect, SelectionKind, Type,
+    TypeDef, TypeKind, TypeOrConst, Value,
 };
 use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
 use std::borrow::Cow;
-use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
 use std::num::NonZeroU32;
 use std::path::Path;
 use std::{io, iter, mem, slice};
@@ -207,6 +209,7 @@ impl Visitor<'_> for NeedsIdsCollector<'_> {
             Attr::Diagnostics(_)
             | Attr::QPtr(_)
             | Attr::SpvAnnotation { .. }
+            | Attr::SpvAnnotationWithConstInputs { .. }
             | Attr::SpvBitflagsOperand(_) => {}
             Attr::SpvDebugLine { file_path, .. } => {
                 self.debug_strings.insert(&self.cx[file_path.0]);
@@ -349,6 +352,84 @@ enum Merge<L> {
     },
 }
 
+/// Reorder `globals` topologically (i.e. dependencies first), and otherwise by
+/// their content hashes (see [`LiftOptions::canonical_definition_order`]).
+fn sort_globals_canonically(cx: &Context, module: &Module, globals: &mut FxIndexSet<Global>) {
+    let deps_of = |global| -> SmallVec<[Global; 4]> {
+        match global {
+            Global::Type(ty) => match &cx[ty].kind {
+                TypeKind::SpvInst { type_and_const_inputs, .. } => type_and_const_inputs
+                    .iter()
+                    .map(|&ty_or_ct| match ty_or_ct {
+                        TypeOrConst::Type(ty) => Global::Type(ty),
+                        TypeOrConst::Const(ct) => Global::Const(ct),
+                    })
+                    .collect(),
+                TypeKind::QPtr | TypeKind::SpvStringLiteralForExtInst => SmallVec::new(),
+            },
+            Global::Const(ct) => {
+                let ct_def = &cx[ct];
+                let const_inputs = match &ct_def.kind {
+                    &ConstKind::PtrToGlobalVar(gv) => match &module.global_vars[gv].def {
+                        DeclDef::Imported(_) => None,
+                        DeclDef::Present(gv_def_body) => gv_def_body.initializer,
+                    }
+                    .into_iter()
+                    .collect(),
+                    ConstKind::SpvInst { spv_inst_and_const_inputs } => {
+                        spv_inst_and_const_inputs.1.clone()
+                    }
+                    ConstKind::SpvStringLiteralForExtInst(_) => SmallVec::new(),
+                };
+                iter::once(Global::Type(ct_def.ty))
+                    .chain(const_inputs.into_iter().map(Global::Const))
+                    .collect()
+            }
+        }
+    };
+
+    let mut content_hasher = ContentHasher::new(cx, module);
+    let mut content_hash = |global| match global {
+        Global::Type(ty) => content_hasher.type_hash(ty),
+        Global::Const(ct) => content_hasher.const_hash(ct),
+    };
+
+    // NOTE: all dependencies are always in `globals` (since the dependencies
+    // of a visited definition are always visited as well), and dependencies
+    // used more than once are also counted (and later removed) more than once.
+    let mut remaining_dep_counts = vec![0; globals.len()];
+    let mut dependents = vec![SmallVec::<[usize; 4]>::new(); globals.len()];
+    for (i, &global) in globals.iter().enumerate() {
+        for dep in deps_of(global) {
+            if let Some(dep_idx) = globals.get_index_of(&dep) {
+                remaining_dep_counts[i] += 1;
+                dependents[dep_idx].push(i);
+            }
+        }
+    }
+
+    let mut ready: BinaryHeap<_> = (0..globals.len())
+        .filter(|&i| remaining_dep_counts[i] == 0)
+        .map(|i| Reverse((content_hash(globals[i]), i)))
+        .collect();
+    let mut sorted = FxIndexSet::default();
+    while let Some(Reverse((_, i))) = ready.pop() {
+        sorted.insert(globals[i]);
+        for &dependent_idx in &dependents[i] {
+            remaining_dep_counts[dependent_idx] -= 1;
+            if remaining_dep_counts[dependent_idx] == 0 {
+                ready.push(Reverse((content_hash(globals[dependent_idx]), dependent_idx)));
+            }
+        }
+    }
+
+    // NOTE: only cycles (i.e. global variables with invalid initializers) can
+    // be left unsorted, and those are kept in their original order.
+    sorted.extend(globals.iter().copied());
+
+    *globals = sorted;
+}
+
 impl<'a> NeedsIdsCollector<'a> {
     fn alloc_ids<E>(
         self,
@@ -1324,12 +1405,341 @@ impl LazyInst<'_, '_> {
     }
 }
 
+/// Upper bound on the number of SPIR-V words taken by the annotations (e.g.
+/// decorations) of an instruction with `attrs`, or by the instruction itself
+/// (e.g. `SpvBitflagsOperand`), including an `OpLine`/`OpNoLine` before it.
+fn estimated_attrs_spv_word_count(cx: &Context, attrs: AttrSet) -> usize {
+    const MAX_DEBUG_LINE_WORDS: usize = 4;
+
+    MAX_DEBUG_LINE_WORDS
+        + cx[attrs]
+            .attrs
+            .iter()
+            .map(|attr| match attr {
+                Attr::SpvAnnotation(spv_inst) => 2 + spv_inst.imms.len(),
+                Attr::SpvAnnotationWithConstInputs { spv_inst, const_inputs } => {
+                    2 + spv_inst.imms.len() + const_inputs.0.len()
+                }
+                Attr::SpvBitflagsOperand(_) => 1,
+                Attr::Diagnostics(_) | Attr::QPtr(_) | Attr::SpvDebugLine { .. } => 0,
+            })
+            .sum::<usize>()
+}
+
+/// Upper bound on the number of SPIR-V words taken by the blocks (and their
+/// contents) lifted from `func_def_body`, see [`Module::estimated_spv_word_count`].
+fn estimated_func_body_spv_word_count(cx: &Context, func_def_body: &FuncDefBody) -> usize {
+    // NOTE: every region and control node may need up to two SPIR-V
+    // blocks (for its entry and exit, see `CfgPoint`), each taking at most an
+    // `OpLabel`, a merge (`OpLoopMerge` being the largest) and a terminator
+    // (with at most one value and two labels, unless accounted for separately,
+    // e.g. `OpSwitch`), and an `OpLine`/`OpNoLine` before each of them.
+    const MAX_BLOCK_WORDS: usize = 2 + 4 + 4 + 3 * 4;
+
+    let mut words = 0;
+
+    // NOTE: φ ("phi") instructions are sized by their predecessors, which
+    // aren't known until everything has been seen, so they're only counted here.
+    let mut phi_count = 0;
+    let mut max_phi_preds = 2;
+
+    let mut regions: Vec<_> = match &func_def_body.unstructured_cfg {
+        None => vec![func_def_body.body],
+        Some(cfg) => {
+            let mut preds = FxHashMap::<ControlRegion, usize>::default();
+            for region in cfg.rev_post_order(func_def_body) {
+                if let Some(control_inst) = cfg.control_inst_on_exit_from.get(region) {
+                    let imms = match &control_inst.kind {
+                        cfg::ControlInstKind::ExitInvocation(cfg::ExitInvocationKind::SpvInst(
+                            spv_inst,
+                        ))
+                        | cfg::ControlInstKind::SelectBranch(SelectionKind::SpvInst(spv_inst)) => {
+                            &spv_inst.imms[..]
+                        }
+                        _ => &[],
+                    };
+                    words += estimated_attrs_spv_word_count(cx, control_inst.attrs)
+                        + 3
+                        + imms.len()
+                        + control_inst.inputs.len()
+                        + 2 * control_inst.targets.len();
+                    for &target in &control_inst.targets {
+                        *preds.entry(target).or_default() += 1;
+                    }
+                }
+            }
+            max_phi_preds = max_phi_preds.max(preds.values().copied().max().unwrap_or(0));
+
+            cfg.rev_post_order(func_def_body).collect()
+        }
+    };
+
+    while let Some(region) = regions.pop() {
+        let region_def = func_def_body.at(region).def();
+
+        words += 2 * MAX_BLOCK_WORDS;
+        phi_count += region_def.inputs.len();
+        for input_decl in &region_def.inputs {
+            words += estimated_attrs_spv_word_count(cx, input_decl.attrs);
+        }
+
+        for func_at_control_node in func_def_body.at(region_def.children) {
+            let control_node_def = func_at_control_node.def();
+
+            words += 2 * MAX_BLOCK_WORDS;
+            phi_count += control_node_def.outputs.len();
+            for output_decl in &control_node_def.outputs {
+                words += estimated_attrs_spv_word_count(cx, output_decl.attrs);
+            }
+
+            match &control_node_def.kind {
+                &ControlNodeKind::Block { insts } => {
+                    for func_at_inst in func_at_control_node.at(insts) {
+                        let data_inst_def = func_at_inst.def();
+                        let data_inst_form_def = &cx[data_inst_def.form];
+
+                        let extra_operands = match &data_inst_form_def.kind {
+                            DataInstKind::QPtr(_) => {
+                                unreachable!("`DataInstKind::QPtr` should be legalized away");
+                            }
+                            DataInstKind::FuncCall(_) => 1,
+                            DataInstKind::SpvInst(spv_inst) => spv_inst.imms.len(),
+                            DataInstKind::SpvExtInst { .. } => 2,
+                        };
+                        let result_words =
+                            if data_inst_form_def.output_type.is_some() { 2 } else { 0 };
+                        words += estimated_attrs_spv_word_count(cx, data_inst_def.attrs)
+                            + 1
+                            + result_words
+                            + extra_operands
+                            + data_inst_def.inputs.len();
+                    }
+                }
+                ControlNodeKind::Select { kind, scrutinee: _, cases } => {
+                    // `OpSwitch` (one literal per non-default case, alongside
+                    // its label), which is always larger than `OpBranchConditional`.
+                    let imms = match kind {
+                        SelectionKind::BoolCond => &[][..],
+                        SelectionKind::SpvInst(spv_inst) => &spv_inst.imms[..],
+                    };
+                    words += 3 + imms.len() + cases.len();
+
+                    max_phi_preds = max_phi_preds.max(cases.len());
+                    regions.extend(cases.iter().copied());
+                }
+                &ControlNodeKind::Loop { body, .. } => regions.push(body),
+            }
+        }
+    }
+
+    words + phi_count * (3 + 2 * max_phi_preds)
+}
+
+/// Options for [`Module::lift_to_spv_module_emitter_with`].
+#[derive(Default)]
+pub struct LiftOptions {
+    /// If `Some`, called to allocate every SPIR-V ID (instead of numbering
+    /// them sequentially, starting at `1`), e.g. to avoid collisions with IDs
+    /// managed elsewhere, when splicing the result into a larger module.
+    ///
+    /// Every call must return a new (i.e. previously unused) ID, and the
+    /// `id_bound` (in the header) will be one past the largest ID returned.
+    pub id_allocator: Option<Box<dyn FnMut() -> spv::Id>>,
+
+    /// If `Some`, the generator magic number to write in the SPIR-V header
+    /// (instead of the original one, i.e. `original_generator_magic` in
+    /// [`spv::ModuleDebugInfo`]), e.g. to record that the module was produced
+    /// by a tool using SPIR-T (see also [`spv::GeneratorTool`]).
+    ///
+    /// The high 16 bits are expected to be a tool ID registered with Khronos,
+    /// and the low 16 bits a tool-specific version (`0` means "unknown").
+    //
+    // FIXME: SPIR-T doesn't have its own registered tool ID (yet), so
+    // tools using SPIR-T should use their own ID here, for now.
+    pub generator_magic: Option<u32>,
+
+    /// If `true`, types, constants and global variables are emitted sorted
+    /// topologically (i.e. dependencies first), and otherwise by a hash of their
+    /// contents, instead of in the order they're first used in (see also
+    /// [`passes::canonicalize::sort_definitions`](crate::passes::canonicalize::sort_definitions)).
+    pub canonical_definition_order: bool,
+}
+
 impl Module {
+    /// Estimate the number of SPIR-V words lifting (e.g. [`Module::lift_to_spv_words`])
+    /// would produce, without actually lifting, e.g. to preallocate a buffer,
+    /// or to check against some size budget.
+    ///
+    /// The estimate is an upper bound, which assumes the worst case wherever
+    /// lifting may emit extra instructions (e.g. `OpLine`/`OpNoLine` before every
+    /// instruction, or separate blocks for every structured control-flow point).
+    pub fn estimated_spv_word_count(&self) -> usize {
+        let cx = self.cx();
+        let (ModuleDialect::Spv(dialect), ModuleDebugInfo::Spv(debug_info)) =
+            (&self.dialect, &self.debug_info);
+
+        let mut needs_ids_collector = NeedsIdsCollector {
+            cx: &cx,
+            module: self,
+            ext_inst_imports: BTreeSet::new(),
+            debug_strings: BTreeSet::new(),
+            globals: FxIndexSet::default(),
+            data_inst_forms_seen: FxIndexSet::default(),
+            global_vars_seen: FxIndexSet::default(),
+            funcs: FxIndexSet::default(),
+        };
+        needs_ids_collector.visit_module(self);
+
+        // NOTE: `LiteralString`s always include a `\0` terminator, which
+        // needs a whole extra word when the length is a multiple of 4.
+        let literal_string_words = |s: &str| s.len() / 4 + 1;
+        let attrs_words = |attrs| estimated_attrs_spv_word_count(&cx, attrs);
+
+        // NOTE: the `LinkageAttributes` decoration of an import is
+        // emitted (by lifting) once per attribute of the imported definition.
+        let import_words = |import: &Import, attrs: AttrSet| match *import {
+            Import::LinkName(name) => {
+                (4 + literal_string_words(&cx[name])) * cx[attrs].attrs.len().max(1)
+            }
+        };
+
+        // Header, `Dialect` and `OpExtInstImport`s.
+        let mut words = spec::HEADER_LEN
+            + 2 * dialect.capabilities.len()
+            + dialect.extensions.iter().map(|ext| 1 + literal_string_words(ext)).sum::<usize>()
+            + (needs_ids_collector.ext_inst_imports.iter())
+                .map(|name| 2 + literal_string_words(name))
+                .sum::<usize>()
+            + 3;
+
+        // Exports (i.e. `OpEntryPoint`s and `LinkageAttributes` decorations).
+        for export_key in self.exports.keys() {
+            words += match export_key {
+                &ExportKey::LinkName(name) => 4 + literal_string_words(&cx[name]),
+                ExportKey::SpvEntryPoint { imms, interface_global_vars } => {
+                    2 + imms.len() + interface_global_vars.len()
+                }
+            };
+        }
+
+        // `ModuleDebugInfo` (and `OpString`s).
+        words += (needs_ids_collector.debug_strings.iter())
+            .map(|s| 2 + literal_string_words(s))
+            .sum::<usize>();
+        for sources in debug_info.source_languages.values() {
+            if sources.file_contents.is_empty() {
+                words += 3;
+            }
+            for contents in sources.file_contents.values() {
+                // NOTE: every instruction (`OpSource` or `OpSourceContinued`),
+                // other than the last one, holds at least this many bytes.
+                const MIN_NON_LAST_CONTENTS_LEN: usize = (0xffff - 1) * 4 - 1 - 3 * 4;
+                let inst_count = contents.len() / MIN_NON_LAST_CONTENTS_LEN + 1;
+                words += 3 + contents.len() / 4 + 2 * inst_count;
+            }
+        }
+        words += (debug_info.source_extensions.iter().chain(&debug_info.module_processes))
+            .map(|s| 1 + literal_string_words(s))
+            .sum::<usize>();
+
+        // Types, constants and global variables.
+        for &global in &needs_ids_collector.globals {
+            words += match global {
+                Global::Type(ty) => {
+                    let ty_def = &cx[ty];
+                    match &ty_def.kind {
+                        TypeKind::SpvInst { spv_inst, type_and_const_inputs } => {
+                            attrs_words(ty_def.attrs)
+                                + 2
+                                + spv_inst.imms.len()
+                                + type_and_const_inputs.len()
+                        }
+                        TypeKind::QPtr | TypeKind::SpvStringLiteralForExtInst => unreachable!(),
+                    }
+                }
+                Global::Const(ct) => {
+                    let ct_def = &cx[ct];
+                    match &ct_def.kind {
+                        ConstKind::SpvInst { spv_inst_and_const_inputs } => {
+                            let (spv_inst, const_inputs) = &**spv_inst_and_const_inputs;
+                            attrs_words(ct_def.attrs) + 3 + spv_inst.imms.len() + const_inputs.len()
+                        }
+
+                        // NOTE: `GlobalVar`s are counted separately below.
+                        ConstKind::PtrToGlobalVar(_) => 0,
+
+                        ConstKind::SpvStringLiteralForExtInst(_) => unreachable!(),
+                    }
+                }
+            };
+        }
+        for &gv in &needs_ids_collector.global_vars_seen {
+            let gv_decl = &self.global_vars[gv];
+
+            // `OpVariable` (with an initializer, in the worst case).
+            words += attrs_words(gv_decl.attrs) + 5;
+            if let DeclDef::Imported(import) = &gv_decl.def {
+                words += import_words(import, gv_decl.attrs);
+            }
+        }
+
+        // Functions (`OpFunction`, `OpFunctionParameter`s, body, `OpFunctionEnd`).
+        for &func in &needs_ids_collector.funcs {
+            let func_decl = &self.funcs[func];
+
+            words += attrs_words(func_decl.attrs) + 5 + 1;
+            words +=
+                func_decl.params.iter().map(|param| attrs_words(param.attrs) + 3).sum::<usize>();
+            words += match &func_decl.def {
+                DeclDef::Imported(import) => import_words(import, func_decl.attrs),
+                DeclDef::Present(func_def_body) => {
+                    estimated_func_body_spv_word_count(&cx, func_def_body)
+                }
+            };
+        }
+
+        words
+    }
+
     pub fn lift_to_spv_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
         self.lift_to_spv_module_emitter()?.write_to_spv_file(path)
     }
 
+    /// Lift to SPIR-V, written to `w` in little-endian byte order (see also
+    /// [`Module::lift_to_spv_writer_with_endianness`]).
+    pub fn lift_to_spv_writer(&self, w: impl io::Write) -> io::Result<()> {
+        self.lift_to_spv_writer_with_endianness(w, spv::write::Endianness::Little)
+    }
+
+    /// Lift to SPIR-V, written to `w` in the `endianness` byte order (which
+    /// readers detect from the magic number in the header, e.g. [`spv::read`]).
+    pub fn lift_to_spv_writer_with_endianness(
+        &self,
+        w: impl io::Write,
+        endianness: spv::write::Endianness,
+    ) -> io::Result<()> {
+        self.lift_to_spv_module_emitter()?.write_to_spv_writer(w, endianness)
+    }
+
+    pub fn lift_to_spv_words(&self) -> io::Result<Vec<u32>> {
+        Ok(self.lift_to_spv_module_emitter()?.words)
+    }
+
+    /// Lift to SPIR-V, as a [`spv::write::ModuleEmitter`] (which all the other
+    /// `lift_to_spv_*` methods use).
+    ///
+    /// Only definitions reachable from [`Module::exports`] are emitted (i.e.
+    /// unused definitions left behind by e.g. [`passes::merge`](crate::passes::merge)
+    /// are dropped, without having to run a separate DCE pass first).
     pub fn lift_to_spv_module_emitter(&self) -> io::Result<spv::write::ModuleEmitter> {
+        self.lift_to_spv_module_emitter_with(LiftOptions::default())
+    }
+
+    /// Like [`Module::lift_to_spv_module_emitter`], but using custom [`LiftOptions`].
+    pub fn lift_to_spv_module_emitter_with(
+        &self,
+        options: LiftOptions,
+    ) -> io::Result<spv::write::ModuleEmitter> {
         let spv_spec = spec::Spec::get();
         let wk = &spv_spec.well_known;
 
@@ -1376,16 +1786,24 @@ impl Module {
             needs_ids_collector.globals.insert(global_var_to_id_giving_global(gv));
         }
 
+        if options.canonical_definition_order {
+            sort_globals_canonically(&cx, self, &mut needs_ids_collector.globals);
+        }
+
         // IDs can be allocated once we have the full sets needing them, whether
         // sorted by contents, or ordered by the first occurence in the module.
+        let mut id_allocator = options.id_allocator;
         let mut id_bound = NonZeroU32::new(1).unwrap();
         let ids = needs_ids_collector.alloc_ids(|| {
-            let id = id_bound;
+            let id = match &mut id_allocator {
+                Some(id_allocator) => id_allocator(),
+                None => id_bound,
+            };
 
-            // FIXME(eddyb) use `id_bound.checked_add(1)` once that's stabilized.
-            match id_bound.get().checked_add(1).and_then(NonZeroU32::new) {
+            // FIXME: use `id.checked_add(1)` once that's stabilized.
+            match id.get().checked_add(1).and_then(NonZeroU32::new) {
                 Some(new_bound) => {
-                    id_bound = new_bound;
+                    id_bound = id_bound.max(new_bound);
                     Ok(id)
                 }
                 None => Err(io::Error::new(
@@ -1459,7 +1877,9 @@ impl Module {
         let header = [
             spv_spec.magic,
             (u32::from(dialect.version_major) << 16) | (u32::from(dialect.version_minor) << 8),
-            debug_info.original_generator_magic.map_or(0, |x| x.get()),
+            options
+                .generator_magic
+                .unwrap_or_else(|| debug_info.original_generator_magic.map_or(0, |x| x.get())),
             id_bound.get(),
             reserved_inst_schema,
         ];
@@ -1513,17 +1933,32 @@ impl Module {
                     | Attr::QPtr(_)
                     | Attr::SpvDebugLine { .. }
                     | Attr::SpvBitflagsOperand(_) => {}
-                    Attr::SpvAnnotation(inst @ spv::Inst { opcode, .. }) => {
+                    Attr::SpvAnnotation(inst @ spv::Inst { opcode, .. })
+                    | Attr::SpvAnnotationWithConstInputs {
+                        spv_inst: inst @ spv::Inst { opcode, .. },
+                        ..
+                    } => {
                         let target_id = result_id.expect(
                             "FIXME: it shouldn't be possible to attach \
                                  attributes to instructions without an output",
                         );
 
+                        let const_inputs = match attr {
+                            Attr::SpvAnnotationWithConstInputs { const_inputs, .. } => {
+                                &const_inputs.0[..]
+                            }
+                            _ => &[],
+                        };
+
                         let inst = spv::InstWithIds {
                             without_ids: inst.clone(),
                             result_type_id: None,
                             result_id: None,
-                            ids: iter::once(target_id).collect(),
+                            ids: iter::once(target_id)
+                                .chain(
+                                    const_inputs.iter().map(|&ct| ids.globals[&Global::Const(ct)]),
+                                )
+                                .collect(),
                         };
 
                         if [wk.OpExecutionMode, wk.OpExecutionModeId].contains(opcode) {
@@ -1601,6 +2036,32 @@ impl Module {
             }
         }
 
+        // NOTE: decorations are grouped by their target (in the order
+        // targets were first decorated, which also brings `LinkageAttributes`
+        // from exports next to the other decorations of the same target),
+        // with `OpDecorate*`s before the `OpMemberDecorate*`s of that target,
+        // the latter being sorted by member index (the sort being stable, the
+        // order within each group comes from the deterministic `AttrSet` order).
+        {
+            let mut target_order = FxHashMap::default();
+            for inst in &decoration_insts {
+                let next_idx = target_order.len();
+                target_order.entry(inst.ids[0]).or_insert(next_idx);
+            }
+            decoration_insts.sort_by_key(|inst| {
+                let member_idx = match inst.imms[..] {
+                    [spv::Imm::Short(_, member_idx), ..]
+                        if [wk.OpMemberDecorate, wk.OpMemberDecorateString]
+                            .contains(&inst.opcode) =>
+                    {
+                        Some(member_idx)
+                    }
+                    _ => None,
+                };
+                (target_order[&inst.ids[0]], member_idx)
+            });
+        }
+
         // FIXME(eddyb) maybe make a helper for `push_inst` with an iterator?
         for entry_point_inst in entry_point_insts {
             emitter.push_inst(&entry_point_inst)?;
@@ -1754,3 +2215,370 @@ impl Module {
         Ok(emitter)
     }
 }
+
+#[test]
+fn estimated_spv_word_count_is_upper_bound() {
+    let lower = |version: u32, src: &str| {
+        let mut spv_words = spv::test_asm::assemble(src);
+        spv_words[1] = version;
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap()
+    };
+    let assert_upper_bound = |module: &Module| {
+        let lifted_words = module.lift_to_spv_words().unwrap();
+        let estimate = module.estimated_spv_word_count();
+        assert!(estimate >= lifted_words.len(), "{estimate} < {}", lifted_words.len());
+        lifted_words
+    };
+
+    // Minimal export.
+    assert_upper_bound(&lower(
+        0x0001_0000,
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "foo" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %1 = OpFunction %2 None %3
+            %4 = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#,
+    ));
+
+    // Debuginfo (including `OpLine`s), and extended instructions.
+    let mut module = lower(
+        0x0001_0100,
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            %glsl = OpExtInstImport "GLSL.std.450"
+            OpMemoryModel Logical GLSL450
+            %file = OpString "shader.glsl"
+            OpSource GLSL 450 %file "float foo() { return abs(1.0) }"
+            OpSourceExtension "GL_GOOGLE_include_directive"
+            OpName %foo "foo"
+            OpModuleProcessed "tool"
+            OpDecorate %foo LinkageAttributes "foo" Export
+            %float = OpTypeFloat 32
+            %one = OpConstant %float 1
+            %func_type = OpTypeFunction %float
+            OpLine %file 1 1
+            %foo = OpFunction %float None %func_type
+            %entry = OpLabel
+            OpLine %file 1 22
+            %abs = OpExtInst %float %glsl 4 %one
+            OpNoLine
+            OpReturnValue %abs
+            OpFunctionEnd
+            "#,
+    );
+    assert_upper_bound(&module);
+
+    // `OpSource` contents too large for one instruction (i.e. which have to be
+    // split across several `OpSource`/`OpSourceContinued` instructions).
+    let crate::ModuleDebugInfo::Spv(debug_info) = &mut module.debug_info;
+    for sources in debug_info.source_languages.values_mut() {
+        for contents in sources.file_contents.values_mut() {
+            *contents = "// large source\n".repeat(50_000);
+        }
+    }
+    let lifted_words = assert_upper_bound(&module);
+    let wk = &spec::Spec::get().well_known;
+    let source_continued_count = spv::read::ModuleParser::read_from_spv_words(&lifted_words)
+        .unwrap()
+        .filter(|inst| inst.as_ref().unwrap().opcode == wk.OpSourceContinued)
+        .count();
+    assert_eq!(source_continued_count, 3);
+
+    // `OpSwitch`, and an `OpPhi` (with more than 2 predecessors).
+    assert_upper_bound(&lower(
+        0x0001_0000,
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %foo LinkageAttributes "foo" Export
+            %int = OpTypeInt 32 0
+            %zero = OpConstant %int 0
+            %one = OpConstant %int 1
+            %two = OpConstant %int 2
+            %func_type = OpTypeFunction %int %int
+            %foo = OpFunction %int None %func_type
+            %x = OpFunctionParameter %int
+            %entry = OpLabel
+            OpSelectionMerge %merge None
+            OpSwitch %x %default 0 %case0 1 %case1 2 %case2
+            %case0 = OpLabel
+            OpBranch %merge
+            %case1 = OpLabel
+            OpBranch %merge
+            %case2 = OpLabel
+            OpBranch %merge
+            %default = OpLabel
+            OpBranch %merge
+            %merge = OpLabel
+            %r = OpPhi %int %zero %case0 %one %case1 %two %case2 %x %default
+            OpReturnValue %r
+            OpFunctionEnd
+            "#,
+    ));
+
+    // Imported functions and global variables.
+    assert_upper_bound(&lower(
+        0x0001_0000,
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %bar LinkageAttributes "bar" Import
+            OpDecorate %gv LinkageAttributes "gv" Import
+            OpDecorate %foo LinkageAttributes "foo" Export
+            %int = OpTypeInt 32 0
+            %private_int_ptr = OpTypePointer Private %int
+            %gv = OpVariable %private_int_ptr Private
+            %func_type = OpTypeFunction %int
+            %bar = OpFunction %int None %func_type
+            OpFunctionEnd
+            %foo = OpFunction %int None %func_type
+            %entry = OpLabel
+            %x = OpLoad %int %gv
+            %y = OpFunctionCall %int %bar
+            %z = OpIAdd %int %x %y
+            OpReturnValue %z
+            OpFunctionEnd
+            "#,
+    ));
+
+    // Entry-point with an `OpExecutionModeId` (SPIR-V 1.2).
+    assert_upper_bound(&lower(
+        0x0001_0200,
+        r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            OpEntryPoint GLCompute %main "main"
+            OpExecutionModeId %main LocalSizeId %one %one %one
+            %void = OpTypeVoid
+            %uint = OpTypeInt 32 0
+            %one = OpConstant %uint 1
+            %func_type = OpTypeFunction %void
+            %main = OpFunction %void None %func_type
+            %entry = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#,
+    ));
+}
+
+#[test]
+fn annotations_are_ordered() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpMemberName %2 1 "b"
+            OpName %2 "S"
+            OpMemberName %2 0 "a"
+            OpDecorate %1 LinkageAttributes "foo" Export
+            OpDecorate %5 LinkageAttributes "bar" Export
+            OpMemberDecorate %2 1 Offset 4
+            OpDecorate %1 Binding 0
+            OpMemberDecorate %2 0 Offset 0
+            OpDecorate %5 Binding 1
+            OpDecorate %2 Block
+            %3 = OpTypeInt 32 0
+            %2 = OpTypeStruct %3 %3
+            %4 = OpTypePointer Uniform %2
+            %1 = OpVariable %4 Uniform
+            %5 = OpVariable %4 Uniform
+            "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+    let lifted_words = module.lift_to_spv_words().unwrap();
+
+    let wk = &spec::Spec::get().well_known;
+    let names = [wk.OpName, wk.OpMemberName];
+    let decorations = [wk.OpDecorate, wk.OpMemberDecorate];
+    let insts: Vec<_> = spv::read::ModuleParser::read_from_spv_words(&lifted_words)
+        .unwrap()
+        .map(Result::unwrap)
+        .filter(|inst| names.contains(&inst.opcode) || decorations.contains(&inst.opcode))
+        .collect();
+
+    // All debug names come before all decorations.
+    let first_decoration = insts.iter().position(|inst| decorations.contains(&inst.opcode));
+    let first_decoration = first_decoration.unwrap();
+    assert!(insts[first_decoration..].iter().all(|inst| decorations.contains(&inst.opcode)));
+    assert_eq!(insts.len() - first_decoration, 7);
+
+    // Decorations are contiguous per target, and member decorations come
+    // last (in increasing order of their member index).
+    let mut finished_targets = vec![];
+    for pair in insts[first_decoration..].windows(2) {
+        let [a, b] = pair else { unreachable!() };
+        if a.ids[0] != b.ids[0] {
+            finished_targets.push(a.ids[0]);
+            assert!(!finished_targets.contains(&b.ids[0]));
+            continue;
+        }
+        let member_idx = |inst: &spv::InstWithIds| match inst.imms[..] {
+            [spv::Imm::Short(_, member_idx), ..] if inst.opcode == wk.OpMemberDecorate => {
+                Some(member_idx)
+            }
+            _ => None,
+        };
+        assert!(member_idx(a) <= member_idx(b));
+    }
+}
+
+#[test]
+fn custom_id_allocator() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "foo" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %1 = OpFunction %2 None %3
+            %4 = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+
+    // Allocate every other ID, starting at `100`.
+    let mut next_id = 100;
+    let options = LiftOptions {
+        id_allocator: Some(Box::new(move || {
+            let id = NonZeroU32::new(next_id).unwrap();
+            next_id += 2;
+            id
+        })),
+        ..LiftOptions::default()
+    };
+    let lifted_words = module.lift_to_spv_module_emitter_with(options).unwrap().words;
+
+    let parser = spv::read::ModuleParser::read_from_spv_words(&lifted_words).unwrap();
+    let id_bound = parser.header[3];
+    let result_ids: Vec<_> =
+        parser.filter_map(|inst| inst.unwrap().result_id.map(|id| id.get())).collect();
+    assert_eq!(result_ids.len(), 4);
+    assert!(result_ids.iter().all(|&id| id >= 100 && id % 2 == 0));
+    assert_eq!(id_bound, result_ids.iter().max().unwrap() + 1);
+
+    Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &lifted_words).unwrap();
+}
+
+#[test]
+fn override_generator_magic() {
+    let mut spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            "#,
+    );
+    // Header generator word (`glslang`, version 11).
+    spv_words[2] = (8 << 16) | 11;
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+
+    let lift_generator_magic =
+        |options| module.lift_to_spv_module_emitter_with(options).unwrap().words[2];
+    assert_eq!(lift_generator_magic(LiftOptions::default()), (8 << 16) | 11);
+    let options = LiftOptions { generator_magic: Some((27 << 16) | 1), ..LiftOptions::default() };
+    assert_eq!(lift_generator_magic(options), (27 << 16) | 1);
+}
+
+#[test]
+fn canonical_definition_order() {
+    let wk = &spec::Spec::get().well_known;
+
+    // Both functions only differ in the order of their (independent) instructions,
+    // which determines the order their types and constants are first used in.
+    let lift_globals = |insts: &str, canonical_definition_order| {
+        let spv_words = spv::test_asm::assemble(&format!(
+            r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %func LinkageAttributes "func" Export
+            %void = OpTypeVoid
+            %int = OpTypeInt 32 1
+            %float = OpTypeFloat 32
+            %int_one = OpConstant %int 1
+            %float_one = OpConstant %float 0x3f800000
+            %func_type = OpTypeFunction %void
+            %func = OpFunction %void None %func_type
+            %entry = OpLabel
+            {insts}
+            OpReturn
+            OpFunctionEnd
+            "#
+        ));
+        let module =
+            Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+        let options = LiftOptions { canonical_definition_order, ..LiftOptions::default() };
+        let lifted_words = module.lift_to_spv_module_emitter_with(options).unwrap().words;
+
+        // Only keep the words before the first function.
+        let func_start = spv::read::ModuleParser::read_from_spv_words(&lifted_words)
+            .unwrap()
+            .with_word_offsets()
+            .map(Result::unwrap)
+            .find(|(_, inst)| inst.opcode == wk.OpFunction)
+            .unwrap()
+            .0;
+        lifted_words[..func_start].to_vec()
+    };
+    let int_then_float = "
+        %a = OpIAdd %int %int_one %int_one
+        %b = OpFAdd %float %float_one %float_one
+    ";
+    let float_then_int = "
+        %b = OpFAdd %float %float_one %float_one
+        %a = OpIAdd %int %int_one %int_one
+    ";
+
+    assert!(lift_globals(int_then_float, false) != lift_globals(float_then_int, false));
+    assert!(lift_globals(int_then_float, true) == lift_globals(float_then_int, true));
+}
+
+#[test]
+fn lift_to_big_endian_writer() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "foo" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %1 = OpFunction %2 None %3
+            %4 = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+    let lifted_words = module.lift_to_spv_words().unwrap();
+
+    let mut big_endian_bytes = vec![];
+    module
+        .lift_to_spv_writer_with_endianness(&mut big_endian_bytes, spv::write::Endianness::Big)
+        .unwrap();
+    assert_eq!(big_endian_bytes[..4], spec::Spec::get().magic.to_be_bytes());
+
+    // Reading back (i.e. byte-swapping) big-endian SPIR-V gives the same module.
+    let parser = spv::read::ModuleParser::read_from_spv_bytes(big_endian_bytes).unwrap();
+    assert_eq!(parser.header, lifted_words[..spec::HEADER_LEN]);
+    let roundtripped_module = Module::lower_from_spv_module_parser(module.cx(), parser).unwrap();
+    assert_eq!(roundtripped_module.lift_to_spv_words().unwrap(), lifted_words);
+}
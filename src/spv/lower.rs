@@ -9,13 +9,14 @@ use crate::{
     Func, FuncDecl, FuncDefBody, FuncParam, FxIndexMap, GlobalVarDecl, GlobalVarDefBody, Import,
     InternedStr, Module, SelectionKind, Type, TypeDef, TypeKind, TypeOrConst, Value,
 };
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use smallvec::SmallVec;
 use std::collections::{BTreeMap, BTreeSet};
 use std::num::NonZeroU32;
 use std::path::Path;
 use std::rc::Rc;
-use std::{io, mem};
+use std::string::FromUtf8Error;
+use std::{fmt, io, mem};
 
 /// SPIR-T definition of a SPIR-V ID.
 enum IdDef {
@@ -80,9 +81,279 @@ struct IntraFuncInst {
     ids: SmallVec<[spv::Id; 4]>,
 }
 
-// FIXME(eddyb) stop abusing `io::Error` for error reporting.
-fn invalid(reason: &str) -> io::Error {
-    io::Error::new(io::ErrorKind::InvalidData, format!("malformed SPIR-V ({reason})"))
+/// Logical layout sections of a SPIR-V module, in the order they're required
+/// to appear in (see also [`LowerErrorKind::OutOfOrderInstruction`]).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Seq {
+    Capability,
+    Extension,
+    ExtInstImport,
+    MemoryModel,
+    EntryPoint,
+    ExecutionMode,
+    DebugStringAndSource,
+    DebugName,
+    DebugModuleProcessed,
+    Decoration,
+
+    // NOTE(eddyb) not its own section, but only a "checkpoint", forcing
+    // instructions following `OpLine`/`OpNoLine` into later sections.
+    DebugLine,
+
+    TypeConstOrGlobalVar,
+    Function,
+}
+
+/// Options for [`Module::lower_from_spv_module_parser_with`].
+#[derive(Clone)]
+pub struct LowerOptions {
+    /// Whether instructions being out of order (wrt the SPIR-V module layout,
+    /// see [`Seq`]) should be an error (the default), or only a [`LowerWarning`].
+    ///
+    /// Note that some out of order instructions can still cause errors, e.g.
+    /// decorations following the definition of their target ID.
+    pub strict_ordering: bool,
+
+    /// Whether to require every capability that other declared capabilities
+    /// depend on (see [`spv::Dialect::missing_capability_dependencies`]) to also
+    /// be declared, erroring with [`LowerErrorKind::MissingCapabilityDependency`].
+    ///
+    /// Note that SPIR-V itself treats such dependencies as implicitly declared,
+    /// so this is off by default, and only useful for stricter tooling.
+    pub require_capability_dependencies: bool,
+
+    /// Whether declaring the same capability (or extension) more than once
+    /// should be an error ([`LowerErrorKind::DuplicateCapability`] or
+    /// [`LowerErrorKind::DuplicateExtension`]), instead of being ignored
+    /// (the default), as duplicates can indicate a malfunctioning producer.
+    pub reject_duplicate_declarations: bool,
+
+    /// Whether to reject modules which SPIR-V disallows, but which can still
+    /// be lowered, instead of lowering them as-is (the default), currently:
+    /// * `OpEntryPoint`s sharing both their execution model and name (with
+    ///   [`LowerErrorKind::DuplicateEntryPoint`]), otherwise kept as separate
+    ///   exports (unless their interfaces also match, in which case their
+    ///   [`ExportKey`]s are equal, and only the last one is kept)
+    pub strict: bool,
+
+    /// If `Some`, the only extended instruction sets (`OpExtInstImport` names)
+    /// allowed to be imported, with any others erroring with
+    /// [`LowerErrorKind::DisallowedExtInstSet`] (useful for tools that only
+    /// support a known subset, see also [`Module::ext_inst_imports`]).
+    pub allowed_ext_inst_sets: Option<FxHashSet<String>>,
+
+    /// Whether to drop all `OpNop` instructions (which have no semantics),
+    /// instead of keeping them (the default) as e.g. [`DataInst`](crate::DataInst)s.
+    ///
+    /// Note that `OpUndef` doesn't need a similar option, as it's always kept
+    /// distinct, i.e. as a [`ConstKind::SpvInst`] using the `OpUndef` opcode.
+    pub drop_nops: bool,
+}
+
+impl Default for LowerOptions {
+    fn default() -> Self {
+        Self {
+            strict_ordering: true,
+            require_capability_dependencies: false,
+            reject_duplicate_declarations: false,
+            strict: false,
+            allowed_ext_inst_sets: None,
+            drop_nops: false,
+        }
+    }
+}
+
+/// Error produced when lowering a SPIR-V module to SPIR-T fails.
+pub struct LowerError {
+    pub kind: LowerErrorKind,
+
+    /// The SPIR-V instruction being lowered, if the error is specific to one.
+    pub opcode: Option<spec::Opcode>,
+
+    /// Position (in words, from the start of the module, including its header)
+    /// of the instruction being lowered, if known.
+    //
+    // FIXME: this is only tracked while reading the module, and not when
+    // instructions are later revisited (e.g. to lower function bodies).
+    pub word_offset: Option<usize>,
+}
+
+pub enum LowerErrorKind {
+    /// Reading the module failed (i.e. I/O error, or invalid SPIR-V binary encoding).
+    Read(io::Error),
+
+    OutOfOrderInstruction {
+        found: Seq,
+        after: Seq,
+    },
+    DuplicateMemoryModel,
+    MissingMemoryModel,
+    UndefinedDecoratedIds(Vec<spv::Id>),
+    MalformedLiteralString(FromUtf8Error),
+    UnsupportedIdDecoration {
+        opcode: spec::Opcode,
+    },
+
+    /// The declared capability `implied_by` depends on the `needed` capability,
+    /// which wasn't declared (see [`LowerOptions::require_capability_dependencies`]).
+    MissingCapabilityDependency {
+        needed: u32,
+        implied_by: u32,
+    },
+
+    /// `OpCapability` declaring an already declared capability
+    /// (see [`LowerOptions::reject_duplicate_declarations`]).
+    DuplicateCapability(u32),
+
+    /// `OpExtension` declaring an already declared extension
+    /// (see [`LowerOptions::reject_duplicate_declarations`]).
+    DuplicateExtension(String),
+
+    /// `OpEntryPoint` with the same execution model and name as an earlier one
+    /// (see [`LowerOptions::strict`]).
+    DuplicateEntryPoint {
+        execution_model: spv::ExecutionModel,
+        name: String,
+    },
+
+    /// `OpExtInstImport` of an extended instruction set not in the allowlist
+    /// (see [`LowerOptions::allowed_ext_inst_sets`]).
+    DisallowedExtInstSet(String),
+
+    /// An ID (defined or used) that isn't below the ID bound in the module header.
+    IdOutOfBounds {
+        id: spv::Id,
+        id_bound: u32,
+    },
+
+    /// The file operand of e.g. `OpLine`/`OpSource` isn't the result of an `OpString`.
+    FileNotOpString(spv::Id),
+
+    /// An ID defined by more than one instruction in the same function.
+    DuplicateLocalId(spv::Id),
+
+    /// Any other (malformed or unsupported) SPIR-V, described by a message.
+    //
+    // FIXME: replace most uses of this with more structured variants.
+    Other(String),
+}
+
+impl LowerError {
+    fn new(kind: LowerErrorKind) -> Self {
+        Self { kind, opcode: None, word_offset: None }
+    }
+
+    fn in_inst(self, opcode: spec::Opcode, word_offset: Option<usize>) -> Self {
+        Self { opcode: Some(opcode), word_offset, ..self }
+    }
+}
+
+impl From<io::Error> for LowerError {
+    fn from(e: io::Error) -> Self {
+        Self::new(LowerErrorKind::Read(e))
+    }
+}
+
+impl From<LowerError> for io::Error {
+    fn from(e: LowerError) -> Self {
+        match e.kind {
+            LowerErrorKind::Read(e) => e,
+            _ => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+impl fmt::Display for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let LowerErrorKind::Read(e) = &self.kind {
+            return e.fmt(f);
+        }
+
+        write!(f, "malformed SPIR-V (")?;
+        if let Some(opcode) = self.opcode {
+            write!(f, "in {}", opcode.name())?;
+            if let Some(word_offset) = self.word_offset {
+                write!(f, " (at word {word_offset})")?;
+            }
+            write!(f, ": ")?;
+        }
+        match &self.kind {
+            LowerErrorKind::Read(_) => unreachable!(),
+            LowerErrorKind::OutOfOrderInstruction { found, after } => write!(
+                f,
+                "out of order: {found:?} instructions must precede {after:?} instructions"
+            )?,
+            LowerErrorKind::DuplicateMemoryModel => write!(f, "duplicate OpMemoryModel")?,
+            LowerErrorKind::MissingMemoryModel => write!(f, "missing OpMemoryModel")?,
+            LowerErrorKind::UndefinedDecoratedIds(ids) => {
+                write!(f, "decorated IDs never defined: {ids:?}")?;
+            }
+            LowerErrorKind::MalformedLiteralString(e) => write!(f, "{} in {:?}", e, e.as_bytes())?,
+            LowerErrorKind::UnsupportedIdDecoration { opcode } => {
+                // NOTE: `self.opcode` is only omitted if it's redundant.
+                if self.opcode != Some(*opcode) {
+                    write!(f, "{}: ", opcode.name())?;
+                }
+                write!(f, "unsupported decoration with ID")?;
+            }
+            LowerErrorKind::MissingCapabilityDependency { needed, implied_by } => {
+                let name = |cap| {
+                    spv::CapabilityDiff::capability_name(cap)
+                        .map_or_else(|| cap.to_string(), |name| name.to_string())
+                };
+                write!(
+                    f,
+                    "capability {} requires capability {} to also be declared",
+                    name(*implied_by),
+                    name(*needed)
+                )?;
+            }
+            LowerErrorKind::DuplicateCapability(cap) => {
+                match spv::CapabilityDiff::capability_name(*cap) {
+                    Some(name) => write!(f, "duplicate capability {name}")?,
+                    None => write!(f, "duplicate capability {cap}")?,
+                }
+            }
+            LowerErrorKind::DuplicateExtension(ext) => write!(f, "duplicate extension {ext:?}")?,
+            LowerErrorKind::DuplicateEntryPoint { execution_model, name } => {
+                write!(f, "duplicate {execution_model:?} entry-point {name:?}")?;
+            }
+            LowerErrorKind::DisallowedExtInstSet(name) => {
+                write!(f, "extended instruction set {name:?} is not allowed")?;
+            }
+            LowerErrorKind::IdOutOfBounds { id, id_bound } => {
+                write!(f, "%{id} is out of bounds (ID bound is {id_bound})")?;
+            }
+            LowerErrorKind::FileNotOpString(id) => write!(f, "%{id} is not an OpString")?,
+            LowerErrorKind::DuplicateLocalId(id) => write!(f, "%{id} is defined more than once")?,
+            LowerErrorKind::Other(reason) => write!(f, "{reason}")?,
+        }
+        write!(f, ")")
+    }
+}
+
+// HACK: `spec::Opcode` isn't `Debug`, and the message is more useful anyway.
+impl fmt::Debug for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for LowerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            LowerErrorKind::Read(e) => Some(e),
+            LowerErrorKind::MalformedLiteralString(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Recoverable [`LowerError`], that was only reported (see [`LowerOptions`]).
+pub type LowerWarning = LowerError;
+
+fn invalid(reason: &str) -> LowerError {
+    LowerError::new(LowerErrorKind::Other(reason.into()))
 }
 
 // FIXME(eddyb) provide more information about any normalization that happened:
@@ -93,28 +364,59 @@ fn invalid(reason: &str) -> io::Error {
 // have the opportunity of silencing them e.g. by removing dead code.
 impl Module {
     pub fn lower_from_spv_file(cx: Rc<Context>, path: impl AsRef<Path>) -> io::Result<Self> {
-        Self::lower_from_spv_module_parser(cx, spv::read::ModuleParser::read_from_spv_file(path)?)
+        Ok(Self::lower_from_spv_module_parser(
+            cx,
+            spv::read::ModuleParser::read_from_spv_file(path)?,
+        )?)
     }
 
     pub fn lower_from_spv_bytes(cx: Rc<Context>, spv_bytes: Vec<u8>) -> io::Result<Self> {
-        Self::lower_from_spv_module_parser(
+        Ok(Self::lower_from_spv_module_parser(
             cx,
             spv::read::ModuleParser::read_from_spv_bytes(spv_bytes)?,
-        )
+        )?)
+    }
+
+    pub fn lower_from_spv_words(cx: Rc<Context>, spv_words: &[u32]) -> io::Result<Self> {
+        Ok(Self::lower_from_spv_module_parser(
+            cx,
+            spv::read::ModuleParser::read_from_spv_words(spv_words)?,
+        )?)
     }
 
     pub fn lower_from_spv_module_parser(
         cx: Rc<Context>,
         parser: spv::read::ModuleParser,
-    ) -> io::Result<Self> {
+    ) -> Result<Self, LowerError> {
+        let (module, warnings) =
+            Self::lower_from_spv_module_parser_with(cx, parser, LowerOptions::default())?;
+
+        // Only non-default `LowerOptions` can turn errors into warnings.
+        assert!(warnings.is_empty());
+
+        Ok(module)
+    }
+
+    /// Like [`Module::lower_from_spv_module_parser`], but using custom [`LowerOptions`],
+    /// and also returning any [`LowerWarning`]s (only possible with lenient options).
+    pub fn lower_from_spv_module_parser_with(
+        cx: Rc<Context>,
+        parser: spv::read::ModuleParser,
+        options: LowerOptions,
+    ) -> Result<(Self, Vec<LowerWarning>), LowerError> {
+        let mut warnings = vec![];
+
         let spv_spec = spec::Spec::get();
         let wk = &spv_spec.well_known;
 
         // HACK(eddyb) used to quickly check whether an `OpVariable` is global.
         let storage_class_function_imm = spv::Imm::Short(wk.StorageClass, wk.Function);
 
+        // All IDs (whether defined or only used) must be below this bound.
+        let id_bound = parser.header[3];
+
         let mut module = {
-            let [magic, version, generator_magic, id_bound, reserved_inst_schema] = parser.header;
+            let [magic, version, generator_magic, _id_bound, reserved_inst_schema] = parser.header;
 
             // Ensured above (this is the value after any endianness swapping).
             assert_eq!(magic, spv_spec.magic);
@@ -128,9 +430,6 @@ impl Module {
                 )));
             }
 
-            // FIXME(eddyb) maybe use this somehow? (e.g. check IDs against it)
-            let _ = id_bound;
-
             if reserved_inst_schema != 0 {
                 return Err(invalid(&format!(
                     "unknown instruction schema {reserved_inst_schema} - only 0 is supported"
@@ -151,6 +450,7 @@ impl Module {
                 }),
                 crate::ModuleDebugInfo::Spv(spv::ModuleDebugInfo {
                     original_generator_magic: NonZeroU32::new(generator_magic),
+                    original_id_bound: NonZeroU32::new(id_bound),
 
                     source_languages: BTreeMap::new(),
                     source_extensions: vec![],
@@ -159,43 +459,41 @@ impl Module {
             )
         };
 
-        #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-        enum Seq {
-            Capability,
-            Extension,
-            ExtInstImport,
-            MemoryModel,
-            EntryPoint,
-            ExecutionMode,
-            DebugStringAndSource,
-            DebugName,
-            DebugModuleProcessed,
-            Decoration,
-
-            // NOTE(eddyb) not its own section, but only a "checkpoint", forcing
-            // instructions following `OpLine`/`OpNoLine` into later sections.
-            DebugLine,
-
-            TypeConstOrGlobalVar,
-            Function,
-        }
         let mut seq = None;
 
         let mut has_memory_model = false;
         let mut pending_attrs = FxHashMap::<spv::Id, crate::AttrSetDef>::default();
+        // NOTE: annotations with (non-target) ID operands can only have
+        // those IDs resolved once their target is defined (see `SpvAnnotationWithConstInputs`).
+        let mut pending_annotations_with_ids =
+            FxHashMap::<spv::Id, Vec<(spv::Inst, SmallVec<[spv::Id; 2]>)>>::default();
         let mut pending_imports = FxHashMap::<spv::Id, Import>::default();
         let mut pending_exports = vec![];
+        let mut entry_point_models_and_names = FxHashSet::default();
         let mut current_debug_line = None;
         let mut current_block_id = None; // HACK(eddyb) for `current_debug_line` resets.
         let mut id_defs = FxHashMap::default();
+        let mut pending_forward_ptrs = FxHashMap::<spv::Id, spv::Imm>::default();
         let mut pending_func_bodies = vec![];
         let mut current_func_body = None;
 
-        let mut spv_insts = parser.peekable();
-        while let Some(mut inst) = spv_insts.next().transpose()? {
+        let mut spv_insts = parser.with_word_offsets().peekable();
+        while let Some((word_offset, mut inst)) = spv_insts.next().transpose()? {
             let opcode = inst.opcode;
 
-            let invalid = |msg: &str| invalid(&format!("in {}: {}", opcode.name(), msg));
+            let error = |kind| LowerError::new(kind).in_inst(opcode, Some(word_offset));
+            let invalid = |msg: &str| error(LowerErrorKind::Other(msg.into()));
+            let malformed_literal_string = |e| error(LowerErrorKind::MalformedLiteralString(e));
+
+            if let Some(id) = inst
+                .result_type_id
+                .into_iter()
+                .chain(inst.result_id)
+                .chain(inst.ids.iter().copied())
+                .find(|id| id.get() >= id_bound)
+            {
+                return Err(error(LowerErrorKind::IdOutOfBounds { id, id_bound }));
+            }
 
             // Handle line debuginfo early, as it doesn't have its own section,
             // but rather can go almost anywhere among globals and functions.
@@ -212,8 +510,8 @@ impl Module {
                             let file_path = match id_defs.get(&file_path_id) {
                                 Some(&IdDef::SpvDebugString(s)) => s,
                                 _ => {
-                                    return Err(invalid(&format!(
-                                        "%{file_path_id} is not an OpString"
+                                    return Err(error(LowerErrorKind::FileNotOpString(
+                                        file_path_id,
                                     )));
                                 }
                             };
@@ -232,6 +530,11 @@ impl Module {
                 continue;
             }
 
+            // Dropping `OpNop`s early avoids them affecting e.g. `seq`.
+            if opcode == wk.OpNop && options.drop_nops {
+                continue;
+            }
+
             // Reset line debuginfo when crossing/leaving blocks.
             let new_block_id = if opcode == wk.OpLabel {
                 Some(inst.result_id.unwrap())
@@ -248,6 +551,36 @@ impl Module {
             let mut attrs =
                 inst.result_id.and_then(|id| pending_attrs.remove(&id)).unwrap_or_default();
 
+            for (spv_inst, input_ids) in inst
+                .result_id
+                .and_then(|id| pending_annotations_with_ids.remove(&id))
+                .into_iter()
+                .flatten()
+            {
+                let const_inputs = input_ids
+                    .iter()
+                    .map(|&id| match id_defs.get(&id) {
+                        Some(&IdDef::Const(ct)) => Ok(ct),
+                        Some(id_def) => Err(invalid(&format!(
+                            "unsupported use of {} in {} operands",
+                            id_def.descr(&cx),
+                            spv_inst.opcode.name(),
+                        ))),
+                        // FIXME: support forward references (to e.g.
+                        // `OpVariable`s defined after the annotation target).
+                        None => Err(invalid(&format!(
+                            "%{id} used in {} operands must be defined before %{}",
+                            spv_inst.opcode.name(),
+                            inst.result_id.unwrap(),
+                        ))),
+                    })
+                    .collect::<Result<_, LowerError>>()?;
+                attrs.attrs.insert(Attr::SpvAnnotationWithConstInputs {
+                    spv_inst,
+                    const_inputs: crate::OrdAssertEq(const_inputs),
+                });
+            }
+
             if let Some((file_path, line, col)) = current_debug_line {
                 // FIXME(eddyb) use `get_or_insert_default` once that's stabilized.
                 attrs.attrs.insert(Attr::SpvDebugLine {
@@ -300,7 +633,11 @@ impl Module {
 
                 match &mut module.dialect {
                     crate::ModuleDialect::Spv(dialect) => {
-                        dialect.capabilities.insert(cap);
+                        if !dialect.capabilities.insert(cap)
+                            && options.reject_duplicate_declarations
+                        {
+                            return Err(error(LowerErrorKind::DuplicateCapability(cap)));
+                        }
                     }
                 }
 
@@ -311,11 +648,16 @@ impl Module {
                         && inst.result_id.is_none()
                         && inst.ids.is_empty()
                 );
-                let ext = spv::extract_literal_string(&inst.imms)
-                    .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?;
+                let ext =
+                    spv::extract_literal_string(&inst.imms).map_err(malformed_literal_string)?;
 
                 match &mut module.dialect {
                     crate::ModuleDialect::Spv(dialect) => {
+                        if dialect.extensions.contains(&ext)
+                            && options.reject_duplicate_declarations
+                        {
+                            return Err(error(LowerErrorKind::DuplicateExtension(ext)));
+                        }
                         dialect.extensions.insert(ext);
                     }
                 }
@@ -324,8 +666,14 @@ impl Module {
             } else if opcode == wk.OpExtInstImport {
                 assert!(inst.result_type_id.is_none() && inst.ids.is_empty());
                 let id = inst.result_id.unwrap();
-                let name = spv::extract_literal_string(&inst.imms)
-                    .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?;
+                let name =
+                    spv::extract_literal_string(&inst.imms).map_err(malformed_literal_string)?;
+
+                if let Some(allowed_ext_inst_sets) = &options.allowed_ext_inst_sets {
+                    if !allowed_ext_inst_sets.contains(&name) {
+                        return Err(error(LowerErrorKind::DisallowedExtInstSet(name)));
+                    }
+                }
 
                 id_defs.insert(id, IdDef::SpvExtInstImport(cx.intern(name)));
 
@@ -341,7 +689,7 @@ impl Module {
                 };
 
                 if has_memory_model {
-                    return Err(invalid("duplicate OpMemoryModel"));
+                    return Err(error(LowerErrorKind::DuplicateMemoryModel));
                 }
                 has_memory_model = true;
 
@@ -356,8 +704,8 @@ impl Module {
             } else if opcode == wk.OpString {
                 assert!(inst.result_type_id.is_none() && inst.ids.is_empty());
                 let id = inst.result_id.unwrap();
-                let s = spv::extract_literal_string(&inst.imms)
-                    .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?;
+                let s =
+                    spv::extract_literal_string(&inst.imms).map_err(malformed_literal_string)?;
 
                 id_defs.insert(id, IdDef::SpvDebugString(cx.intern(s)));
 
@@ -385,25 +733,21 @@ impl Module {
                     (contents, &[file_path_id]) => {
                         let file_path = match id_defs.get(&file_path_id) {
                             Some(&IdDef::SpvDebugString(s)) => s,
-                            _ => {
-                                return Err(invalid(&format!(
-                                    "%{file_path_id} is not an OpString"
-                                )));
-                            }
+                            _ => return Err(error(LowerErrorKind::FileNotOpString(file_path_id))),
                         };
                         let mut contents = if contents.is_empty() {
                             String::new()
                         } else {
                             spv::extract_literal_string(contents)
-                                .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?
+                                .map_err(malformed_literal_string)?
                         };
 
                         // Absorb all following `OpSourceContinued` into `contents`.
-                        while let Some(Ok(cont_inst)) = spv_insts.peek() {
+                        while let Some(Ok((_, cont_inst))) = spv_insts.peek() {
                             if cont_inst.opcode != wk.OpSourceContinued {
                                 break;
                             }
-                            let cont_inst = spv_insts.next().unwrap().unwrap();
+                            let (_, cont_inst) = spv_insts.next().unwrap().unwrap();
 
                             assert!(
                                 cont_inst.result_type_id.is_none()
@@ -411,13 +755,16 @@ impl Module {
                                     && cont_inst.ids.is_empty()
                             );
                             let cont_contents = spv::extract_literal_string(&cont_inst.imms)
-                                .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?;
+                                .map_err(malformed_literal_string)?;
                             contents += &cont_contents;
                         }
 
                         debug_sources.file_contents.insert(file_path, contents);
                     }
                     (&[], &[]) => {}
+                    // FIXME: support this, e.g. by allowing `file_contents`
+                    // entries without an actual file path.
+                    (_, &[]) => return Err(invalid("source text without a file is unsupported")),
                     _ => unreachable!(),
                 }
 
@@ -432,8 +779,8 @@ impl Module {
                         && inst.result_id.is_none()
                         && inst.ids.is_empty()
                 );
-                let ext = spv::extract_literal_string(&inst.imms)
-                    .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?;
+                let ext =
+                    spv::extract_literal_string(&inst.imms).map_err(malformed_literal_string)?;
 
                 match &mut module.debug_info {
                     crate::ModuleDebugInfo::Spv(debug_info) => {
@@ -450,8 +797,8 @@ impl Module {
                         && inst.result_id.is_none()
                         && inst.ids.is_empty()
                 );
-                let proc = spv::extract_literal_string(&inst.imms)
-                    .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?;
+                let proc =
+                    spv::extract_literal_string(&inst.imms).map_err(malformed_literal_string)?;
 
                 match &mut module.debug_info {
                     crate::ModuleDebugInfo::Spv(debug_info) => {
@@ -465,6 +812,23 @@ impl Module {
             } else if opcode == wk.OpEntryPoint {
                 assert!(inst.result_type_id.is_none() && inst.result_id.is_none());
 
+                if options.strict {
+                    let execution_model = match inst.imms[..] {
+                        [imm @ spv::Imm::Short(..), ..] => {
+                            spv::ExecutionModel::from_imm(imm).unwrap()
+                        }
+                        _ => unreachable!(),
+                    };
+                    let name = spv::extract_literal_string(&inst.imms[1..])
+                        .map_err(malformed_literal_string)?;
+                    if !entry_point_models_and_names.insert((execution_model, name.clone())) {
+                        return Err(error(LowerErrorKind::DuplicateEntryPoint {
+                            execution_model,
+                            name,
+                        }));
+                    }
+                }
+
                 pending_exports.push(Export::EntryPoint {
                     func_id: inst.ids[0],
                     imms: inst.without_ids.imms,
@@ -474,12 +838,12 @@ impl Module {
                 Seq::EntryPoint
             } else if [
                 wk.OpExecutionMode,
-                wk.OpExecutionModeId, // FIXME(eddyb) not actually supported
+                wk.OpExecutionModeId,
                 wk.OpName,
                 wk.OpMemberName,
                 wk.OpDecorate,
                 wk.OpMemberDecorate,
-                wk.OpDecorateId, // FIXME(eddyb) not actually supported
+                wk.OpDecorateId,
                 wk.OpDecorateString,
                 wk.OpMemberDecorateString,
             ]
@@ -488,11 +852,19 @@ impl Module {
                 assert!(inst.result_type_id.is_none() && inst.result_id.is_none());
 
                 let target_id = inst.ids[0];
-                if inst.ids.len() > 1 {
-                    return Err(invalid("unsupported decoration with ID"));
+                if inst.ids.len() > 1 && ![wk.OpExecutionModeId, wk.OpDecorateId].contains(&opcode)
+                {
+                    return Err(error(LowerErrorKind::UnsupportedIdDecoration { opcode }));
                 }
 
                 match inst.imms[..] {
+                    _ if inst.ids.len() > 1 => {
+                        pending_annotations_with_ids
+                            .entry(target_id)
+                            .or_default()
+                            .push((inst.without_ids, inst.ids[1..].iter().copied().collect()));
+                    }
+
                     // Special-case `OpDecorate LinkageAttributes ... Import|Export`.
                     [
                         decoration @ spv::Imm::Short(..),
@@ -503,8 +875,8 @@ impl Module {
                         && lt_kind == wk.LinkageType
                         && [wk.Import, wk.Export].contains(&linkage_type) =>
                     {
-                        let name = spv::extract_literal_string(name)
-                            .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?;
+                        let name =
+                            spv::extract_literal_string(name).map_err(malformed_literal_string)?;
                         let name = cx.intern(name);
 
                         if linkage_type == wk.Import {
@@ -541,9 +913,19 @@ impl Module {
                     _ => unreachable!(),
                 };
 
+                if id_defs.contains_key(&id) || pending_forward_ptrs.insert(id, sc).is_some() {
+                    return Err(invalid(&format!(
+                        "OpTypeForwardPointer for already declared %{id}"
+                    )));
+                }
+
                 // HACK(eddyb) this is not a proper implementation - one would
                 // require fixpoint (aka "μ" aka "mu") types - but for now this
                 // serves as a first approximation for a "deferred error".
+                // NOTE: uses before the `OpTypePointer` completing this
+                // (see `pending_forward_ptrs`) get this placeholder type, while
+                // later uses get the actual pointer type, as interned types
+                // can't form cycles (e.g. for recursive structs).
                 let ty = cx.intern(TypeDef {
                     attrs: mem::take(&mut attrs),
                     kind: TypeKind::SpvInst {
@@ -557,6 +939,14 @@ impl Module {
             } else if inst_category == spec::InstructionCategory::Type {
                 assert!(inst.result_type_id.is_none());
                 let id = inst.result_id.unwrap();
+                if let Some(forward_sc) = pending_forward_ptrs.remove(&id) {
+                    if opcode != wk.OpTypePointer || inst.imms[..] != [forward_sc] {
+                        return Err(invalid(&format!(
+                            "%{id} doesn't match its OpTypeForwardPointer \
+                             (expected OpTypePointer with the same storage class)"
+                        )));
+                    }
+                }
                 let type_and_const_inputs = inst
                     .ids
                     .iter()
@@ -698,8 +1088,13 @@ impl Module {
                 let (func_type_ret_type, func_type_param_types) =
                     match id_defs.get(&func_type_id) {
                         Some(&IdDef::Type(ty)) => match &cx[ty].kind {
+                            // NOTE: constants are only allowed in other
+                            // types (e.g. as the length of `OpTypeArray`).
                             TypeKind::SpvInst { spv_inst, type_and_const_inputs }
-                                if spv_inst.opcode == wk.OpTypeFunction =>
+                                if spv_inst.opcode == wk.OpTypeFunction
+                                    && type_and_const_inputs.iter().all(|ty_or_ct| {
+                                        matches!(ty_or_ct, TypeOrConst::Type(_))
+                                    }) =>
                             {
                                 let mut types =
                                     type_and_const_inputs.iter().map(|&ty_or_ct| match ty_or_ct {
@@ -805,12 +1200,19 @@ impl Module {
             };
             if let Some(prev_seq) = seq {
                 if prev_seq > next_seq {
-                    return Err(invalid(&format!(
-                        "out of order: {next_seq:?} instructions must precede {prev_seq:?} instructions"
-                    )));
+                    let e = error(LowerErrorKind::OutOfOrderInstruction {
+                        found: next_seq,
+                        after: prev_seq,
+                    });
+                    if options.strict_ordering {
+                        return Err(e);
+                    }
+                    warnings.push(e);
                 }
             }
-            seq = Some(next_seq);
+            // NOTE: `max` only matters in lenient mode, where going back
+            // to an earlier section is allowed, but shouldn't suppress warnings.
+            seq = seq.max(Some(next_seq));
 
             if attrs != Default::default() {
                 return Err(invalid("unused decorations / line debuginfo"));
@@ -818,18 +1220,40 @@ impl Module {
         }
 
         if !has_memory_model {
-            return Err(invalid("missing OpMemoryModel"));
+            return Err(LowerError::new(LowerErrorKind::MissingMemoryModel));
         }
 
-        if !pending_attrs.is_empty() {
-            let ids = pending_attrs.keys().collect::<BTreeSet<_>>();
-            return Err(invalid(&format!("decorated IDs never defined: {ids:?}")));
+        if options.require_capability_dependencies {
+            let crate::ModuleDialect::Spv(dialect) = &module.dialect;
+            if let Some((implied_by, needed)) = dialect.missing_capability_dependencies().next() {
+                return Err(LowerError::new(LowerErrorKind::MissingCapabilityDependency {
+                    needed,
+                    implied_by,
+                }));
+            }
+        }
+
+        if !pending_attrs.is_empty() || !pending_annotations_with_ids.is_empty() {
+            let ids = pending_attrs
+                .keys()
+                .chain(pending_annotations_with_ids.keys())
+                .copied()
+                .collect::<BTreeSet<_>>();
+            return Err(LowerError::new(LowerErrorKind::UndefinedDecoratedIds(
+                ids.into_iter().collect(),
+            )));
         }
 
         if current_func_body.is_some() {
             return Err(invalid("OpFunction without matching OpFunctionEnd"));
         }
 
+        if let Some(&id) = pending_forward_ptrs.keys().min() {
+            return Err(invalid(&format!(
+                "OpTypeForwardPointer without matching OpTypePointer (for %{id})"
+            )));
+        }
+
         // HACK(eddyb) `OpNop` is useful for defining `DataInst`s before they're
         // actually lowered (to be able to refer to their outputs `Value`s).
         let mut cached_op_nop_form = None;
@@ -971,7 +1395,10 @@ impl Module {
                                 LocalIdDef::Value(Value::DataInstOutput(inst))
                             }
                         };
-                        local_id_defs.insert(id, local_id_def);
+                        if local_id_defs.insert(id, local_id_def).is_some() {
+                            return Err(LowerError::new(LowerErrorKind::DuplicateLocalId(id))
+                                .in_inst(opcode, None));
+                        }
                     }
                 }
             }
@@ -1017,7 +1444,7 @@ impl Module {
                     ref ids,
                 } = *raw_inst;
 
-                let invalid = |msg: &str| invalid(&format!("in {}: {}", opcode.name(), msg));
+                let invalid = |msg: &str| invalid(msg).in_inst(opcode, None);
 
                 // FIXME(eddyb) find a more compact name and/or make this a method.
                 // FIXME(eddyb) this returns `LocalIdDef` even for global values.
@@ -1141,7 +1568,7 @@ impl Module {
                             ))),
                         }
                     };
-                    let mut record_cfg_edge = |target_block| -> io::Result<()> {
+                    let mut record_cfg_edge = |target_block| -> Result<(), LowerError> {
                         use indexmap::map::Entry;
 
                         let target_block_details = &block_details[&target_block];
@@ -1203,21 +1630,36 @@ impl Module {
                         }
                     }
 
+                    // NOTE: only the number of operands is guaranteed
+                    // by the SPIR-V grammar, not whether they're labels.
+                    let expect_operands = |expected_targets, expected_inputs| {
+                        if (targets.len(), inputs.len()) == (expected_targets, expected_inputs) {
+                            Ok(())
+                        } else {
+                            Err(invalid(&format!(
+                                "expected {expected_targets} target label(s) \
+                                 and {expected_inputs} value(s), found {} and {}",
+                                targets.len(),
+                                inputs.len()
+                            )))
+                        }
+                    };
+
                     let kind = if opcode == wk.OpUnreachable {
-                        assert!(targets.is_empty() && inputs.is_empty());
+                        expect_operands(0, 0)?;
                         cfg::ControlInstKind::Unreachable
                     } else if [wk.OpReturn, wk.OpReturnValue].contains(&opcode) {
-                        assert!(targets.is_empty() && inputs.len() <= 1);
+                        expect_operands(0, usize::from(opcode == wk.OpReturnValue))?;
                         cfg::ControlInstKind::Return
                     } else if targets.is_empty() {
                         cfg::ControlInstKind::ExitInvocation(cfg::ExitInvocationKind::SpvInst(
                             raw_inst.without_ids.clone(),
                         ))
                     } else if opcode == wk.OpBranch {
-                        assert_eq!((targets.len(), inputs.len()), (1, 0));
+                        expect_operands(1, 0)?;
                         cfg::ControlInstKind::Branch
                     } else if opcode == wk.OpBranchConditional {
-                        assert_eq!((targets.len(), inputs.len()), (2, 1));
+                        expect_operands(2, 1)?;
                         cfg::ControlInstKind::SelectBranch(SelectionKind::BoolCond)
                     } else if opcode == wk.OpSwitch {
                         cfg::ControlInstKind::SelectBranch(SelectionKind::SpvInst(
@@ -1367,7 +1809,7 @@ impl Module {
                                     )),
                                 }
                             })
-                            .collect::<io::Result<_>>()?,
+                            .collect::<Result<_, LowerError>>()?,
                     };
                     let inst = match result_id {
                         Some(id) => match local_id_defs[&id] {
@@ -1551,8 +1993,423 @@ impl Module {
                     ))
                 }
             })
-            .collect::<io::Result<_>>()?;
+            .collect::<Result<_, LowerError>>()?;
 
-        Ok(module)
+        Ok((module, warnings))
     }
 }
+
+#[test]
+fn identical_decorations_share_attr_set() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            OpDecorate %1 LinkageAttributes "a" Export
+            OpDecorate %2 LinkageAttributes "b" Export
+            OpDecorate %1 RelaxedPrecision
+            OpDecorate %2 RelaxedPrecision
+            %3 = OpTypeFloat 32
+            %4 = OpTypePointer Private %3
+            %1 = OpVariable %4 Private
+            %2 = OpVariable %4 Private
+            "#,
+    );
+    let module = Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words).unwrap();
+
+    let attrs: Vec<_> = module
+        .exports
+        .values()
+        .map(|&exportee| match exportee {
+            Exportee::GlobalVar(gv) => module.global_vars[gv].attrs,
+            Exportee::Func(_) => unreachable!(),
+        })
+        .collect();
+    assert_eq!(attrs.len(), 2);
+    assert!(attrs[0] == attrs[1]);
+    assert!(!module.cx_ref()[attrs[0]].attrs.is_empty());
+}
+
+#[test]
+fn drop_nops() {
+    let spv_src_with_nops = r#"
+        OpCapability Shader
+        OpNop
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpNop
+        OpDecorate %1 LinkageAttributes "foo" Export
+        %2 = OpTypeVoid
+        OpNop
+        %3 = OpTypeFunction %2
+        %1 = OpFunction %2 None %3
+        %4 = OpLabel
+        OpNop
+        OpNop
+        OpReturn
+        OpFunctionEnd
+        "#;
+
+    let lower = |spv_words: &[u32]| {
+        let parser = spv::read::ModuleParser::read_from_spv_words(spv_words).unwrap();
+        let options = LowerOptions { drop_nops: true, ..LowerOptions::default() };
+        let (module, warnings) =
+            Module::lower_from_spv_module_parser_with(Rc::new(Context::new()), parser, options)
+                .unwrap();
+        assert!(warnings.is_empty());
+        module.lift_to_spv_words().unwrap()
+    };
+    let lifted_words = lower(&spv::test_asm::assemble(spv_src_with_nops));
+    let spv_src_without_nops = spv_src_with_nops.replace("OpNop", "");
+    assert!(lifted_words == lower(&spv::test_asm::assemble(&spv_src_without_nops)));
+
+    let wk = &spec::Spec::get().well_known;
+    assert!(
+        spv::read::ModuleParser::read_from_spv_words(&lifted_words)
+            .unwrap()
+            .all(|inst| inst.unwrap().opcode != wk.OpNop)
+    );
+}
+
+#[test]
+fn generator_tool_from_header() {
+    let lower_with_generator = |generator_magic| {
+        let mut spv_words = spv::test_asm::assemble(
+            r#"
+                OpCapability Shader
+                OpMemoryModel Logical GLSL450
+                "#,
+        );
+        spv_words[2] = generator_magic;
+        let module = Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words).unwrap();
+        let crate::ModuleDebugInfo::Spv(debug_info) = module.debug_info;
+        (debug_info.generator_tool(), debug_info.generator_version())
+    };
+
+    let (tool, version) = lower_with_generator((8 << 16) | 11);
+    assert_eq!((tool, version), (Some(spv::GeneratorTool::Glslang), Some(11)));
+    assert_eq!(tool.unwrap().to_string(), "glslang");
+
+    let (tool, _) = lower_with_generator(0xabcd_0001);
+    assert_eq!(tool, Some(spv::GeneratorTool::Unknown(0xabcd)));
+    assert_eq!(tool.unwrap().id(), 0xabcd);
+
+    assert_eq!(lower_with_generator(0), (None, None));
+}
+
+#[test]
+fn forward_pointer_linked_list() {
+    let lower_linked_list = |ptr_storage_class: Option<&str>| {
+        let complete_ptr = match ptr_storage_class {
+            Some(storage_class) => format!("%1 = OpTypePointer {storage_class} %3"),
+            None => String::new(),
+        };
+        let spv_words = spv::test_asm::assemble(&format!(
+            r#"
+                OpCapability Shader
+                OpCapability PhysicalStorageBufferAddresses
+                OpMemoryModel PhysicalStorageBuffer64 GLSL450
+                OpTypeForwardPointer %1 PhysicalStorageBuffer
+                %2 = OpTypeInt 32 0
+                %3 = OpTypeStruct %2 %1
+                {complete_ptr}
+                "#
+        ));
+        Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words)
+    };
+
+    assert!(lower_linked_list(Some("PhysicalStorageBuffer")).is_ok());
+
+    let err = lower_linked_list(Some("StorageBuffer")).err().unwrap();
+    assert!(err.to_string().contains("doesn't match its OpTypeForwardPointer"));
+
+    let err = lower_linked_list(None).err().unwrap();
+    assert!(err.to_string().contains("OpTypeForwardPointer without matching OpTypePointer"));
+}
+
+#[test]
+fn original_header_is_kept() {
+    let mut spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            "#,
+    );
+    // Header version (1.3), generator (`glslang`, version 11), and ID bound.
+    spv_words[1..4].copy_from_slice(&[0x0001_0300, (8 << 16) | 11, 42]);
+    let module = Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words).unwrap();
+    let (crate::ModuleDialect::Spv(dialect), crate::ModuleDebugInfo::Spv(debug_info)) =
+        (&module.dialect, &module.debug_info);
+    assert_eq!(debug_info.original_header(dialect).unwrap(), spv_words[..spec::HEADER_LEN]);
+
+    // NOTE: lifting always computes its own ID bound.
+    let lifted_words = module.lift_to_spv_words().unwrap();
+    assert_eq!(lifted_words[3], 1);
+}
+
+#[test]
+fn random_words_never_panic() {
+    // NOTE: this is a simple xorshift PRNG (to avoid depending on `rand`),
+    // used to generate "instruction-shaped" words (i.e. with a valid word count,
+    // and operands below the ID bound), as fully random words would be rejected
+    // immediately by the parser, without reaching much of the lowering code.
+    let mut state = 0x2545_f491_u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+
+    for _ in 0..1000 {
+        let id_bound = 16;
+        let mut spv_words = spv::test_asm::assemble(
+            r#"
+                OpCapability Shader
+                OpMemoryModel Logical GLSL450
+                "#,
+        );
+        spv_words[3] = id_bound;
+        for _ in 0..next() % 32 {
+            let operand_count = next() % 6;
+            let opcode = next() % 400;
+            spv_words.push(((operand_count + 1) << 16) | opcode);
+            spv_words.extend((0..operand_count).map(|_| next() % id_bound));
+        }
+
+        // Only panics (not errors) are considered failures.
+        let _ = Module::lower_from_spv_words(Rc::new(Context::new()), &spv_words);
+    }
+}
+
+#[test]
+fn allowed_ext_inst_sets() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        %1 = OpExtInstImport "GLSL.std.450"
+        OpMemoryModel Logical GLSL450
+        "#,
+    );
+    let lower = |allowed_ext_inst_sets: &[&str]| {
+        let parser = spv::read::ModuleParser::read_from_spv_words(&spv_words).unwrap();
+        let options = LowerOptions {
+            allowed_ext_inst_sets: Some(
+                allowed_ext_inst_sets.iter().map(|&name| name.to_string()).collect(),
+            ),
+            ..LowerOptions::default()
+        };
+        Module::lower_from_spv_module_parser_with(Rc::new(Context::new()), parser, options)
+    };
+
+    lower(&["GLSL.std.450", "NonSemantic.DebugPrintf"]).unwrap();
+    let err = lower(&["NonSemantic.DebugPrintf"]).err().unwrap();
+    assert!(
+        matches!(&err.kind, LowerErrorKind::DisallowedExtInstSet(name) if name == "GLSL.std.450")
+    );
+}
+
+#[test]
+fn strict_rejects_duplicate_entry_points() {
+    let lower = |execution_models: [&str; 2], strict: bool| {
+        let spv_words = spv::test_asm::assemble(&format!(
+            r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            OpEntryPoint {} %a "main"
+            OpEntryPoint {} %b "main"
+            OpExecutionMode %a OriginUpperLeft
+            %void = OpTypeVoid
+            %func_type = OpTypeFunction %void
+            %a = OpFunction %void None %func_type
+            %a_entry = OpLabel
+            OpReturn
+            OpFunctionEnd
+            %b = OpFunction %void None %func_type
+            %b_entry = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#,
+            execution_models[0], execution_models[1]
+        ));
+        let parser = spv::read::ModuleParser::read_from_spv_words(&spv_words).unwrap();
+        let options = LowerOptions { strict, ..LowerOptions::default() };
+        Module::lower_from_spv_module_parser_with(Rc::new(Context::new()), parser, options)
+    };
+
+    // NOTE: identical interfaces make for identical `ExportKey`s.
+    assert_eq!(lower(["Fragment", "Fragment"], false).unwrap().0.exports.len(), 1);
+    assert_eq!(lower(["Fragment", "Vertex"], true).unwrap().0.exports.len(), 2);
+    let err = lower(["Fragment", "Fragment"], true).err().unwrap();
+    assert!(matches!(
+        &err.kind,
+        LowerErrorKind::DuplicateEntryPoint { execution_model: spv::ExecutionModel::Fragment, name }
+            if name == "main"
+    ));
+}
+
+#[test]
+fn ids_out_of_bounds_are_rejected() {
+    let mut spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpMemoryModel Logical GLSL450
+        %1 = OpTypeInt 32 0
+        %2 = OpTypeFloat 32
+        "#,
+    );
+    // NOTE: `%2` is the first ID at (or above) this (too low) ID bound.
+    spv_words[3] = 2;
+    let parser = spv::read::ModuleParser::read_from_spv_words(&spv_words).unwrap();
+    let err = Module::lower_from_spv_module_parser_with(
+        Rc::new(Context::new()),
+        parser,
+        LowerOptions::default(),
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(
+        err.kind,
+        LowerErrorKind::IdOutOfBounds { id, id_bound: 2 } if id.get() == 2
+    ));
+    // Header (5 words), `OpCapability` (2), `OpMemoryModel` (3), `OpTypeInt` (4).
+    assert_eq!(err.word_offset, Some(5 + 2 + 3 + 4));
+}
+
+#[test]
+fn lenient_ordering_warns() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpMemoryModel Logical GLSL450
+        OpCapability Int64
+        "#,
+    );
+    let lower = |strict_ordering: bool| {
+        let parser = spv::read::ModuleParser::read_from_spv_words(&spv_words).unwrap();
+        let options = LowerOptions { strict_ordering, ..LowerOptions::default() };
+        Module::lower_from_spv_module_parser_with(Rc::new(Context::new()), parser, options)
+    };
+    let is_capability_after_memory_model = |e: &LowerError| {
+        matches!(
+            e.kind,
+            LowerErrorKind::OutOfOrderInstruction {
+                found: Seq::Capability,
+                after: Seq::MemoryModel
+            }
+        ) && e.word_offset == Some(5 + 2 + 3)
+    };
+
+    let err = lower(true).err().unwrap();
+    assert!(is_capability_after_memory_model(&err));
+
+    let (module, warnings) = lower(false).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(is_capability_after_memory_model(&warnings[0]));
+
+    // The out of order instruction is still lowered.
+    let crate::ModuleDialect::Spv(dialect) = &module.dialect;
+    let int64 = spv::CapabilityDiff::capability_by_name("Int64").unwrap();
+    assert!(dialect.capabilities.contains(&int64));
+}
+
+#[test]
+fn reject_duplicate_declarations() {
+    let lower = |decls: &str, reject_duplicate_declarations: bool| {
+        let spv_words = spv::test_asm::assemble(&format!(
+            r#"
+            OpCapability Shader
+            {decls}
+            OpMemoryModel Logical GLSL450
+            "#
+        ));
+        let parser = spv::read::ModuleParser::read_from_spv_words(&spv_words).unwrap();
+        let options = LowerOptions { reject_duplicate_declarations, ..LowerOptions::default() };
+        Module::lower_from_spv_module_parser_with(Rc::new(Context::new()), parser, options)
+    };
+
+    let duplicate_capability = "OpCapability Shader";
+    let duplicate_extension = r#"
+        OpExtension "SPV_KHR_storage_buffer_storage_class"
+        OpExtension "SPV_KHR_storage_buffer_storage_class"
+    "#;
+
+    // Duplicates are ignored by default.
+    for decls in [duplicate_capability, duplicate_extension] {
+        let (module, _) = lower(decls, false).unwrap();
+        let crate::ModuleDialect::Spv(dialect) = &module.dialect;
+        assert_eq!(dialect.capabilities.len(), 1);
+        assert!(dialect.extensions.len() <= 1);
+    }
+
+    let shader = spv::CapabilityDiff::capability_by_name("Shader").unwrap();
+    let err = lower(duplicate_capability, true).err().unwrap();
+    assert!(matches!(err.kind, LowerErrorKind::DuplicateCapability(cap) if cap == shader));
+
+    let err = lower(duplicate_extension, true).err().unwrap();
+    assert!(matches!(
+        &err.kind,
+        LowerErrorKind::DuplicateExtension(ext) if ext == "SPV_KHR_storage_buffer_storage_class"
+    ));
+}
+
+#[test]
+fn require_capability_dependencies() {
+    let lower = |capabilities: &str, require_capability_dependencies: bool| {
+        let spv_words = spv::test_asm::assemble(&format!(
+            r#"
+            {capabilities}
+            OpMemoryModel Logical GLSL450
+            "#
+        ));
+        let parser = spv::read::ModuleParser::read_from_spv_words(&spv_words).unwrap();
+        let options = LowerOptions { require_capability_dependencies, ..LowerOptions::default() };
+        Module::lower_from_spv_module_parser_with(Rc::new(Context::new()), parser, options)
+    };
+
+    // NOTE: `Shader` depends on `Matrix`, which SPIR-V treats as implied.
+    lower("OpCapability Shader", false).unwrap();
+    lower("OpCapability Shader\nOpCapability Matrix", true).unwrap();
+
+    let [shader, matrix] =
+        ["Shader", "Matrix"].map(|name| spv::CapabilityDiff::capability_by_name(name).unwrap());
+    let err = lower("OpCapability Shader", true).err().unwrap();
+    assert!(matches!(
+        err.kind,
+        LowerErrorKind::MissingCapabilityDependency { needed, implied_by }
+            if (needed, implied_by) == (matrix, shader)
+    ));
+}
+
+#[test]
+fn line_file_must_be_op_string() {
+    let lower = |file: &str| {
+        let spv_words = spv::test_asm::assemble(&format!(
+            r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            %file = OpString "file.glsl"
+            %int = OpTypeInt 32 0
+            OpLine {file} 1 1
+            %uint = OpTypeInt 32 1
+            "#
+        ));
+        let parser = spv::read::ModuleParser::read_from_spv_words(&spv_words).unwrap();
+        Module::lower_from_spv_module_parser_with(
+            Rc::new(Context::new()),
+            parser,
+            LowerOptions::default(),
+        )
+    };
+
+    lower("%file").unwrap();
+
+    let err = lower("%int").err().unwrap();
+    let LowerErrorKind::FileNotOpString(id) = err.kind else {
+        panic!("expected `FileNotOpString`, found: {err}");
+    };
+    // NOTE: `%int` is the 2nd ID defined (after `%file`).
+    assert_eq!(id.get(), 2);
+    assert!(err.opcode == Some(spec::Spec::get().well_known.OpLine));
+}
@@ -2,22 +2,30 @@
 
 // NOTE(eddyb) all the modules are declared here, but they're documented "inside"
 // (i.e. using inner doc comments).
+pub mod coverage;
 pub mod lift;
 pub mod lower;
 pub mod print;
+pub mod query;
 pub mod read;
 pub mod spec;
+#[cfg(test)]
+pub(crate) mod test_asm;
+pub mod validate;
 pub mod write;
 
 use crate::{FxIndexMap, InternedStr};
+use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
-use std::iter;
 use std::num::NonZeroU32;
 use std::string::FromUtf8Error;
+use std::{fmt, io, iter};
 
 /// Semantic properties of a SPIR-V module (not tied to any IDs).
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Dialect {
     pub version_major: u8,
     pub version_minor: u8,
@@ -29,16 +37,478 @@ pub struct Dialect {
     pub memory_model: u32,
 }
 
+/// SPIR-V version (major and minor), ordered such that e.g. `SpvVersion(1, 4)`
+/// is less than `SpvVersion(1, 5)` (see also [`Dialect::version`]).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpvVersion(pub u8, pub u8);
+
+impl fmt::Display for SpvVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.0, self.1)
+    }
+}
+
+macro_rules! def_value_enums {
+    ($($(#[doc = $doc:literal])* enum $enum_name:ident { $($name:ident),+ $(,)? })+) => {
+        $(
+            $(#[doc = $doc])*
+            ///
+            /// The values of all variants (other than `Other`) are taken from [`spec::Spec`].
+            #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+            pub enum $enum_name {
+                $($name,)+
+
+                /// Any value not listed above (e.g. from a newer grammar).
+                Other(u32),
+            }
+
+            impl $enum_name {
+                /// Look up the value of the variant named `name`, in [`spec::Spec`].
+                fn value_of_name(name: &str) -> Option<u32> {
+                    let spv_spec = spec::Spec::get();
+                    match &spv_spec.operand_kinds[spv_spec.well_known.$enum_name] {
+                        spec::OperandKindDef::ValueEnum { variants } => {
+                            variants.lookup(name).map(u32::from)
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+
+                pub fn from_u32(value: u32) -> Self {
+                    // NOTE: this compares values (instead of looking up
+                    // the name of `value`), as the grammar may contain aliases
+                    // (e.g. `VulkanKHR` for the `Vulkan` memory model).
+                    $(if Self::value_of_name(stringify!($name)) == Some(value) {
+                        return Self::$name;
+                    })+
+                    Self::Other(value)
+                }
+
+                pub fn to_u32(self) -> u32 {
+                    let name = match self {
+                        $(Self::$name => stringify!($name),)+
+                        Self::Other(value) => return value,
+                    };
+                    Self::value_of_name(name).unwrap()
+                }
+            }
+
+            impl fmt::Display for $enum_name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    match self {
+                        $(Self::$name => f.write_str(stringify!($name)),)+
+                        Self::Other(value) => write!(f, "{value}"),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+// FIXME: consider generating these lists from the grammar (stably, as
+// removing a variant is a breaking change, even if the grammar removed it).
+def_value_enums! {
+    /// SPIR-V `ExecutionModel` (i.e. the kind of an entry-point).
+    enum ExecutionModel {
+        Vertex,
+        TessellationControl,
+        TessellationEvaluation,
+        Geometry,
+        Fragment,
+        GLCompute,
+        Kernel,
+        TaskNV,
+        MeshNV,
+        RayGenerationKHR,
+        IntersectionKHR,
+        AnyHitKHR,
+        ClosestHitKHR,
+        MissKHR,
+        CallableKHR,
+        TaskEXT,
+        MeshEXT,
+    }
+
+    /// SPIR-V `AddressingModel` (see [`Dialect::addressing_model`]).
+    enum AddressingModel {
+        Logical,
+        Physical32,
+        Physical64,
+        PhysicalStorageBuffer64,
+    }
+
+    /// SPIR-V `MemoryModel` (see [`Dialect::memory_model`]).
+    enum MemoryModel {
+        Simple,
+        GLSL450,
+        OpenCL,
+        Vulkan,
+    }
+}
+
+impl ExecutionModel {
+    /// Decode an `ExecutionModel` immediate operand (e.g. of `OpEntryPoint`).
+    pub fn from_imm(imm: Imm) -> Option<Self> {
+        match imm {
+            Imm::Short(kind, value) if kind == spec::Spec::get().well_known.ExecutionModel => {
+                Some(Self::from_u32(value))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_imm(self) -> Imm {
+        Imm::Short(spec::Spec::get().well_known.ExecutionModel, self.to_u32())
+    }
+}
+
+/// Comparison of the capabilities of two [`Dialect`]s (see [`Dialect::capability_diff`]).
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct CapabilityDiff {
+    pub only_self: BTreeSet<u32>,
+    pub only_other: BTreeSet<u32>,
+    pub shared: BTreeSet<u32>,
+}
+
+impl CapabilityDiff {
+    /// Resolve the name of a capability (e.g. `Shader`), using [`spec::Spec`].
+    pub fn capability_name(cap: u32) -> Option<&'static str> {
+        let spv_spec = spec::Spec::get();
+        spv_spec.enumerant_name(spv_spec.well_known.Capability, cap)
+    }
+
+    /// Look up a capability by its name (e.g. `Shader`), using [`spec::Spec`].
+    pub fn capability_by_name(name: &str) -> Option<u32> {
+        let spv_spec = spec::Spec::get();
+        spv_spec.enumerant_by_name(spv_spec.well_known.Capability, name)
+    }
+
+    // FIXME: unknown capabilities are printed as their numeric value.
+    fn names(caps: &BTreeSet<u32>) -> impl Iterator<Item = Cow<'static, str>> + '_ {
+        caps.iter().map(|&cap| {
+            Self::capability_name(cap).map_or_else(|| cap.to_string().into(), Cow::from)
+        })
+    }
+
+    pub fn only_self_names(&self) -> impl Iterator<Item = Cow<'static, str>> + '_ {
+        Self::names(&self.only_self)
+    }
+    pub fn only_other_names(&self) -> impl Iterator<Item = Cow<'static, str>> + '_ {
+        Self::names(&self.only_other)
+    }
+    pub fn shared_names(&self) -> impl Iterator<Item = Cow<'static, str>> + '_ {
+        Self::names(&self.shared)
+    }
+}
+
+impl Dialect {
+    pub fn version(&self) -> SpvVersion {
+        SpvVersion(self.version_major, self.version_minor)
+    }
+
+    /// Typed version of the `addressing_model` field.
+    pub fn addressing_model(&self) -> AddressingModel {
+        AddressingModel::from_u32(self.addressing_model)
+    }
+
+    /// Typed version of the `memory_model` field.
+    pub fn memory_model(&self) -> MemoryModel {
+        MemoryModel::from_u32(self.memory_model)
+    }
+
+    /// Compare the capabilities of `self` and `other`, e.g. to find out which
+    /// capabilities would be added by merging the two (i.e. `only_other`).
+    pub fn capability_diff(&self, other: &Dialect) -> CapabilityDiff {
+        CapabilityDiff {
+            only_self: self.capabilities.difference(&other.capabilities).copied().collect(),
+            only_other: other.capabilities.difference(&self.capabilities).copied().collect(),
+            shared: self.capabilities.intersection(&other.capabilities).copied().collect(),
+        }
+    }
+
+    /// All the `(implied_by, needed)` pairs of capabilities, where the declared
+    /// capability `implied_by` depends on the undeclared capability `needed`
+    /// (see [`spec::Spec::capability_dependencies`]), ordered by `implied_by`.
+    ///
+    /// Note that SPIR-V itself treats such dependencies as implicitly declared,
+    /// so these are only relevant to stricter tooling.
+    pub fn missing_capability_dependencies(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let spv_spec = spec::Spec::get();
+        self.capabilities.iter().flat_map(move |&cap| {
+            let deps = spv_spec.capability_dependencies.get(&cap).into_iter().flatten();
+            deps.filter(|dep| !self.capabilities.contains(dep)).map(move |&needed| (cap, needed))
+        })
+    }
+}
+
+/// Information from the start of a SPIR-V module, see [`scan_header`].
+#[derive(Clone)]
+pub struct HeaderInfo {
+    pub dialect: Dialect,
+
+    pub generator_magic: Option<NonZeroU32>,
+
+    /// All IDs in the module are below this bound.
+    pub id_bound: u32,
+}
+
+/// Read only the header (and the instructions defining the [`Dialect`], i.e.
+/// `OpCapability`, `OpExtension` and `OpMemoryModel`) of a SPIR-V module,
+/// stopping after `OpMemoryModel`, without lowering the module to SPIR-T.
+///
+/// For other kinds of cheap inspection, [`read::ModuleParser`] can also be
+/// used directly, as an iterator of [`InstWithIds`]s.
+pub fn scan_header(parser: read::ModuleParser) -> io::Result<HeaderInfo> {
+    let wk = &spec::Spec::get().well_known;
+
+    let invalid = |reason: &str| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("malformed SPIR-V ({reason})"))
+    };
+
+    let [_, version, generator_magic, id_bound, _] = parser.header;
+    let [_, version_major, version_minor, _] = version.to_be_bytes();
+    let mut dialect = Dialect {
+        version_major,
+        version_minor,
+
+        capabilities: BTreeSet::new(),
+        extensions: BTreeSet::new(),
+
+        addressing_model: 0,
+        memory_model: 0,
+    };
+
+    for inst in parser {
+        let inst = inst?;
+        match inst.imms[..] {
+            [Imm::Short(_, cap)] if inst.opcode == wk.OpCapability => {
+                dialect.capabilities.insert(cap);
+            }
+            _ if inst.opcode == wk.OpExtension => {
+                let ext = extract_literal_string(&inst.imms)
+                    .map_err(|e| invalid(&format!("{} in {:?}", e, e.as_bytes())))?;
+                dialect.extensions.insert(ext);
+            }
+            // NOTE: `OpExtInstImport`s are between extensions and
+            // `OpMemoryModel`, but not part of the `Dialect`.
+            _ if inst.opcode == wk.OpExtInstImport => {}
+            [Imm::Short(_, am), Imm::Short(_, mm)] if inst.opcode == wk.OpMemoryModel => {
+                dialect.addressing_model = am;
+                dialect.memory_model = mm;
+                return Ok(HeaderInfo {
+                    dialect,
+                    generator_magic: NonZeroU32::new(generator_magic),
+                    id_bound,
+                });
+            }
+            _ => break,
+        }
+    }
+    Err(invalid("missing OpMemoryModel"))
+}
+
+/// One SPIR-V instruction (as read by [`read::ModuleParser`]), along with
+/// human-readable details (e.g. its operands printed), see [`inst_views`].
+pub struct InstView {
+    pub inst: InstWithIds,
+
+    /// The opcode name (e.g. `"OpTypeInt"`).
+    pub mnemonic: &'static str,
+
+    /// Every (logical) operand of `inst`, printed as plain text, with IDs
+    /// printed as `%name` (using `OpName`, if seen) or `%123` (otherwise).
+    ///
+    /// The result type/ID are not included (see `result_type` and `result`).
+    pub operands: Vec<String>,
+
+    /// `inst.result_type_id`, printed like the IDs in `operands`.
+    pub result_type: Option<String>,
+
+    /// `inst.result_id`, printed like the IDs in `operands`.
+    pub result: Option<String>,
+
+    /// `OpDecorate`s (seen so far) targeting `inst.result_id`, printed as
+    /// plain text (e.g. `"Binding(0)"`).
+    pub decorations: Vec<String>,
+}
+
+/// Read every instruction from `parser`, as an [`InstView`], resolving IDs
+/// to the names given to them by `OpName`, and tracking `OpDecorate`s.
+///
+/// This is meant for ad-hoc inspection (e.g. quick textual dumps), and relies
+/// on the SPIR-V logical layout (which places `OpName`s and `OpDecorate`s
+/// before all definitions) to avoid buffering the whole module, i.e. names and
+/// decorations are only resolved for instructions *after* them.
+//
+// FIXME: also handle `OpMemberName`, `OpMemberDecorate` and `OpDecorateId`.
+pub fn inst_views(parser: read::ModuleParser) -> impl Iterator<Item = io::Result<InstView>> {
+    let wk = &spec::Spec::get().well_known;
+
+    let mut names = FxHashMap::<Id, String>::default();
+    let mut decorations = FxHashMap::<Id, Vec<String>>::default();
+    parser.map(move |inst| {
+        let inst = inst?;
+
+        if inst.opcode == wk.OpName {
+            if let (&[target], Ok(name)) = (&inst.ids[..], extract_literal_string(&inst.imms)) {
+                names.insert(target, name);
+            }
+        } else if inst.opcode == wk.OpDecorate {
+            if let &[target] = &inst.ids[..] {
+                decorations.entry(target).or_default().push(
+                    print::operand_from_imms(inst.imms.iter().copied()).concat_to_plain_text(),
+                );
+            }
+        }
+
+        let id_to_string = |id: Id| match names.get(&id) {
+            Some(name) => format!("%{name}"),
+            None => format!("%{id}"),
+        };
+        Ok(InstView {
+            mnemonic: inst.opcode.name(),
+            operands: print::inst_operands(
+                inst.opcode,
+                inst.imms.iter().copied(),
+                inst.ids.iter().copied().map(id_to_string),
+            )
+            .map(|operand| operand.concat_to_plain_text())
+            .collect(),
+            result_type: inst.result_type_id.map(id_to_string),
+            result: inst.result_id.map(id_to_string),
+            decorations: inst
+                .result_id
+                .and_then(|id| decorations.get(&id))
+                .cloned()
+                .unwrap_or_default(),
+            inst,
+        })
+    })
+}
+
 /// Non-semantic details (i.e. debuginfo) of a SPIR-V module (not tied to any IDs).
 #[derive(Clone)]
 pub struct ModuleDebugInfo {
     pub original_generator_magic: Option<NonZeroU32>,
 
+    /// The ID bound from the header of the original module (if lowered from one).
+    ///
+    /// This is purely informational (e.g. for reconstructing the original
+    /// header, see [`ModuleDebugInfo::original_header`]), and never used by
+    /// lifting, which always computes its own ID bound.
+    pub original_id_bound: Option<NonZeroU32>,
+
     pub source_languages: BTreeMap<DebugSourceLang, DebugSources>,
     pub source_extensions: Vec<String>,
+
+    /// `OpModuleProcessed` strings (i.e. which tools processed the module),
+    /// in their original order, with new entries expected to be appended.
     pub module_processes: Vec<String>,
 }
 
+impl ModuleDebugInfo {
+    /// The tool that produced the original module, decoded from the high 16
+    /// bits of `original_generator_magic` (if the module had a generator at all).
+    pub fn generator_tool(&self) -> Option<GeneratorTool> {
+        let magic = self.original_generator_magic?.get();
+        Some(GeneratorTool::from_id((magic >> 16) as u16))
+    }
+
+    /// The (tool-specific) version of the tool that produced the original module,
+    /// i.e. the low 16 bits of `original_generator_magic` (see also [`Self::generator_tool`]).
+    pub fn generator_version(&self) -> Option<u16> {
+        Some(self.original_generator_magic?.get() as u16)
+    }
+
+    /// Reconstruct the header of the original module (if lowered from one),
+    /// using the version from `dialect` (which only matches the original one
+    /// if it wasn't changed since, e.g. by [`set_version`](crate::passes::version::set_version)).
+    pub fn original_header(&self, dialect: &Dialect) -> Option<[u32; spec::HEADER_LEN]> {
+        let SpvVersion(major, minor) = dialect.version();
+        Some([
+            spec::Spec::get().magic,
+            u32::from_be_bytes([0, major, minor, 0]),
+            self.original_generator_magic.map_or(0, |x| x.get()),
+            self.original_id_bound?.get(),
+            0,
+        ])
+    }
+}
+
+/// Tool that produced a SPIR-V module, as identified by the high 16 bits of
+/// its generator magic number, registered with Khronos (see `spir-v.xml` in
+/// the SPIR-V registry, for the full list of IDs).
+///
+/// Only some of the more commonly encountered tools are known, any others
+/// are represented by their ID, as `GeneratorTool::Unknown`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GeneratorTool {
+    LlvmSpirvTranslator,
+    SpirvToolsAssembler,
+    Glslang,
+    Shaderc,
+    Dxc,
+    Rspirv,
+    SpirvToolsLinker,
+    Vkd3d,
+    Clspv,
+    MlirSpirvSerializer,
+    Tint,
+    Angle,
+    RustGpu,
+    Naga,
+
+    Unknown(u16),
+}
+
+impl GeneratorTool {
+    /// Known tools, with their registered IDs and (short) names.
+    const KNOWN: &'static [(u16, GeneratorTool, &'static str)] = &[
+        (6, Self::LlvmSpirvTranslator, "LLVM/SPIR-V Translator"),
+        (7, Self::SpirvToolsAssembler, "SPIR-V Tools Assembler"),
+        (8, Self::Glslang, "glslang"),
+        (13, Self::Shaderc, "shaderc"),
+        (14, Self::Dxc, "DXC"),
+        (15, Self::Rspirv, "rspirv"),
+        (17, Self::SpirvToolsLinker, "SPIR-V Tools Linker"),
+        (18, Self::Vkd3d, "VKD3D Shader Compiler"),
+        (21, Self::Clspv, "clspv"),
+        (22, Self::MlirSpirvSerializer, "MLIR SPIR-V Serializer"),
+        (23, Self::Tint, "Tint"),
+        (24, Self::Angle, "ANGLE"),
+        (27, Self::RustGpu, "Rust-GPU"),
+        (28, Self::Naga, "Naga"),
+    ];
+
+    pub fn from_id(id: u16) -> Self {
+        Self::KNOWN
+            .iter()
+            .find(|&&(known_id, ..)| known_id == id)
+            .map_or(Self::Unknown(id), |&(_, tool, _)| tool)
+    }
+
+    pub fn id(self) -> u16 {
+        match self {
+            Self::Unknown(id) => id,
+            _ => Self::KNOWN.iter().find(|&&(_, tool, _)| tool == self).unwrap().0,
+        }
+    }
+
+    /// Human-readable name of the tool (`None` for `GeneratorTool::Unknown`).
+    pub fn name(self) -> Option<&'static str> {
+        Self::KNOWN.iter().find(|&&(_, tool, _)| tool == self).map(|&(.., name)| name)
+    }
+}
+
+impl fmt::Display for GeneratorTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "unknown tool #{}", self.id()),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DebugSourceLang {
     pub lang: u32,
@@ -50,6 +520,19 @@ pub struct DebugSources {
     pub file_contents: FxIndexMap<InternedStr, String>,
 }
 
+/// One SPIR-V `OpSource` (see [`Module::source_records`](crate::Module::source_records)).
+#[derive(Copy, Clone)]
+pub struct SourceRecord<'a> {
+    pub lang: &'a DebugSourceLang,
+
+    /// The `OpString` file path, if the `OpSource` has one.
+    pub file: Option<InternedStr>,
+
+    /// The source text of `file` (possibly continued with `OpSourceContinued`),
+    /// which is empty if the `OpSource` doesn't include it (or has no `file`).
+    pub contents: &'a str,
+}
+
 /// A SPIR-V instruction, in its minimal form (opcode and immediate operands).
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Inst {
@@ -69,6 +552,71 @@ impl From<spec::Opcode> for Inst {
     }
 }
 
+/// Common SPIR-V decorations (i.e. `Decoration` operands, with their parameter),
+/// as parsed from annotations by [`Attr::spv_decoration`](crate::Attr::spv_decoration)
+/// (and [`Attr::spv_member_decoration`](crate::Attr::spv_member_decoration)).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Decoration {
+    Location(u32),
+    Binding(u32),
+    DescriptorSet(u32),
+
+    /// The parameter is a `BuiltIn` enumerand (e.g. `Position`).
+    BuiltIn(u32),
+
+    Offset(u32),
+    ArrayStride(u32),
+}
+
+impl Decoration {
+    /// Parse `imms` (of e.g. an `OpDecorate`, after its target), i.e. a
+    /// `Decoration` operand, followed by its single parameter.
+    fn from_imms(imms: &[Imm]) -> Option<Self> {
+        let wk = &spec::Spec::get().well_known;
+        let (decoration, value) = match *imms {
+            [Imm::Short(kind, decoration), Imm::Short(_, value)] if kind == wk.Decoration => {
+                (decoration, value)
+            }
+            _ => return None,
+        };
+        Some(match decoration {
+            d if d == wk.Location => Self::Location(value),
+            d if d == wk.Binding => Self::Binding(value),
+            d if d == wk.DescriptorSet => Self::DescriptorSet(value),
+            d if d == wk.BuiltIn => Self::BuiltIn(value),
+            d if d == wk.Offset => Self::Offset(value),
+            d if d == wk.ArrayStride => Self::ArrayStride(value),
+            _ => return None,
+        })
+    }
+}
+
+impl Inst {
+    /// Parse an `OpDecorate` of one of the common [`Decoration`]s, if `self` is one.
+    pub fn decoration(&self) -> Option<Decoration> {
+        let wk = &spec::Spec::get().well_known;
+        if self.opcode != wk.OpDecorate {
+            return None;
+        }
+        Decoration::from_imms(&self.imms)
+    }
+
+    /// Parse an `OpMemberDecorate` of one of the common [`Decoration`]s, if
+    /// `self` is one, returning the index of the decorated member, as well.
+    pub fn member_decoration(&self) -> Option<(u32, Decoration)> {
+        let wk = &spec::Spec::get().well_known;
+        if self.opcode != wk.OpMemberDecorate {
+            return None;
+        }
+        match self.imms[..] {
+            [Imm::Short(_, member_idx), ref rest @ ..] => {
+                Some((member_idx, Decoration::from_imms(rest)?))
+            }
+            _ => None,
+        }
+    }
+}
+
 /// A full SPIR-V instruction (like [`Inst`], but including input/output ID operands).
 pub struct InstWithIds {
     pub without_ids: Inst,
@@ -170,3 +718,63 @@ pub fn encode_literal_string(s: &str) -> impl Iterator<Item = Imm> + '_ {
         },
     )
 }
+
+#[test]
+fn capability_diff_shader_and_float64() {
+    let dialect = |extra_capabilities: &str| {
+        let spv_words = test_asm::assemble(&format!(
+            "
+            OpCapability Shader
+            {extra_capabilities}
+            OpMemoryModel Logical GLSL450
+            "
+        ));
+        scan_header(read::ModuleParser::read_from_spv_words(&spv_words).unwrap()).unwrap().dialect
+    };
+    let shader_only = dialect("");
+    let with_float64 = dialect("OpCapability Float64");
+
+    let diff = shader_only.capability_diff(&with_float64);
+    assert!(diff.only_self.is_empty());
+    assert_eq!(diff.only_other_names().collect::<Vec<_>>(), ["Float64"]);
+    assert_eq!(diff.shared_names().collect::<Vec<_>>(), ["Shader"]);
+
+    // Swapping the two sides only swaps `only_self` and `only_other`.
+    let reverse_diff = with_float64.capability_diff(&shader_only);
+    assert!(reverse_diff.only_self == diff.only_other);
+    assert!(reverse_diff.only_other.is_empty());
+    assert!(reverse_diff.shared == diff.shared);
+}
+
+#[test]
+fn inst_views_resolve_names_and_decorations() {
+    let spv_words = test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpMemoryModel Logical GLSL450
+        OpName %buf "buf"
+        OpDecorate %buf DescriptorSet 0
+        OpDecorate %buf Binding 1
+        %int = OpTypeInt 32 0
+        %ptr = OpTypePointer Uniform %int
+        %buf = OpVariable %ptr Uniform
+        "#,
+    );
+    let views: Vec<_> = inst_views(read::ModuleParser::read_from_spv_words(&spv_words).unwrap())
+        .map(|view| view.unwrap())
+        .collect();
+    let [.., int, ptr, buf] = &views[..] else { unreachable!() };
+
+    // NOTE: IDs without an `OpName` are printed numerically.
+    assert_eq!((int.mnemonic, int.result.as_deref()), ("OpTypeInt", Some("%2")));
+    assert_eq!(int.operands, ["Width: 32", "Signedness: 0"]);
+    assert_eq!(ptr.operands, ["StorageClass.Uniform", "Type: %2"]);
+    assert!(int.decorations.is_empty() && ptr.decorations.is_empty());
+
+    assert_eq!(buf.mnemonic, "OpVariable");
+    assert_eq!((buf.result_type.as_deref(), buf.result.as_deref()), (Some("%3"), Some("%buf")));
+    assert_eq!(
+        buf.decorations,
+        ["Decoration.DescriptorSet(Descriptor Set: 0)", "Decoration.Binding(Binding Point: 1)"]
+    );
+}
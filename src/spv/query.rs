@@ -0,0 +1,503 @@
+//! SPIR-V-specific queries (e.g. for reflection) on SPIR-T [`Module`]s and [`Attr`]s,
+//! i.e. interpreting the parts of SPIR-T that are only generic SPIR-V instructions.
+
+use crate::visit::{ReachableUseCollector, Visitor};
+use crate::{
+    spv, Attr, Const, ConstKind, DataInstKind, ExportKey, Exportee, Func, FxIndexSet, GlobalVar,
+    GlobalVarDecl, Module, ModuleDebugInfo, ModuleDialect, Type,
+};
+use smallvec::SmallVec;
+use std::collections::BTreeSet;
+
+impl Module {
+    /// SPIR-V capabilities declared by the module (see also [`spv::Dialect`]).
+    pub fn capabilities(&self) -> &BTreeSet<u32> {
+        match &self.dialect {
+            ModuleDialect::Spv(dialect) => &dialect.capabilities,
+        }
+    }
+
+    /// SPIR-V extensions declared by the module (see also [`spv::Dialect`]).
+    pub fn extensions(&self) -> impl Iterator<Item = &str> {
+        match &self.dialect {
+            ModuleDialect::Spv(dialect) => dialect.extensions.iter().map(|ext| &ext[..]),
+        }
+    }
+
+    pub fn has_capability(&self, cap: u32) -> bool {
+        self.capabilities().contains(&cap)
+    }
+
+    /// Names of all the SPIR-V extended instruction sets (`OpExtInstImport`s)
+    /// used by (`OpExtInst`s in) functions reachable from the module's exports.
+    pub fn ext_inst_imports(&self) -> impl Iterator<Item = &str> {
+        let cx = self.cx_ref();
+        let mut collector = ReachableUseCollector::new(self);
+        collector.visit_module_exports();
+
+        // NOTE: `OpExtInst`s can only appear in function bodies.
+        let ext_inst_sets: FxIndexSet<_> = collector
+            .seen_data_inst_forms
+            .iter()
+            .filter_map(|&data_inst_form| match cx[data_inst_form].kind {
+                DataInstKind::SpvExtInst { ext_set, .. } => Some(ext_set),
+                _ => None,
+            })
+            .collect();
+        ext_inst_sets.into_iter().map(move |name| &cx[name])
+    }
+
+    /// All the SPIR-V specialization constants (i.e. `OpSpecConstant*`) used by
+    /// (i.e. reachable from the exports of) the module (see [`SpecConstantInfo`]).
+    pub fn spec_constants(&self) -> impl Iterator<Item = SpecConstantInfo> + '_ {
+        let cx = self.cx_ref();
+        let wk = &spv::spec::Spec::get().well_known;
+        self.collect_consts().into_iter().filter_map(move |ct| {
+            let ct_def = &cx[ct];
+            let spv_inst = match &ct_def.kind {
+                ConstKind::SpvInst { spv_inst_and_const_inputs } => &spv_inst_and_const_inputs.0,
+                _ => return None,
+            };
+            let default = if spv_inst.opcode == wk.OpSpecConstantTrue {
+                SpecConstantDefault::Bool(true)
+            } else if spv_inst.opcode == wk.OpSpecConstantFalse {
+                SpecConstantDefault::Bool(false)
+            } else if spv_inst.opcode == wk.OpSpecConstant {
+                SpecConstantDefault::Literal(spv_inst.imms.clone())
+            } else if [wk.OpSpecConstantComposite, wk.OpSpecConstantOp].contains(&spv_inst.opcode) {
+                SpecConstantDefault::Derived
+            } else {
+                return None;
+            };
+            let spec_id = cx[ct_def.attrs].attrs.iter().find_map(|attr| match attr {
+                Attr::SpvAnnotation(spv::Inst { opcode, imms }) if *opcode == wk.OpDecorate => {
+                    match imms[..] {
+                        [spv::Imm::Short(_, decoration), spv::Imm::Short(_, spec_id)]
+                            if decoration == wk.SpecId =>
+                        {
+                            Some(spec_id)
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            });
+            Some(SpecConstantInfo { ct, spec_id, ty: ct_def.ty, default })
+        })
+    }
+
+    /// Iterate over all the [`ExportKey::SpvEntryPoint`] exports, in order.
+    pub fn entry_points(&self) -> impl Iterator<Item = EntryPointInfo<'_>> {
+        self.exports.iter().filter_map(|(export_key, &exportee)| match (export_key, exportee) {
+            (ExportKey::SpvEntryPoint { imms, interface_global_vars }, Exportee::Func(func)) => {
+                let execution_model = match imms[..] {
+                    [imm @ spv::Imm::Short(..), ..] => spv::ExecutionModel::from_imm(imm).unwrap(),
+                    _ => unreachable!(),
+                };
+                let name = spv::extract_literal_string(&imms[1..])
+                    .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+                Some(EntryPointInfo { execution_model, name, func, interface_global_vars })
+            }
+            _ => None,
+        })
+    }
+
+    /// The SPIR-V `OpName` of a [`GlobalVar`] or [`Func`] (i.e. `exportee`,
+    /// which doesn't have to be exported), if it has one (and it's valid UTF-8).
+    pub fn debug_name_of(&self, exportee: Exportee) -> Option<String> {
+        let attrs = match exportee {
+            Exportee::GlobalVar(gv) => self.global_vars[gv].attrs,
+            Exportee::Func(func) => self.funcs[func].attrs,
+        };
+        let wk = &spv::spec::Spec::get().well_known;
+        self.cx_ref()[attrs].attrs.iter().find_map(|attr| match attr {
+            Attr::SpvAnnotation(spv_inst) if spv_inst.opcode == wk.OpName => {
+                spv::extract_literal_string(&spv_inst.imms).ok()
+            }
+            _ => None,
+        })
+    }
+
+    /// All the SPIR-V `OpSource` records of this module (of which there can be
+    /// several, e.g. one per source language, after merging modules compiled
+    /// from different languages), in the order they will be lifted in.
+    pub fn source_records(&self) -> impl Iterator<Item = spv::SourceRecord<'_>> {
+        let ModuleDebugInfo::Spv(debug_info) = &self.debug_info;
+        debug_info.source_languages.iter().flat_map(|(lang, sources)| {
+            let records = sources.file_contents.iter().map(move |(&file, contents)| {
+                spv::SourceRecord { lang, file: Some(file), contents }
+            });
+            // NOTE: an `OpSource` without any file is still a record.
+            let no_file_record = sources.file_contents.is_empty().then_some(spv::SourceRecord {
+                lang,
+                file: None,
+                contents: "",
+            });
+            records.chain(no_file_record)
+        })
+    }
+
+    /// The SPIR-V `OpMemberName` of the member `member_idx` of the struct type
+    /// `ty`, if it has one (and it's valid UTF-8).
+    pub fn member_name_of(&self, ty: Type, member_idx: u32) -> Option<String> {
+        let cx = self.cx_ref();
+        let wk = &spv::spec::Spec::get().well_known;
+        cx[cx[ty].attrs].attrs.iter().find_map(|attr| match attr {
+            Attr::SpvAnnotation(spv_inst) if spv_inst.opcode == wk.OpMemberName => {
+                match spv_inst.imms[..] {
+                    [spv::Imm::Short(_, idx), ref name @ ..] if idx == member_idx => {
+                        spv::extract_literal_string(name).ok()
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+    }
+
+    /// Call `f` for every [`GlobalVar`] in the interface of `entry_point`, i.e.
+    /// every global variable statically used by it, along with its declaration
+    /// (e.g. for reflecting on its type, storage class and decorations).
+    ///
+    /// Before SPIR-V 1.4, `OpEntryPoint` only lists `Input`/`Output` variables
+    /// (see [`EntryPointInfo::interface_global_vars`]), so any other global
+    /// variables are found by traversing the entry-point function (and its
+    /// callees), and visited after the listed ones.
+    /// Since SPIR-V 1.4, all of them are listed, so only the list is used.
+    pub fn for_each_interface_var(
+        &self,
+        entry_point: &EntryPointInfo<'_>,
+        mut f: impl FnMut(GlobalVar, &GlobalVarDecl),
+    ) {
+        let mut global_vars: FxIndexSet<_> =
+            entry_point.interface_global_vars.iter().copied().collect();
+
+        let ModuleDialect::Spv(dialect) = &self.dialect;
+        if dialect.version() < spv::SpvVersion(1, 4) {
+            // NOTE: `AttrSet`s can't be ignored here, as they can refer
+            // to `Const`s (which can refer to `GlobalVar`s).
+            let mut collector = ReachableUseCollector::new(self);
+            collector.visit_attr_sets = true;
+            collector.visit_func_use(entry_point.func);
+            global_vars.extend(collector.seen_global_vars);
+        }
+
+        for gv in global_vars {
+            f(gv, &self.global_vars[gv]);
+        }
+    }
+}
+
+/// Information about a SPIR-V specialization constant (i.e. `OpSpecConstant*`),
+/// as returned by [`Module::spec_constants`].
+pub struct SpecConstantInfo {
+    pub ct: Const,
+
+    /// The value of the SPIR-V `SpecId` decoration (needed to specialize it),
+    /// or `None` if the constant has no `SpecId` (e.g. `OpSpecConstantOp`).
+    pub spec_id: Option<u32>,
+
+    pub ty: Type,
+
+    /// The default value, used when the constant isn't specialized.
+    pub default: SpecConstantDefault,
+}
+
+/// The default value of a SPIR-V specialization constant (see [`SpecConstantInfo`]).
+#[derive(Clone)]
+pub enum SpecConstantDefault {
+    /// `OpSpecConstantTrue`/`OpSpecConstantFalse`.
+    Bool(bool),
+
+    /// `OpSpecConstant` (i.e. a scalar literal, of the constant's type).
+    Literal(SmallVec<[spv::Imm; 2]>),
+
+    /// `OpSpecConstantComposite`/`OpSpecConstantOp`, whose values are computed
+    /// from other constants (which may themselves be specialization constants).
+    Derived,
+}
+
+/// Information about an entry-point, i.e. an [`ExportKey::SpvEntryPoint`] export
+/// (see [`Module::entry_points`]).
+pub struct EntryPointInfo<'a> {
+    /// SPIR-V `ExecutionModel` (e.g. `Vertex`, `Fragment`, `GLCompute`, etc.).
+    pub execution_model: spv::ExecutionModel,
+
+    /// The name of the entry-point (decoded lossily, if not valid UTF-8).
+    pub name: String,
+
+    /// The function called when the entry-point is invoked.
+    pub func: Func,
+
+    /// The global variables making up the interface of the entry-point.
+    pub interface_global_vars: &'a [GlobalVar],
+}
+
+impl Attr {
+    /// Parse a SPIR-V `OpDecorate` of one of the common [`spv::Decoration`]s,
+    /// if `self` is one (e.g. to get the numeric value of a `Binding`).
+    pub fn spv_decoration(&self) -> Option<spv::Decoration> {
+        match self {
+            Attr::SpvAnnotation(spv_inst) => spv_inst.decoration(),
+            _ => None,
+        }
+    }
+
+    /// Like [`Attr::spv_decoration`], but for `OpMemberDecorate` (returning
+    /// the index of the decorated member, as well).
+    pub fn spv_member_decoration(&self) -> Option<(u32, spv::Decoration)> {
+        match self {
+            Attr::SpvAnnotation(spv_inst) => spv_inst.member_decoration(),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn spv_decoration_accessors() {
+    let wk = &spv::spec::Spec::get().well_known;
+    let decorate = |opcode, imms: &[spv::Imm]| {
+        Attr::SpvAnnotation(spv::Inst { opcode, imms: imms.iter().copied().collect() })
+    };
+
+    let binding = decorate(
+        wk.OpDecorate,
+        &[spv::Imm::Short(wk.Decoration, wk.Binding), spv::Imm::Short(wk.LiteralInteger, 3)],
+    );
+    assert_eq!(binding.spv_decoration(), Some(spv::Decoration::Binding(3)));
+    assert_eq!(binding.spv_member_decoration(), None);
+
+    let offset = decorate(
+        wk.OpMemberDecorate,
+        &[
+            spv::Imm::Short(wk.LiteralInteger, 1),
+            spv::Imm::Short(wk.Decoration, wk.Offset),
+            spv::Imm::Short(wk.LiteralInteger, 16),
+        ],
+    );
+    assert_eq!(offset.spv_decoration(), None);
+    assert_eq!(offset.spv_member_decoration(), Some((1, spv::Decoration::Offset(16))));
+
+    // Decorations without a parameter aren't supported.
+    let block = decorate(wk.OpDecorate, &[spv::Imm::Short(wk.Decoration, wk.Block)]);
+    assert_eq!(block.spv_decoration(), None);
+}
+
+#[test]
+fn entry_points() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpMemoryModel Logical GLSL450
+        OpEntryPoint Fragment %frag "main" %color
+        OpEntryPoint GLCompute %comp "compute"
+        OpExecutionMode %frag OriginUpperLeft
+        OpExecutionMode %comp LocalSize 1 1 1
+        %void = OpTypeVoid
+        %float = OpTypeFloat 32
+        %out_float_ptr = OpTypePointer Output %float
+        %color = OpVariable %out_float_ptr Output
+        %func_type = OpTypeFunction %void
+        %frag = OpFunction %void None %func_type
+        %frag_entry = OpLabel
+        OpReturn
+        OpFunctionEnd
+        %comp = OpFunction %void None %func_type
+        %comp_entry = OpLabel
+        OpReturn
+        OpFunctionEnd
+        "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(crate::Context::new()), &spv_words).unwrap();
+
+    let entry_points: Vec<_> = module.entry_points().collect();
+    assert_eq!(entry_points.len(), 2);
+    let [frag, comp] = [&entry_points[0], &entry_points[1]];
+    assert_eq!(
+        (frag.execution_model, &frag.name[..], frag.interface_global_vars.len()),
+        (spv::ExecutionModel::Fragment, "main", 1)
+    );
+    assert_eq!(
+        (comp.execution_model, &comp.name[..], comp.interface_global_vars.len()),
+        (spv::ExecutionModel::GLCompute, "compute", 0)
+    );
+    assert!(frag.func != comp.func);
+
+    let color = frag.interface_global_vars[0];
+    assert!(matches!(
+        module.global_vars[color].addr_space,
+        crate::AddrSpace::SpvStorageClass(sc) if sc == spv::spec::Spec::get().well_known.Output
+    ));
+}
+
+#[test]
+fn capabilities_and_extensions() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpCapability Int64
+        OpExtension "SPV_KHR_storage_buffer_storage_class"
+        OpMemoryModel Logical GLSL450
+        "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(crate::Context::new()), &spv_words).unwrap();
+
+    let [shader, int64, matrix] = ["Shader", "Int64", "Matrix"]
+        .map(|name| spv::CapabilityDiff::capability_by_name(name).unwrap());
+    assert_eq!(*module.capabilities(), BTreeSet::from([shader, int64]));
+    assert!(module.has_capability(shader) && module.has_capability(int64));
+
+    // NOTE: implied capabilities (`Shader` depends on `Matrix`) aren't included.
+    assert!(!module.has_capability(matrix));
+
+    assert_eq!(module.extensions().collect::<Vec<_>>(), ["SPV_KHR_storage_buffer_storage_class"]);
+}
+
+#[test]
+fn spec_constants() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpDecorate %foo LinkageAttributes "foo" Export
+        OpDecorate %cond SpecId 3
+        OpDecorate %size SpecId 7
+        OpDecorate %unused SpecId 9
+        %bool = OpTypeBool
+        %int = OpTypeInt 32 0
+        %int2 = OpTypeVector %int 2
+        %cond = OpSpecConstantTrue %bool
+        %size = OpSpecConstant %int 42
+        %unused = OpSpecConstant %int 5
+        %one = OpConstant %int 1
+        %sizes = OpSpecConstantComposite %int2 %size %one
+        %func_type = OpTypeFunction %int2
+        %foo = OpFunction %int2 None %func_type
+        %entry = OpLabel
+        %result = OpSelect %int2 %cond %sizes %sizes
+        OpReturnValue %result
+        OpFunctionEnd
+        "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(crate::Context::new()), &spv_words).unwrap();
+    let wk = &spv::spec::Spec::get().well_known;
+
+    // NOTE: `%one` isn't a specialization constant, and `%unused` isn't reachable.
+    let mut spec_constants: Vec<_> = module.spec_constants().collect();
+    assert_eq!(spec_constants.len(), 3);
+    spec_constants.sort_by_key(|info| info.spec_id);
+
+    let [sizes, cond, size] = &spec_constants[..] else { unreachable!() };
+    assert!(matches!((sizes.spec_id, &sizes.default), (None, SpecConstantDefault::Derived)));
+    assert!(matches!((cond.spec_id, &cond.default), (Some(3), SpecConstantDefault::Bool(true))));
+    assert!(matches!(
+        (size.spec_id, &size.default),
+        (Some(7), SpecConstantDefault::Literal(imms))
+            if imms[..] == [spv::Imm::Short(wk.LiteralContextDependentNumber, 42)]
+    ));
+}
+
+#[test]
+fn for_each_interface_var() {
+    let lower = |version: u32| {
+        let mut spv_words = spv::test_asm::assemble(
+            r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            OpEntryPoint Fragment %main "main" %input
+            OpExecutionMode %main OriginUpperLeft
+            %void = OpTypeVoid
+            %float = OpTypeFloat 32
+            %in_float_ptr = OpTypePointer Input %float
+            %private_float_ptr = OpTypePointer Private %float
+            %input = OpVariable %in_float_ptr Input
+            %private = OpVariable %private_float_ptr Private
+            %unused = OpVariable %private_float_ptr Private
+            %func_type = OpTypeFunction %void
+            %main = OpFunction %void None %func_type
+            %main_entry = OpLabel
+            %x = OpLoad %float %input
+            %call = OpFunctionCall %void %helper
+            OpReturn
+            OpFunctionEnd
+            %helper = OpFunction %void None %func_type
+            %helper_entry = OpLabel
+            %y = OpLoad %float %private
+            OpReturn
+            OpFunctionEnd
+            "#,
+        );
+        spv_words[1] = version;
+        Module::lower_from_spv_words(std::rc::Rc::new(crate::Context::new()), &spv_words).unwrap()
+    };
+    let spv_spec = spv::spec::Spec::get();
+    let storage_classes_of_interface_vars = |module: &Module| {
+        let entry_point = module.entry_points().next().unwrap();
+        let mut storage_classes = vec![];
+        module.for_each_interface_var(&entry_point, |_, gv_decl| match gv_decl.addr_space {
+            crate::AddrSpace::SpvStorageClass(sc) => storage_classes
+                .push(spv_spec.enumerant_name(spv_spec.well_known.StorageClass, sc).unwrap()),
+            crate::AddrSpace::Handles => unreachable!(),
+        });
+        storage_classes
+    };
+
+    // NOTE: before SPIR-V 1.4, `%private` is only found through `%helper`,
+    // while `%unused` isn't used at all (by the entry-point, or otherwise).
+    assert_eq!(storage_classes_of_interface_vars(&lower(0x0001_0000)), ["Input", "Private"]);
+
+    // NOTE: since SPIR-V 1.4, only the listed variables are visited (i.e. this
+    // module is invalid for SPIR-V 1.4, which requires listing `%private` too).
+    assert_eq!(storage_classes_of_interface_vars(&lower(0x0001_0400)), ["Input"]);
+}
+
+#[test]
+fn debug_and_member_names() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpName %foo "foo_func"
+        OpName %counter "counter"
+        OpName %Pair "Pair"
+        OpMemberName %Pair 1 "second"
+        OpDecorate %foo LinkageAttributes "foo" Export
+        %int = OpTypeInt 32 0
+        %Pair = OpTypeStruct %int %int
+        %private_int_ptr = OpTypePointer Private %int
+        %counter = OpVariable %private_int_ptr Private
+        %func_type = OpTypeFunction %Pair
+        %foo = OpFunction %Pair None %func_type
+        %entry = OpLabel
+        %x = OpLoad %int %counter
+        %call = OpFunctionCall %Pair %helper
+        OpReturnValue %call
+        OpFunctionEnd
+        %helper = OpFunction %Pair None %func_type
+        %helper_entry = OpLabel
+        %pair = OpUndef %Pair
+        OpReturnValue %pair
+        OpFunctionEnd
+        "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(crate::Context::new()), &spv_words).unwrap();
+
+    let mut collector = ReachableUseCollector::new(&module);
+    collector.visit_module_exports();
+    let [foo, helper] = [0, 1].map(|i| collector.seen_funcs[i]);
+    let counter = collector.seen_global_vars[0];
+
+    assert_eq!(module.debug_name_of(Exportee::Func(foo)).as_deref(), Some("foo_func"));
+    assert_eq!(module.debug_name_of(Exportee::GlobalVar(counter)).as_deref(), Some("counter"));
+    assert_eq!(module.debug_name_of(Exportee::Func(helper)), None);
+
+    let pair = module.funcs[foo].ret_type;
+    assert_eq!(module.member_name_of(pair, 1).as_deref(), Some("second"));
+    assert_eq!(module.member_name_of(pair, 0), None);
+}
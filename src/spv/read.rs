@@ -4,7 +4,9 @@ use crate::spv::{self, spec};
 use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
 use std::borrow::Cow;
+use std::io::Read as _;
 use std::num::NonZeroU32;
+use std::ops::Range;
 use std::path::Path;
 use std::{fs, io, iter, slice};
 
@@ -26,6 +28,9 @@ impl KnownIdDef {
     }
 }
 
+/// Operand spans of one instruction (see [`InstSpans`]).
+type OperandSpans = SmallVec<[OperandSpan; 4]>;
+
 // FIXME(eddyb) keep a `&'static spec::Spec` if that can even speed up anything.
 struct InstParser<'a> {
     /// IDs defined so far in the module.
@@ -36,6 +41,13 @@ struct InstParser<'a> {
 
     /// Output instruction, being parsed.
     inst: spv::InstWithIds,
+
+    /// Position (in words, from the start of the module) right after the
+    /// last word of the instruction, used to compute `operand_spans`.
+    words_end: usize,
+
+    /// Output operand spans (only recorded if present, see [`InstSpans`]).
+    operand_spans: Option<OperandSpans>,
 }
 
 enum InstParseError {
@@ -104,6 +116,11 @@ impl InstParser<'_> {
         self.words.len() == 0
     }
 
+    /// Position (in words, from the start of the module) of the next word.
+    fn word_position(&self) -> usize {
+        self.words_end - self.words.len()
+    }
+
     fn enumerant_params(&mut self, enumerant: &spec::Enumerant) -> Result<(), InstParseError> {
         for (mode, kind) in enumerant.all_params() {
             if mode == spec::OperandMode::Optional && self.is_exhausted() {
@@ -118,6 +135,12 @@ impl InstParser<'_> {
     fn operand(&mut self, kind: spec::OperandKind) -> Result<(), InstParseError> {
         use InstParseError as Error;
 
+        let start = self.word_position();
+        let span_idx = self.operand_spans.as_mut().map(|spans| {
+            spans.push(OperandSpan { kind, words: start..start + 1 });
+            spans.len() - 1
+        });
+
         let word = self.words.next().ok_or(Error::NotEnoughWords)?;
         match kind.def() {
             spec::OperandKindDef::BitEnum { bits, .. } => {
@@ -198,10 +221,21 @@ impl InstParser<'_> {
             }
         }
 
+        // NOTE: enumerands are always one word, and their parameters
+        // (if any) were already recorded as spans of their own, above.
+        if let (Some(spans), Some(span_idx)) = (&mut self.operand_spans, span_idx) {
+            if let spec::OperandKindDef::Literal { .. } = kind.def() {
+                spans[span_idx].words.end = self.words_end - self.words.len();
+            }
+        }
+
         Ok(())
     }
 
-    fn inst(mut self, def: &spec::InstructionDef) -> Result<spv::InstWithIds, InstParseError> {
+    fn inst(
+        mut self,
+        def: &spec::InstructionDef,
+    ) -> Result<(spv::InstWithIds, Option<OperandSpans>), InstParseError> {
         use InstParseError as Error;
 
         {
@@ -232,10 +266,67 @@ impl InstParser<'_> {
             return Err(Error::TooManyWords);
         }
 
-        Ok(self.inst)
+        Ok((self.inst, self.operand_spans))
+    }
+}
+
+/// Position (in words, from the start of the module, including its header)
+/// of every word of one instruction, as yielded by
+/// [`ModuleParser::with_operand_spans`] (e.g. for patching with [`patch_word`]).
+#[derive(Clone, PartialEq, Eq)]
+pub struct InstSpans {
+    /// The first word of the instruction (i.e. opcode and word count).
+    pub word_offset: usize,
+
+    pub result_type_id: Option<usize>,
+    pub result_id: Option<usize>,
+
+    /// All other operands, in the order they appear in the instruction (which
+    /// interleaves [`spv::Imm`]s and [`spv::Id`]s, distinguishable by `kind`).
+    pub operands: SmallVec<[OperandSpan; 4]>,
+}
+
+/// The word range (see [`InstSpans`]) of one operand, with only its own words
+/// included (i.e. the parameters of an enumerand are separate operands).
+#[derive(Clone, PartialEq, Eq)]
+pub struct OperandSpan {
+    pub kind: spec::OperandKind,
+    pub words: Range<usize>,
+}
+
+/// Replace the word at `word_offset` (in words, e.g. from [`InstSpans`]) in
+/// the SPIR-V module `spv_bytes`, with `value`, keeping the same endianness
+/// as the rest of the module (as indicated by the magic in its header).
+pub fn patch_word(spv_bytes: &mut [u8], word_offset: usize, value: u32) -> io::Result<()> {
+    let spv_spec = spec::Spec::get();
+
+    if spv_bytes.len() < spec::HEADER_LEN * 4 {
+        return Err(truncated_header(spv_bytes.len()));
     }
+    let magic = u32::from_ne_bytes(spv_bytes[..4].try_into().unwrap());
+    let value = if magic == spv_spec.magic {
+        value
+    } else if magic.swap_bytes() == spv_spec.magic {
+        value.swap_bytes()
+    } else {
+        return Err(bad_magic(magic));
+    };
+
+    let byte_len = spv_bytes.len();
+    let word_bytes = word_offset
+        .checked_mul(4)
+        .and_then(|start| spv_bytes.get_mut(start..start.checked_add(4)?))
+        .ok_or_else(|| {
+            invalid(&format!("word {word_offset} is past the end ({byte_len} bytes)"))
+        })?;
+    word_bytes.copy_from_slice(&value.to_ne_bytes());
+
+    Ok(())
 }
 
+/// Parser for whole SPIR-V modules, which is then used as an [`Iterator`] of
+/// their instructions (with all operands already categorized, i.e. split into
+/// [`spv::Imm`]s and [`spv::Id`]s), and which doesn't require lowering to SPIR-T.
 pub struct ModuleParser {
     /// Copy of the header words (for convenience).
     // FIXME(eddyb) add a `spec::Header` or `spv::Header` struct with named fields.
@@ -257,15 +348,68 @@ fn invalid(reason: &str) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidData, format!("malformed SPIR-V ({reason})"))
 }
 
+/// Error for inputs which aren't even shaped like SPIR-V modules (e.g. an
+/// empty or text file), as opposed to malformed SPIR-V (see `invalid`).
+fn not_spv(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("not a SPIR-V module: {reason}"))
+}
+
+fn truncated_header(byte_len: usize) -> io::Error {
+    not_spv(&format!(
+        "only {byte_len} bytes, but the header alone is {} bytes",
+        spec::HEADER_LEN * 4
+    ))
+}
+
+fn bad_magic(magic: u32) -> io::Error {
+    not_spv(&format!("bad magic 0x{magic:08x} (expected 0x{:08x})", spec::Spec::get().magic))
+}
+
 impl ModuleParser {
     pub fn read_from_spv_file(path: impl AsRef<Path>) -> io::Result<Self> {
-        Self::read_from_spv_bytes(fs::read(path)?)
+        Self::read_from_reader(fs::File::open(path)?)
+    }
+
+    /// Read a whole module from `reader` (e.g. a decompression stream, or an
+    /// archive entry), checking the magic number (in the module header) before
+    /// reading the remainder of the module.
+    //
+    // FIXME: parse instructions as they're being read, without first
+    // reading the entire module into memory.
+    pub fn read_from_reader(mut reader: impl io::Read) -> io::Result<Self> {
+        let spv_spec = spec::Spec::get();
+
+        // NOTE: `read_to_end` handles short reads (i.e. this only stops
+        // early if the whole input is shorter than the header).
+        let header_byte_len = spec::HEADER_LEN * 4;
+        let mut spv_bytes = Vec::with_capacity(header_byte_len);
+        reader.by_ref().take(header_byte_len as u64).read_to_end(&mut spv_bytes)?;
+        if spv_bytes.len() < header_byte_len {
+            return Err(truncated_header(spv_bytes.len()));
+        }
+
+        let magic = u32::from_ne_bytes(spv_bytes[..4].try_into().unwrap());
+        if magic != spv_spec.magic && magic.swap_bytes() != spv_spec.magic {
+            return Err(bad_magic(magic));
+        }
+
+        reader.read_to_end(&mut spv_bytes)?;
+        Self::read_from_spv_bytes(spv_bytes)
+    }
+
+    /// Like [`ModuleParser::read_from_spv_bytes`], but for an in-memory module
+    /// already split into words (whose endianness is still checked, however).
+    pub fn read_from_spv_words(spv_words: &[u32]) -> io::Result<Self> {
+        // FIXME: avoid the copy, if `word_bytes` ever becomes `Vec<u32>`.
+        Self::read_from_spv_bytes(bytemuck::cast_slice::<u32, u8>(spv_words).to_vec())
     }
 
-    // FIXME(eddyb) also add `from_spv_words`.
     pub fn read_from_spv_bytes(spv_bytes: Vec<u8>) -> io::Result<Self> {
         let spv_spec = spec::Spec::get();
 
+        if spv_bytes.len() < spec::HEADER_LEN * 4 {
+            return Err(truncated_header(spv_bytes.len()));
+        }
         if spv_bytes.len() % 4 != 0 {
             return Err(invalid("not a multiple of 4 bytes"));
         }
@@ -273,10 +417,6 @@ impl ModuleParser {
         let mut spv_bytes = spv_bytes;
         let spv_words = bytemuck::cast_slice_mut::<u8, u32>(&mut spv_bytes);
 
-        if spv_words.len() < spec::HEADER_LEN {
-            return Err(invalid("truncated header"));
-        }
-
         // Check the magic, and swap endianness of all words if we have to.
         {
             let magic = spv_words[0];
@@ -287,7 +427,7 @@ impl ModuleParser {
                     *word = word.swap_bytes();
                 }
             } else {
-                return Err(invalid("incorrect magic number"));
+                return Err(bad_magic(magic));
             }
         }
 
@@ -301,79 +441,452 @@ impl ModuleParser {
     }
 }
 
+impl ModuleParser {
+    /// Position (in words, from the start of the module, including its header)
+    /// of the instruction which the next [`Iterator::next`] call will parse.
+    pub fn next_word_offset(&self) -> usize {
+        self.next_word
+    }
+
+    /// Adapt this parser into an iterator of instructions paired with their
+    /// positions (see also [`ModuleParser::next_word_offset`]), which are
+    /// always the position of the first word (i.e. opcode and word count).
+    pub fn with_word_offsets(
+        mut self,
+    ) -> impl Iterator<Item = io::Result<(usize, spv::InstWithIds)>> {
+        iter::from_fn(move || {
+            let word_offset = self.next_word_offset();
+            Some(self.next()?.map(|inst| (word_offset, inst)))
+        })
+    }
+
+    /// Adapt this parser into an iterator of instructions paired with the
+    /// positions of all of their words (see [`InstSpans`]), which are always
+    /// relative to the original module, even if its endianness was swapped
+    /// (i.e. they can be used with [`patch_word`], on a copy of the input).
+    pub fn with_operand_spans(
+        mut self,
+    ) -> impl Iterator<Item = io::Result<(spv::InstWithIds, InstSpans)>> {
+        iter::from_fn(move || {
+            let word_offset = self.next_word_offset();
+            Some(self.next_inst(true)?.map(|(inst, operand_spans)| {
+                let mut next_word = word_offset + 1;
+                let mut id_word = |has_id: bool| {
+                    has_id.then(|| {
+                        next_word += 1;
+                        next_word - 1
+                    })
+                };
+                let spans = InstSpans {
+                    word_offset,
+                    result_type_id: id_word(inst.result_type_id.is_some()),
+                    result_id: id_word(inst.result_id.is_some()),
+                    operands: operand_spans.unwrap(),
+                };
+                (inst, spans)
+            }))
+        })
+    }
+}
+
 impl Iterator for ModuleParser {
     type Item = io::Result<spv::InstWithIds>;
     fn next(&mut self) -> Option<Self::Item> {
-        let spv_spec = spec::Spec::get();
-        let wk = &spv_spec.well_known;
+        Some(self.next_inst(false)?.map(|(inst, _)| inst))
+    }
+}
 
+impl ModuleParser {
+    fn next_inst(
+        &mut self,
+        record_operand_spans: bool,
+    ) -> Option<io::Result<(spv::InstWithIds, Option<OperandSpans>)>> {
         let words = &bytemuck::cast_slice::<u8, u32>(&self.word_bytes)[self.next_word..];
-        let &opcode = words.first()?;
+        let result = parse_inst(&mut self.known_ids, words, self.next_word, record_operand_spans)?;
+        if result.is_ok() {
+            self.next_word += (words[0] >> 16) as usize;
+        }
+        Some(result)
+    }
+}
 
-        let (inst_len, opcode) = ((opcode >> 16) as usize, opcode as u16);
+/// Streaming alternative to [`ModuleParser`], which reads (and parses) one
+/// instruction at a time from `reader`, instead of the entire module at once,
+/// i.e. its memory usage doesn't grow with the size of the module (other than
+/// some bookkeeping, of a few bytes per ID, needed for parsing some literals).
+///
+/// Note that this can't be used for lowering to SPIR-T, which requires the
+/// entire module (e.g. decorations refer to IDs only defined later), and is
+/// only meant for tools which can process instructions one by one (and then
+/// discard them), e.g. to collect statistics from very large modules.
+pub struct ModuleStreamParser<R> {
+    /// Copy of the header words (for convenience).
+    pub header: [u32; spec::HEADER_LEN],
 
-        let (opcode, inst_name, def) = match spec::Opcode::try_from_u16_with_name_and_def(opcode) {
-            Some(opcode_name_and_def) => opcode_name_and_def,
-            None => return Some(Err(invalid(&format!("unsupported opcode {opcode}")))),
-        };
+    reader: R,
 
-        let invalid = |msg: &str| invalid(&format!("in {inst_name}: {msg}"));
+    /// Whether the module's words have the opposite endianness to the native one
+    /// (i.e. have to be byte-swapped after being read).
+    swap_bytes: bool,
 
-        if words.len() < inst_len {
-            return Some(Err(invalid("truncated instruction")));
+    /// Next (instructions') word position in the module.
+    next_word: usize,
+
+    /// IDs defined so far in the module.
+    known_ids: FxHashMap<spv::Id, KnownIdDef>,
+
+    /// Buffers reused between instructions, for their bytes and words.
+    inst_bytes: Vec<u8>,
+    inst_words: Vec<u32>,
+
+    /// Set after an error, as `reader` can't be in a meaningful position anymore.
+    failed: bool,
+}
+
+impl<R: io::Read> ModuleStreamParser<R> {
+    /// Read only the header of the module from `reader` (checking its magic number),
+    /// with instructions then being read one at a time, by [`Iterator::next`].
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let spv_spec = spec::Spec::get();
+
+        let header_byte_len = spec::HEADER_LEN * 4;
+        let mut header_bytes = Vec::with_capacity(header_byte_len);
+        reader.by_ref().take(header_byte_len as u64).read_to_end(&mut header_bytes)?;
+        if header_bytes.len() < header_byte_len {
+            return Err(truncated_header(header_bytes.len()));
         }
 
-        let parser = InstParser {
-            known_ids: &self.known_ids,
-            words: words[1..inst_len].iter().copied(),
-            inst: spv::InstWithIds {
-                without_ids: opcode.into(),
-                result_type_id: None,
-                result_id: None,
-                ids: SmallVec::new(),
-            },
-        };
+        let mut header = [0; spec::HEADER_LEN];
+        for (word, bytes) in header.iter_mut().zip(header_bytes.chunks_exact(4)) {
+            *word = u32::from_ne_bytes(bytes.try_into().unwrap());
+        }
 
-        let inst = match parser.inst(def) {
-            Ok(inst) => inst,
-            Err(e) => return Some(Err(invalid(&e.message()))),
+        let swap_bytes = if header[0] == spv_spec.magic {
+            false
+        } else if header[0].swap_bytes() == spv_spec.magic {
+            true
+        } else {
+            return Err(bad_magic(header[0]));
         };
+        if swap_bytes {
+            for word in &mut header {
+                *word = word.swap_bytes();
+            }
+        }
 
-        // HACK(eddyb) `Option::map` allows using `?` for `Result` in the closure.
-        let maybe_known_id_result = inst.result_id.map(|id| {
-            let known_id_def = if opcode == wk.OpTypeInt {
-                KnownIdDef::TypeInt(match inst.imms[0] {
-                    spv::Imm::Short(kind, n) => {
-                        assert_eq!(kind, wk.LiteralInteger);
-                        n.try_into().ok().ok_or_else(|| invalid("Width cannot be 0"))?
-                    }
-                    _ => unreachable!(),
-                })
-            } else if opcode == wk.OpTypeFloat {
-                KnownIdDef::TypeFloat(match inst.imms[0] {
-                    spv::Imm::Short(kind, n) => {
-                        assert_eq!(kind, wk.LiteralInteger);
-                        n.try_into().ok().ok_or_else(|| invalid("Width cannot be 0"))?
-                    }
-                    _ => unreachable!(),
-                })
-            } else {
-                KnownIdDef::Uncategorized { opcode, result_type_id: inst.result_type_id }
-            };
+        Ok(Self {
+            header,
+            reader,
+            swap_bytes,
+            next_word: spec::HEADER_LEN,
 
-            let old = self.known_ids.insert(id, known_id_def);
-            if old.is_some() {
-                return Err(invalid(&format!("ID %{id} is a result of multiple instructions")));
-            }
+            known_ids: FxHashMap::default(),
 
-            Ok(())
-        });
-        if let Some(Err(e)) = maybe_known_id_result {
+            inst_bytes: vec![],
+            inst_words: vec![],
+
+            failed: false,
+        })
+    }
+
+    /// Position (in words, from the start of the module, including its header)
+    /// of the instruction which the next [`Iterator::next`] call will parse.
+    pub fn next_word_offset(&self) -> usize {
+        self.next_word
+    }
+
+    /// Read (into `self.inst_words`) the next `word_count` words, or fewer,
+    /// if `reader` runs out of bytes (i.e. the module is truncated).
+    fn read_words(&mut self, word_count: usize) -> io::Result<()> {
+        self.inst_bytes.clear();
+        self.reader.by_ref().take(word_count as u64 * 4).read_to_end(&mut self.inst_bytes)?;
+        let word_bytes = self.inst_bytes.chunks_exact(4);
+        if !word_bytes.remainder().is_empty() {
+            return Err(invalid("not a multiple of 4 bytes"));
+        }
+
+        let swap_bytes = self.swap_bytes;
+        self.inst_words.extend(word_bytes.map(|bytes| {
+            let word = u32::from_ne_bytes(bytes.try_into().unwrap());
+            if swap_bytes { word.swap_bytes() } else { word }
+        }));
+        Ok(())
+    }
+
+    fn try_next(&mut self) -> Option<io::Result<spv::InstWithIds>> {
+        self.inst_words.clear();
+        if let Err(e) = self.read_words(1) {
             return Some(Err(e));
         }
+        let inst_len = (*self.inst_words.first()? >> 16) as usize;
+        if inst_len > 1 {
+            if let Err(e) = self.read_words(inst_len - 1) {
+                return Some(Err(e));
+            }
+        }
 
+        let result = parse_inst(&mut self.known_ids, &self.inst_words, self.next_word, false)?;
         self.next_word += inst_len;
+        Some(result.map(|(inst, _)| inst))
+    }
+}
+
+impl<R: io::Read> Iterator for ModuleStreamParser<R> {
+    type Item = io::Result<spv::InstWithIds>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.try_next()?;
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// Parse one instruction, from the start of `words` (which can contain more
+/// words after the instruction), found at `word_offset` in the module
+/// (only used for error messages and operand spans), updating `known_ids`.
+///
+/// Returns `None` only if `words` is empty (i.e. the end of the module).
+fn parse_inst(
+    known_ids: &mut FxHashMap<spv::Id, KnownIdDef>,
+    words: &[u32],
+    word_offset: usize,
+    record_operand_spans: bool,
+) -> Option<io::Result<(spv::InstWithIds, Option<OperandSpans>)>> {
+    let spv_spec = spec::Spec::get();
+    let wk = &spv_spec.well_known;
+
+    let &opcode = words.first()?;
+
+    let (inst_len, opcode) = ((opcode >> 16) as usize, opcode as u16);
+
+    let (opcode, inst_name, def) = match spec::Opcode::try_from_u16_with_name_and_def(opcode) {
+        Some(opcode_name_and_def) => opcode_name_and_def,
+        None => {
+            return Some(Err(invalid(&format!(
+                "unsupported opcode {opcode} (at word {word_offset})"
+            ))));
+        }
+    };
+
+    let invalid = |msg: &str| invalid(&format!("in {inst_name} (at word {word_offset}): {msg}"));
+
+    if inst_len == 0 {
+        return Some(Err(invalid("word count is 0")));
+    }
+    if words.len() < inst_len {
+        return Some(Err(invalid(&format!(
+            "truncated instruction (word count is {inst_len}, but only {} word(s) left)",
+            words.len()
+        ))));
+    }
+
+    let parser = InstParser {
+        known_ids,
+        words: words[1..inst_len].iter().copied(),
+        inst: spv::InstWithIds {
+            without_ids: opcode.into(),
+            result_type_id: None,
+            result_id: None,
+            ids: SmallVec::new(),
+        },
+        words_end: word_offset + inst_len,
+        operand_spans: record_operand_spans.then(SmallVec::new),
+    };
+
+    let (inst, operand_spans) = match parser.inst(def) {
+        Ok(inst_and_operand_spans) => inst_and_operand_spans,
+        Err(e) => return Some(Err(invalid(&e.message()))),
+    };
+
+    // HACK(eddyb) `Option::map` allows using `?` for `Result` in the closure.
+    let maybe_known_id_result = inst.result_id.map(|id| {
+        let known_id_def = if opcode == wk.OpTypeInt {
+            KnownIdDef::TypeInt(match inst.imms[0] {
+                spv::Imm::Short(kind, n) => {
+                    assert_eq!(kind, wk.LiteralInteger);
+                    n.try_into().ok().ok_or_else(|| invalid("Width cannot be 0"))?
+                }
+                _ => unreachable!(),
+            })
+        } else if opcode == wk.OpTypeFloat {
+            KnownIdDef::TypeFloat(match inst.imms[0] {
+                spv::Imm::Short(kind, n) => {
+                    assert_eq!(kind, wk.LiteralInteger);
+                    n.try_into().ok().ok_or_else(|| invalid("Width cannot be 0"))?
+                }
+                _ => unreachable!(),
+            })
+        } else {
+            KnownIdDef::Uncategorized { opcode, result_type_id: inst.result_type_id }
+        };
+
+        let old = known_ids.insert(id, known_id_def);
+        if old.is_some() {
+            return Err(invalid(&format!("ID %{id} is a result of multiple instructions")));
+        }
+
+        Ok(())
+    });
+    if let Some(Err(e)) = maybe_known_id_result {
+        return Some(Err(e));
+    }
+
+    Some(Ok((inst, operand_spans)))
+}
+
+#[test]
+fn truncated_module() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            %1 = OpTypeVoid
+            %2 = OpTypeFunction %1
+            %3 = OpTypeFloat 32
+            "#,
+    );
+    let inst_boundaries = [5, 7, 10, 12, 15, 18];
+
+    for len in spec::HEADER_LEN..=spv_words.len() {
+        let parsed: io::Result<Vec<_>> =
+            ModuleParser::read_from_spv_words(&spv_words[..len]).unwrap().collect();
+        assert_eq!(parsed.is_ok(), inst_boundaries.contains(&len), "truncated to {len} words");
+    }
+
+    // A word count of 0 is never valid (and can't be skipped over).
+    let mut zero_word_count = spv_words[..spec::HEADER_LEN + 2].to_vec();
+    zero_word_count[spec::HEADER_LEN] &= 0xffff;
+    assert!(ModuleParser::read_from_spv_words(&zero_word_count).unwrap().any(|r| r.is_err()));
+}
+
+#[test]
+fn not_a_module() {
+    for bytes in [&[][..], b"not SPIR-V\n", b"definitely not a SPIR-V module!\n"] {
+        for result in [
+            ModuleParser::read_from_spv_bytes(bytes.to_vec()),
+            ModuleParser::read_from_reader(bytes),
+        ] {
+            let err = result.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().starts_with("not a SPIR-V module: "), "{err}");
+        }
+    }
+}
+
+#[test]
+fn patch_operand_via_spans() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpMemoryModel Logical GLSL450
+            %1 = OpTypeFloat 32
+            OpSource Unknown 0
+            "#,
+    );
+
+    // NOTE: the input is big-endian, to test endianness swapping.
+    let mut spv_bytes: Vec<u8> = spv_words.iter().flat_map(|w| w.to_be_bytes()).collect();
+
+    let parser = ModuleParser::read_from_spv_bytes(spv_bytes.clone()).unwrap();
+    let all_spans: Vec<_> = parser.with_operand_spans().map(|r| r.unwrap().1).collect();
+    assert_eq!(all_spans.len(), 3);
+
+    let float_spans = &all_spans[1];
+    assert_eq!(float_spans.word_offset, 8);
+    assert_eq!(float_spans.result_type_id, None);
+    assert_eq!(float_spans.result_id, Some(9));
+    assert_eq!(float_spans.operands.len(), 1);
+    assert_eq!(float_spans.operands[0].words, 10..11);
+
+    patch_word(&mut spv_bytes, float_spans.operands[0].words.start, 64).unwrap();
+    assert_eq!(spv_bytes[40..44], 64u32.to_be_bytes());
+
+    let insts: Vec<_> =
+        ModuleParser::read_from_spv_bytes(spv_bytes).unwrap().map(|r| r.unwrap()).collect();
+    assert!(matches!(insts[1].imms[..], [spv::Imm::Short(_, 64)]));
+}
+
+#[test]
+fn stream_parse_matches_whole_module_parse() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpMemoryModel Logical GLSL450
+            %1 = OpTypeInt 64 0
+            OpSource Unknown 0
+            "#,
+    );
+    let spv_bytes: Vec<u8> = spv_words.iter().flat_map(|w| w.to_be_bytes()).collect();
+
+    let collect_opcodes = |parser: &mut dyn Iterator<Item = io::Result<spv::InstWithIds>>| {
+        parser.map(|inst| inst.map(|inst| inst.opcode)).collect::<io::Result<Vec<_>>>()
+    };
+    let whole = collect_opcodes(&mut ModuleParser::read_from_spv_bytes(spv_bytes.clone()).unwrap());
+    let streamed = collect_opcodes(&mut ModuleStreamParser::new(&spv_bytes[..]).unwrap());
+    assert!(whole.unwrap() == streamed.unwrap());
+
+    // Truncation is detected (only once), even in the middle of an instruction.
+    let mut truncated = ModuleStreamParser::new(&spv_bytes[..spv_bytes.len() - 4]).unwrap();
+    assert_eq!(truncated.by_ref().filter(|inst| inst.is_err()).count(), 1);
+}
 
-        Some(Ok(inst))
+#[test]
+fn big_endian_module() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpMemoryModel Logical GLSL450
+            OpEntryPoint GLCompute %main "main"
+            OpExecutionMode %main LocalSize 8 4 1
+            OpName %main "main"
+            OpName %value "a_longer_name_for_the_value"
+            %void = OpTypeVoid
+            %uint = OpTypeInt 32 0
+            %value = OpConstant %uint 0x12345678
+            %func_type = OpTypeFunction %void
+            %main = OpFunction %void None %func_type
+            %entry = OpLabel
+            %sum = OpIAdd %uint %value %value
+            OpReturn
+            OpFunctionEnd
+            "#,
+    );
+    // The same module, but with every word (including literals) byte-swapped.
+    let swapped_bytes: Vec<u8> =
+        spv_words.iter().flat_map(|word| word.swap_bytes().to_ne_bytes()).collect();
+
+    let parse = |parser: ModuleParser| {
+        assert_eq!(parser.header[..], spv_words[..spec::HEADER_LEN]);
+        parser
+            .map(Result::unwrap)
+            .map(|inst| (inst.without_ids.clone(), inst.result_type_id, inst.result_id, inst.ids))
+            .collect::<Vec<_>>()
+    };
+    let expected_insts = parse(ModuleParser::read_from_spv_words(&spv_words).unwrap());
+    for parser in [
+        ModuleParser::read_from_spv_bytes(swapped_bytes.clone()).unwrap(),
+        ModuleParser::read_from_reader(&swapped_bytes[..]).unwrap(),
+    ] {
+        assert!(parse(parser) == expected_insts);
     }
+    let stream_parser = ModuleStreamParser::new(&swapped_bytes[..]).unwrap();
+    assert_eq!(stream_parser.header[..], spv_words[..spec::HEADER_LEN]);
+    let stream_insts: Vec<_> = stream_parser
+        .map(Result::unwrap)
+        .map(|inst| (inst.without_ids.clone(), inst.result_type_id, inst.result_id, inst.ids))
+        .collect();
+    assert!(stream_insts == expected_insts);
+
+    // Lowering the byte-swapped module matches lowering the original one.
+    let lower = |parser| {
+        crate::Module::lower_from_spv_module_parser(std::rc::Rc::new(crate::Context::new()), parser)
+            .unwrap()
+    };
+    let expected_module = lower(ModuleParser::read_from_spv_words(&spv_words).unwrap());
+    let swapped_module = lower(ModuleParser::read_from_reader(&swapped_bytes[..]).unwrap());
+    assert!(
+        swapped_module.structurally_eq(&expected_module),
+        "{:?}",
+        swapped_module.diff(&expected_module)
+    );
 }
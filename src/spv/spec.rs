@@ -1,9 +1,11 @@
 //! SPIR-V specification parsing/indexing.
 
 use crate::FxIndexSet;
+use crate::spv::SpvVersion;
 use arrayvec::ArrayVec;
 use lazy_static::lazy_static;
 use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::{fmt, iter};
@@ -15,13 +17,17 @@ pub const HEADER_LEN: usize = 5;
 pub struct Spec {
     pub magic: u32,
 
-    /// Pre-cached IDs for "well-known" names.
+    /// Pre-cached IDs for "well-known" names (see [`WellKnown`]).
     pub well_known: WellKnown,
 
     pub instructions: indexed::NamedIdxMap<Opcode, InstructionDef, indexed::KhrSegmented>,
 
     pub operand_kinds: indexed::NamedIdxMap<OperandKind, OperandKindDef, indexed::Flat>,
 
+    /// For every `Capability` which depends on other capabilities (i.e. listing
+    /// them in its `"capabilities"`, in the grammar), those dependencies.
+    pub capability_dependencies: FxHashMap<u32, SmallVec<[u32; 1]>>,
+
     // HACK(eddyb) ad-hoc interning, to reduce the cost of tracking operand names
     // down to a single extra byte per operand (see `PackedOperandNameAndKind`).
     operand_names: FxIndexSet<&'static str>,
@@ -57,6 +63,20 @@ pub struct ExtInstSetInstructionDesc {
 
 macro_rules! def_well_known {
     ($($group:ident: $ty:ty = [$($entry:ident),+ $(,)?]),+ $(,)?) => {
+        /// Opcodes, operand kinds, and enumerant values, that SPIR-T itself
+        /// needs to refer to, pre-looked-up by name (see [`Spec::well_known`]).
+        ///
+        /// Each field is named exactly like its entry in the SPIR-V grammar,
+        /// and its value is looked up from the grammar (when the [`Spec`] is
+        /// loaded), so e.g. `wk.OpName` can be used to match instructions,
+        /// instead of hardcoding numbers.
+        ///
+        /// All fields are public, and will not be removed outside of
+        /// semver-breaking releases, but the set of fields only grows as
+        /// SPIR-T needs more of them, and isn't meant to be comprehensive
+        /// (see [`Spec::instructions`] and [`Spec::operand_kinds`] for lookups
+        /// by arbitrary names).
+        //
         // FIXME(eddyb) decide whether to split this type into one per-group.
         #[allow(non_snake_case)]
         pub struct WellKnown {
@@ -138,6 +158,12 @@ def_well_known! {
         OpConstant,
         OpUndef,
 
+        OpSpecConstantFalse,
+        OpSpecConstantTrue,
+        OpSpecConstant,
+        OpSpecConstantComposite,
+        OpSpecConstantOp,
+
         OpVariable,
 
         OpFunction,
@@ -166,9 +192,12 @@ def_well_known! {
         OpPtrAccessChain,
         OpInBoundsPtrAccessChain,
         OpBitcast,
+
+        OpSelect,
     ],
     operand_kind: OperandKind = [
         Capability,
+        ExecutionModel,
         AddressingModel,
         MemoryModel,
         SourceLanguage,
@@ -188,6 +217,10 @@ def_well_known! {
     addressing_model: u32 = [
         Logical,
     ],
+    memory_model: u32 = [
+        Simple,
+        GLSL450,
+    ],
     storage_class: u32 = [
         Function,
 
@@ -204,11 +237,19 @@ def_well_known! {
     decoration: u32 = [
         LinkageAttributes,
 
+        BuiltIn,
+        Location,
+
         ArrayStride,
 
         Block,
         RowMajor,
         Offset,
+
+        SpecId,
+
+        DescriptorSet,
+        Binding,
     ],
     linkage_type: u32 = [
         Import,
@@ -270,6 +311,14 @@ pub struct InstructionDef {
     pub req_operands: ArrayVec<PackedOperandNameAndKind, 14>,
     pub opt_operands: ArrayVec<PackedOperandNameAndKind, 2>,
     pub rest_operands: Option<RestOperandsUnit>,
+
+    /// The first SPIR-V version this instruction is in the "core" spec of
+    /// (`None` if it's only available through extensions).
+    pub version: Option<SpvVersion>,
+
+    /// The last SPIR-V version this instruction is in the "core" spec of
+    /// (`None` if it hasn't been removed from the latest version).
+    pub last_version: Option<SpvVersion>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -618,6 +667,53 @@ impl Spec {
             .map(|(once_cell, init)| once_cell.get_or_init(init))
     }
 
+    /// Lookup the name (e.g. `"OpLoad"`) of the instruction with this `opcode`,
+    /// returning `None` if it's not a known opcode.
+    pub fn instruction_name(&self, opcode: u16) -> Option<&'static str> {
+        Some(self.instructions.get_named(Opcode(opcode))?.0)
+    }
+
+    /// Lookup the name (e.g. `"StorageClass"`) of the operand kind `kind`.
+    pub fn operand_kind_name(&self, kind: OperandKind) -> Option<&'static str> {
+        Some(self.operand_kinds.get_named(kind)?.0)
+    }
+
+    /// Lookup the name (e.g. `"Uniform"`) of the enumerant with this `value`,
+    /// in the (value or bit) enum operand kind `kind`, returning `None` if not
+    /// known (or if `kind` isn't an enum, or `value` isn't a single bit, for
+    /// bit enums other than the `0` "empty" value).
+    pub fn enumerant_name(&self, kind: OperandKind, value: u32) -> Option<&'static str> {
+        match self.operand_kinds.get(kind)? {
+            OperandKindDef::BitEnum { empty_name, bits } => {
+                if value == 0 {
+                    Some(*empty_name)
+                } else {
+                    Some(bits.get_named(BitIdx::of_single_set_bit(value)?)?.0)
+                }
+            }
+            OperandKindDef::ValueEnum { variants } => {
+                Some(variants.get_named(u16::try_from(value).ok()?)?.0)
+            }
+            OperandKindDef::Id | OperandKindDef::Literal { .. } => None,
+        }
+    }
+
+    /// Lookup the value of the enumerant named `name` (e.g. `"Uniform"`), in the
+    /// (value or bit) enum operand kind `kind`, i.e. the inverse of [`Spec::enumerant_name`].
+    pub fn enumerant_by_name(&self, kind: OperandKind, name: &str) -> Option<u32> {
+        match self.operand_kinds.get(kind)? {
+            OperandKindDef::BitEnum { empty_name, bits } => {
+                if name == *empty_name {
+                    Some(0)
+                } else {
+                    Some(1 << bits.lookup(name)?.0)
+                }
+            }
+            OperandKindDef::ValueEnum { variants } => variants.lookup(name).map(u32::from),
+            OperandKindDef::Id | OperandKindDef::Literal { .. } => None,
+        }
+    }
+
     /// Implementation detail of [`Spec::get`], indexes the raw data to produce a [`Spec`].
     fn from_raw(raw_core_grammar: raw::CoreGrammar<'static>) -> Self {
         /// Helper for picking a name when the same index has multiple names.
@@ -925,6 +1021,11 @@ impl Spec {
                     ),
                 }
 
+                let parse_version = |version: &str| {
+                    let (major, minor) = version.split_once('.')?;
+                    Some(SpvVersion(major.parse().ok()?, minor.parse().ok()?))
+                };
+
                 let mut def = InstructionDef {
                     // FIXME(eddyb) should `Other` be replaced with `Option`?
                     category: category_from_class.unwrap_or(InstructionCategory::Other),
@@ -935,6 +1036,21 @@ impl Spec {
                     req_operands: ArrayVec::new(),
                     opt_operands: ArrayVec::new(),
                     rest_operands: None,
+
+                    // NOTE: a missing `version` implies SPIR-V 1.0, while
+                    // `"version": "None"` means "only available through extensions".
+                    version: match inst.version {
+                        None => Some(SpvVersion(1, 0)),
+                        Some("None") => None,
+                        Some(version) => Some(parse_version(version).unwrap_or_else(|| {
+                            panic!("{}: invalid version {version:?}", inst.opname)
+                        })),
+                    },
+                    last_version: inst.last_version.map(|version| {
+                        parse_version(version).unwrap_or_else(|| {
+                            panic!("{}: invalid lastVersion {version:?}", inst.opname)
+                        })
+                    }),
                 };
 
                 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
@@ -997,7 +1113,24 @@ impl Spec {
                 (inst.opcode, (inst.opname, def))
             }),
             // `merge_duplicates` closure:
-            |(prev_name, prev_def), (new_name, new_def)| {
+            |(prev_name, mut prev_def), (new_name, mut new_def)| {
+                // NOTE: aliases (e.g. due to an extension being promoted
+                // to "core") can differ in their versions, in which case the
+                // widest range of versions is kept (i.e. as if the instruction
+                // had never had to be used as an extension instruction).
+                let version = match (prev_def.version, new_def.version) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                let last_version = match (prev_def.last_version, new_def.last_version) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    _ => None,
+                };
+                for def in [&mut prev_def, &mut new_def] {
+                    def.version = version;
+                    def.last_version = last_version;
+                }
+
                 // Only allow aliases that do not meaningfully differ.
                 assert!(
                     prev_def == new_def,
@@ -1023,6 +1156,10 @@ impl Spec {
                 OperandKindDef::ValueEnum { variants } => variants,
                 _ => unreachable!(),
             };
+        let memory_models = match &operand_kinds[operand_kinds.lookup("MemoryModel").unwrap()] {
+            OperandKindDef::ValueEnum { variants } => variants,
+            _ => unreachable!(),
+        };
         let storage_classes = match &operand_kinds[operand_kinds.lookup("StorageClass").unwrap()] {
             OperandKindDef::ValueEnum { variants } => variants,
             _ => unreachable!(),
@@ -1036,12 +1173,29 @@ impl Spec {
             _ => unreachable!(),
         };
 
+        let capability_dependencies = {
+            let capability_enumerants = raw_core_grammar
+                .operand_kinds
+                .iter()
+                .find(|o| o.kind == "Capability")
+                .and_then(|o| o.enumerants.as_ref())
+                .unwrap();
+            let capability_by_name: FxHashMap<_, _> =
+                capability_enumerants.iter().map(|e| (e.enumerant, e.value)).collect();
+            capability_enumerants
+                .iter()
+                .filter(|e| !e.capabilities.is_empty())
+                .map(|e| (e.value, e.capabilities.iter().map(|&c| capability_by_name[c]).collect()))
+                .collect()
+        };
+
         // FIXME(eddyb) if this is computed earlier, `IdResultType` and `IdResult`
         // wouldn't be looked up twice - but for now, this is mildly cleaner.
         let well_known = WellKnown::lookup_with(PerWellKnownGroup {
             opcode: |name| instructions.lookup(name).unwrap(),
             operand_kind: |name| operand_kinds.lookup(name).unwrap(),
             addressing_model: |name| addressing_models.lookup(name).unwrap().into(),
+            memory_model: |name| memory_models.lookup(name).unwrap().into(),
             storage_class: |name| storage_classes.lookup(name).unwrap().into(),
             decoration: |name| decorations.lookup(name).unwrap().into(),
             linkage_type: |name| linkage_types.lookup(name).unwrap().into(),
@@ -1053,6 +1207,7 @@ impl Spec {
             instructions,
             well_known,
             operand_kinds,
+            capability_dependencies,
 
             operand_names,
 
@@ -1061,6 +1216,35 @@ impl Spec {
     }
 }
 
+#[test]
+fn instruction_operand_kind_and_enumerant_names() {
+    let spec = Spec::get();
+    let wk = &spec.well_known;
+
+    assert_eq!(spec.instruction_name(wk.OpLoad.as_u16()), Some("OpLoad"));
+    assert_eq!(spec.instruction_name(u16::MAX), None);
+
+    assert_eq!(spec.operand_kind_name(wk.StorageClass), Some("StorageClass"));
+    assert_eq!(spec.operand_kind_name(wk.LiteralInteger), Some("LiteralInteger"));
+
+    // Value enums.
+    let uniform = spec.enumerant_by_name(wk.StorageClass, "Uniform").unwrap();
+    assert_eq!(spec.enumerant_name(wk.StorageClass, uniform), Some("Uniform"));
+    assert_eq!(spec.enumerant_name(wk.StorageClass, u32::MAX), None);
+    assert_eq!(spec.enumerant_by_name(wk.StorageClass, "NotAStorageClass"), None);
+
+    // Bit enums (with only single bits, or the "empty" value, having names).
+    assert_eq!(spec.enumerant_by_name(wk.FunctionControl, "None"), Some(0));
+    assert_eq!(spec.enumerant_by_name(wk.FunctionControl, "DontInline"), Some(2));
+    assert_eq!(spec.enumerant_name(wk.FunctionControl, 0), Some("None"));
+    assert_eq!(spec.enumerant_name(wk.FunctionControl, 2), Some("DontInline"));
+    assert_eq!(spec.enumerant_name(wk.FunctionControl, 1 | 2), None);
+
+    // Non-enum operand kinds.
+    assert_eq!(spec.enumerant_name(wk.LiteralInteger, 0), None);
+    assert_eq!(spec.enumerant_by_name(wk.LiteralInteger, "None"), None);
+}
+
 /// Deserialization for the `.grammar.json` files, without any post-processing.
 pub mod raw {
     use serde::Deserialize;
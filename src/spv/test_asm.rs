@@ -0,0 +1,196 @@
+//! Minimal SPIR-V assembler (only used by tests), for the same textual form
+//! as `spirv-dis`/`spirv-as` (see also the `.spvasm` files in `tests/data`).
+//!
+//! Only the subset of the syntax needed by tests is supported:
+//! * `; ...` comments, and at most one instruction per line
+//! * `%name = OpFoo ...`, with IDs either numeric (e.g. `%1`), used as-is,
+//!   or named (e.g. `%main`), which get the lowest IDs not used numerically
+//! * operands: IDs, integers (decimal or `0x`-prefixed hexadecimal, only one
+//!   word long), `"..."` strings (without escapes), and enumerants by name
+//!   (or `|`-separated names, for bit-enums)
+
+use crate::spv::spec;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Assemble `src` into a whole module (with a SPIR-V 1.0 header, without any
+/// generator, and the smallest valid ID bound), panicking on any errors.
+pub(crate) fn assemble(src: &str) -> Vec<u32> {
+    let spv_spec = spec::Spec::get();
+
+    let lines: Vec<Vec<&str>> = src
+        .lines()
+        .map(|line| tokenize(line.split(';').next().unwrap()))
+        .filter(|tokens| !tokens.is_empty())
+        .collect();
+
+    // Named IDs can only use the IDs not already used numerically.
+    let numeric_ids: FxHashSet<u32> =
+        lines.iter().flatten().filter_map(|token| token.strip_prefix('%')?.parse().ok()).collect();
+    let mut ids = Ids { numeric_ids, named_ids: FxHashMap::default(), next_named_id: 1 };
+
+    let mut words = vec![spv_spec.magic, 0x0001_0000, 0, 0, 0];
+    for tokens in lines {
+        let (result_id, tokens) = match tokens[..] {
+            [result, "=", ..] => (Some(ids.get(result)), &tokens[2..]),
+            _ => (None, &tokens[..]),
+        };
+        let (&opcode_name, mut operands) = tokens.split_first().unwrap();
+        let opcode = spv_spec
+            .instructions
+            .lookup(opcode_name)
+            .unwrap_or_else(|| panic!("unknown instruction `{opcode_name}`"));
+        let def = opcode.def();
+
+        let start = words.len();
+        words.push(0);
+        if def.has_result_type_id {
+            let (&result_type, rest) = operands.split_first().unwrap();
+            words.push(ids.get(result_type));
+            operands = rest;
+        }
+        assert_eq!(def.has_result_id, result_id.is_some(), "`{opcode_name}` result ID");
+        words.extend(result_id);
+
+        let mut operands = operands.iter().copied().peekable();
+        encode_operands(&mut ids, &mut words, &mut operands, def.all_operands());
+        if let Some(extra) = operands.next() {
+            panic!("`{opcode_name}`: unexpected extra operand `{extra}`");
+        }
+
+        let inst_len = u32::try_from(words.len() - start).unwrap();
+        words[start] = (inst_len << 16) | u32::from(opcode.as_u16());
+    }
+
+    let max_id = ids.numeric_ids.iter().copied().max().unwrap_or(0);
+    words[3] = max_id.max(ids.next_named_id - 1) + 1;
+    words
+}
+
+struct Ids<'a> {
+    numeric_ids: FxHashSet<u32>,
+    named_ids: FxHashMap<&'a str, u32>,
+    next_named_id: u32,
+}
+
+impl<'a> Ids<'a> {
+    fn get(&mut self, token: &'a str) -> u32 {
+        let name =
+            token.strip_prefix('%').unwrap_or_else(|| panic!("expected ID, found `{token}`"));
+        if let Ok(id) = name.parse() {
+            return id;
+        }
+        if let Some(&id) = self.named_ids.get(name) {
+            return id;
+        }
+        while self.numeric_ids.contains(&self.next_named_id) {
+            self.next_named_id += 1;
+        }
+        let id = self.next_named_id;
+        self.next_named_id += 1;
+        self.named_ids.insert(name, id);
+        id
+    }
+}
+
+/// Split `line` into whitespace-separated tokens (keeping strings whole).
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let len = if let Some(string) = rest.strip_prefix('"') {
+            1 + string.find('"').expect("unterminated string") + 1
+        } else {
+            rest.find(char::is_whitespace).unwrap_or(rest.len())
+        };
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    tokens
+}
+
+fn encode_operands<'a>(
+    ids: &mut Ids<'a>,
+    words: &mut Vec<u32>,
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+    operands: impl Iterator<Item = (spec::OperandMode, spec::OperandKind)>,
+) {
+    for (mode, kind) in operands {
+        let Some(token) = tokens.next() else {
+            assert!(mode == spec::OperandMode::Optional, "missing {} operand", kind.name());
+            break;
+        };
+        match kind.def() {
+            spec::OperandKindDef::Id => words.push(ids.get(token)),
+            spec::OperandKindDef::Literal { size: spec::LiteralSize::NulTerminated } => {
+                let s = token.strip_prefix('"').and_then(|s| s.strip_suffix('"'));
+                let s = s.unwrap_or_else(|| panic!("expected string, found `{token}`"));
+                let mut bytes = s.as_bytes().to_vec();
+                bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+                words.extend(bytes.chunks(4).map(|w| u32::from_le_bytes(w.try_into().unwrap())));
+            }
+            spec::OperandKindDef::Literal {
+                size: spec::LiteralSize::Word | spec::LiteralSize::FromContextualType,
+            } => words.push(parse_int(token)),
+            spec::OperandKindDef::ValueEnum { variants } => {
+                let value = match variants.lookup(token) {
+                    Some(value) => value,
+                    None => u16::try_from(parse_int(token)).unwrap(),
+                };
+                words.push(value.into());
+                encode_operands(ids, words, tokens, variants[value].all_params());
+            }
+            spec::OperandKindDef::BitEnum { empty_name, bits } => {
+                let mut value = 0;
+                let mut params = vec![];
+                for name in token.split('|').filter(|&name| name != *empty_name) {
+                    let bit_idx = bits
+                        .lookup(name)
+                        .unwrap_or_else(|| panic!("unknown {} `{name}`", kind.name()));
+                    value |= 1 << bit_idx.0;
+                    params.push(bit_idx);
+                }
+                words.push(value);
+
+                // Parameters of bit-enums are ordered by bit index.
+                params.sort_by_key(|bit_idx| bit_idx.0);
+                for bit_idx in params {
+                    encode_operands(ids, words, tokens, bits[bit_idx].all_params());
+                }
+            }
+        }
+    }
+}
+
+fn parse_int(token: &str) -> u32 {
+    let parsed = match token.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => token.parse::<u32>().ok().or_else(|| Some(token.parse::<i32>().ok()? as u32)),
+    };
+    parsed.unwrap_or_else(|| panic!("expected integer, found `{token}`"))
+}
+
+#[test]
+fn assemble_linkage_export() {
+    let words = assemble(
+        r#"
+        OpCapability Shader
+        OpMemoryModel Logical GLSL450
+        OpDecorate %main LinkageAttributes "main" Export
+        %void = OpTypeVoid
+        "#,
+    );
+    #[rustfmt::skip]
+    let expected = [
+        // Header (magic, version 1.0, generator, ID bound, schema).
+        0x07230203, 0x0001_0000, 0, 3, 0,
+        // OpCapability Shader
+        (2 << 16) | 17, 1,
+        // OpMemoryModel Logical GLSL450
+        (3 << 16) | 14, 0, 1,
+        // OpDecorate %1 LinkageAttributes "main" Export
+        (6 << 16) | 71, 1, 41, u32::from_le_bytes(*b"main"), 0, 0,
+        // %2 = OpTypeVoid
+        (2 << 16) | 19, 2,
+    ];
+    assert_eq!(words, expected);
+}
@@ -0,0 +1,203 @@
+//! Checking SPIR-V instructions (as kept in SPIR-T) against the grammar.
+//!
+//! Unlike [`spv::read`](crate::spv::read) and [`spv::write`](crate::spv::write),
+//! which check operands while converting from/to the binary form, this can be
+//! used on e.g. [`Attr::SpvAnnotation`](crate::Attr::SpvAnnotation)s created
+//! (or modified) after lowering, which would otherwise only fail during lifting.
+
+use crate::spv::{self, spec};
+use std::borrow::Cow;
+use std::{iter, slice};
+
+/// Error produced when the immediates of a [`spv::Inst`] don't match the
+/// operands (and enumerant parameters) expected by the SPIR-V grammar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImmsError {
+    /// Ran out of immediates while checking an instruction's operands.
+    NotEnoughImms,
+
+    /// Extra immediates were left over, after checking an instruction's operands.
+    TooManyImms,
+
+    /// An immediate of the wrong [`spec::OperandKind`] was found.
+    KindMismatch { expected: spec::OperandKind, found: spec::OperandKind },
+
+    /// A [`spv::Imm::LongCont`] was found without a preceding [`spv::Imm::LongStart`],
+    /// or a multi-word immediate was found where only a single word is allowed.
+    MalformedLongImm(spec::OperandKind),
+
+    /// Unsupported enumerand value.
+    UnsupportedEnumerand(spec::OperandKind, u32),
+}
+
+impl ImmsError {
+    // FIXME: deduplicate this with `spv::read` and `spv::write`.
+    pub fn message(&self) -> Cow<'static, str> {
+        match *self {
+            Self::NotEnoughImms => "missing immediate operands".into(),
+            Self::TooManyImms => "too many immediate operands".into(),
+            Self::KindMismatch { expected, found } => {
+                format!("expected {} operand, found {}", expected.name(), found.name()).into()
+            }
+            Self::MalformedLongImm(kind) => {
+                format!("malformed multi-word {} operand", kind.name()).into()
+            }
+            Self::UnsupportedEnumerand(kind, word) => {
+                let (name, def) = kind.name_and_def();
+                match def {
+                    spec::OperandKindDef::BitEnum { bits, .. } => {
+                        let unsupported = spec::BitIdx::of_all_set_bits(word)
+                            .filter(|&bit_idx| bits.get(bit_idx).is_none())
+                            .fold(0u32, |x, i| x | (1 << i.0));
+                        format!("unsupported {name} bit-pattern 0x{unsupported:08x}").into()
+                    }
+
+                    spec::OperandKindDef::ValueEnum { .. } => {
+                        format!("unsupported {name} value {word}").into()
+                    }
+
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+struct ImmsChecker<'a> {
+    /// Input immediate operands of an instruction.
+    imms: iter::Peekable<iter::Copied<slice::Iter<'a, spv::Imm>>>,
+}
+
+impl ImmsChecker<'_> {
+    fn is_exhausted(&mut self) -> bool {
+        self.imms.peek().is_none()
+    }
+
+    fn operands(
+        &mut self,
+        operands: impl Iterator<Item = (spec::OperandMode, spec::OperandKind)>,
+    ) -> Result<(), ImmsError> {
+        for (mode, kind) in operands {
+            if mode == spec::OperandMode::Optional && self.is_exhausted() {
+                break;
+            }
+
+            if let spec::OperandKindDef::Id = kind.def() {
+                // NOTE: IDs aren't part of `spv::Inst` (they're kept
+                // separately, as SPIR-T references, e.g. `DataInstDef` inputs).
+                // HACK: optional IDs can only be followed by more of the
+                // same (e.g. "rest operands"), so they can't affect immediates.
+                if mode == spec::OperandMode::Optional {
+                    break;
+                }
+                continue;
+            }
+
+            self.operand(kind)?;
+        }
+        Ok(())
+    }
+
+    fn operand(&mut self, kind: spec::OperandKind) -> Result<(), ImmsError> {
+        use ImmsError as Error;
+
+        let check_kind = |found_kind| {
+            if found_kind == kind {
+                Ok(())
+            } else {
+                Err(Error::KindMismatch { expected: kind, found: found_kind })
+            }
+        };
+
+        let imm = self.imms.next().ok_or(Error::NotEnoughImms)?;
+        let single_word = match kind.def() {
+            spec::OperandKindDef::BitEnum { .. }
+            | spec::OperandKindDef::ValueEnum { .. }
+            | spec::OperandKindDef::Literal { size: spec::LiteralSize::Word } => true,
+
+            spec::OperandKindDef::Literal {
+                size: spec::LiteralSize::NulTerminated | spec::LiteralSize::FromContextualType,
+            } => false,
+
+            // Handled by `operands` above.
+            spec::OperandKindDef::Id => unreachable!(),
+        };
+        let word = match imm {
+            spv::Imm::Short(found_kind, word) => {
+                check_kind(found_kind)?;
+                word
+            }
+            spv::Imm::LongStart(found_kind, word) if !single_word => {
+                check_kind(found_kind)?;
+                while let Some(&spv::Imm::LongCont(cont_kind, _)) = self.imms.peek() {
+                    check_kind(cont_kind)?;
+                    self.imms.next();
+                }
+                word
+            }
+            spv::Imm::LongStart(found_kind, _) | spv::Imm::LongCont(found_kind, _) => {
+                check_kind(found_kind)?;
+                return Err(Error::MalformedLongImm(kind));
+            }
+        };
+
+        match kind.def() {
+            spec::OperandKindDef::BitEnum { bits, .. } => {
+                for bit_idx in spec::BitIdx::of_all_set_bits(word) {
+                    let bit_def =
+                        bits.get(bit_idx).ok_or(Error::UnsupportedEnumerand(kind, word))?;
+                    self.operands(bit_def.all_params())?;
+                }
+            }
+            spec::OperandKindDef::ValueEnum { variants } => {
+                let variant_def = u16::try_from(word)
+                    .ok()
+                    .and_then(|v| variants.get(v))
+                    .ok_or(Error::UnsupportedEnumerand(kind, word))?;
+                self.operands(variant_def.all_params())?;
+            }
+            spec::OperandKindDef::Id | spec::OperandKindDef::Literal { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that the immediates of `inst` have the number and kinds expected by
+/// the SPIR-V grammar, for `inst.opcode` (including any parameters required
+/// by enumerants, e.g. a `Binding` decoration needs one `LiteralInteger`).
+///
+/// ID operands are ignored, as [`spv::Inst`] doesn't contain them.
+pub fn check_inst_imms(inst: &spv::Inst) -> Result<(), ImmsError> {
+    let mut checker = ImmsChecker { imms: inst.imms.iter().copied().peekable() };
+    checker.operands(inst.opcode.def().all_operands())?;
+
+    // The instruction must consume all of its immediates.
+    if !checker.is_exhausted() {
+        return Err(ImmsError::TooManyImms);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn check_binding_decoration_imms() {
+    let spv_spec = spec::Spec::get();
+    let wk = &spv_spec.well_known;
+
+    let binding = match &spv_spec.operand_kinds[wk.Decoration] {
+        spec::OperandKindDef::ValueEnum { variants } => variants.lookup("Binding").unwrap(),
+        _ => unreachable!(),
+    };
+    let decoration_imm = spv::Imm::Short(wk.Decoration, binding.into());
+
+    let well_formed = spv::Inst {
+        opcode: wk.OpDecorate,
+        imms: [decoration_imm, spv::Imm::Short(wk.LiteralInteger, 0)].into_iter().collect(),
+    };
+    assert_eq!(check_inst_imms(&well_formed), Ok(()));
+
+    let missing_operand =
+        spv::Inst { opcode: wk.OpDecorate, imms: [decoration_imm].into_iter().collect() };
+    assert_eq!(check_inst_imms(&missing_operand), Err(ImmsError::NotEnoughImms));
+}
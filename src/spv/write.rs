@@ -171,6 +171,14 @@ impl OperandEmitter<'_> {
     }
 }
 
+/// Byte order to use when writing SPIR-V words out as bytes.
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
 pub struct ModuleEmitter {
     /// Output SPIR-V words.
     // FIXME(eddyb) try to write bytes to an `impl io::Write` directly.
@@ -238,4 +246,21 @@ impl ModuleEmitter {
     pub fn write_to_spv_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
         fs::write(path, bytemuck::cast_slice::<u32, u8>(&self.words))
     }
+
+    /// Write all the words to `w`, one at a time (in the requested byte order).
+    ///
+    /// Note that `w` isn't buffered here, so e.g. [`io::BufWriter`] may be needed.
+    pub fn write_to_spv_writer(
+        &self,
+        mut w: impl io::Write,
+        endianness: Endianness,
+    ) -> io::Result<()> {
+        for &word in &self.words {
+            w.write_all(&match endianness {
+                Endianness::Little => word.to_le_bytes(),
+                Endianness::Big => word.to_be_bytes(),
+            })?;
+        }
+        w.flush()
+    }
 }
@@ -7,7 +7,7 @@ use crate::{
     ControlNodeDef, ControlNodeKind, ControlNodeOutputDecl, ControlRegion, ControlRegionDef,
     ControlRegionInputDecl, DataInst, DataInstDef, DataInstForm, DataInstFormDef, DataInstKind,
     DeclDef, EntityListIter, ExportKey, Exportee, Func, FuncDecl, FuncDefBody, FuncParam,
-    GlobalVar, GlobalVarDecl, GlobalVarDefBody, Import, Module, ModuleDebugInfo, ModuleDialect,
+    GlobalVar, GlobalVarDecl, GlobalVarDefBody, Import, InternedStr, Module, ModuleDebugInfo, ModuleDialect,
     OrdAssertEq, SelectionKind, Type, TypeDef, TypeKind, TypeOrConst, Value,
 };
 use std::cmp::Ordering;
@@ -132,6 +132,54 @@ macro_rules! transform {
     }};
 }
 
+/// Helper macro for [`Transformer`] impls, generating the `transform_type_use`,
+/// `transform_const_use` and `transform_data_inst_form_use` methods, which
+/// transform (and re-intern) the definitions behind those handles, caching the
+/// results in `self.transformed_{types,consts,data_inst_forms}` (`FxHashMap`s),
+/// and using `self.cx` (a `&Context`) for accessing/interning definitions.
+macro_rules! cached_transform_interned_uses {
+    () => {
+        fn transform_type_use(
+            &mut self,
+            ty: $crate::Type,
+        ) -> $crate::transform::Transformed<$crate::Type> {
+            if let Some(&cached) = self.transformed_types.get(&ty) {
+                return cached;
+            }
+            let transformed =
+                self.transform_type_def(&self.cx[ty]).map(|ty_def| self.cx.intern(ty_def));
+            self.transformed_types.insert(ty, transformed);
+            transformed
+        }
+        fn transform_const_use(
+            &mut self,
+            ct: $crate::Const,
+        ) -> $crate::transform::Transformed<$crate::Const> {
+            if let Some(&cached) = self.transformed_consts.get(&ct) {
+                return cached;
+            }
+            let transformed =
+                self.transform_const_def(&self.cx[ct]).map(|ct_def| self.cx.intern(ct_def));
+            self.transformed_consts.insert(ct, transformed);
+            transformed
+        }
+        fn transform_data_inst_form_use(
+            &mut self,
+            data_inst_form: $crate::DataInstForm,
+        ) -> $crate::transform::Transformed<$crate::DataInstForm> {
+            if let Some(&cached) = self.transformed_data_inst_forms.get(&data_inst_form) {
+                return cached;
+            }
+            let transformed = self
+                .transform_data_inst_form_def(&self.cx[data_inst_form])
+                .map(|data_inst_form_def| self.cx.intern(data_inst_form_def));
+            self.transformed_data_inst_forms.insert(data_inst_form, transformed);
+            transformed
+        }
+    };
+}
+pub(crate) use cached_transform_interned_uses;
+
 // FIXME(eddyb) `Sized` bound shouldn't be needed but removing it requires
 // writing `impl Transformer + ?Sized` in `fn inner_transform_with` signatures.
 pub trait Transformer: Sized {
@@ -160,9 +208,31 @@ pub trait Transformer: Sized {
         Transformed::Unchanged
     }
 
+    // Leaves (noop default behavior).
+    // NOTE: `InternedStr`s are used for e.g. link names, extended
+    // instruction set names, and debuginfo file paths.
+    fn transform_interned_str_use(&mut self, _s: InternedStr) -> Transformed<InternedStr> {
+        Transformed::Unchanged
+    }
+
     // Leaves transformed in-place (noop default behavior).
     fn in_place_transform_spv_dialect(&mut self, _dialect: &mut spv::Dialect) {}
-    fn in_place_transform_spv_module_debug_info(&mut self, _debug_info: &mut spv::ModuleDebugInfo) {
+
+    // Leaves transformed in-place (defaulting to only transforming any `InternedStr`s).
+    fn in_place_transform_spv_module_debug_info(&mut self, debug_info: &mut spv::ModuleDebugInfo) {
+        for sources in debug_info.source_languages.values_mut() {
+            let file_contents = &mut sources.file_contents;
+            Transformed::map_iter(file_contents.keys(), |&file| {
+                self.transform_interned_str_use(file)
+            })
+            .map(|new_keys_iter| new_keys_iter.zip(file_contents.values().cloned()).collect())
+            .apply_to(file_contents);
+        }
+    }
+    fn in_place_transform_import(&mut self, import: &mut Import) {
+        match import {
+            Import::LinkName(name) => self.transform_interned_str_use(*name).apply_to(name),
+        }
     }
 
     // Non-leaves (defaulting to calling `.inner_transform_with(self)`).
@@ -283,7 +353,9 @@ impl InnerInPlaceTransform for ModuleDebugInfo {
 impl InnerTransform for ExportKey {
     fn inner_transform_with(&self, transformer: &mut impl Transformer) -> Transformed<Self> {
         match self {
-            Self::LinkName(_) => Transformed::Unchanged,
+            &Self::LinkName(name) => transform!({
+                name -> transformer.transform_interned_str_use(name),
+            } => Self::LinkName(name)),
 
             Self::SpvEntryPoint { imms, interface_global_vars } => transform!({
                 imms -> Transformed::Unchanged,
@@ -331,10 +403,28 @@ impl InnerTransform for AttrSetDef {
 impl InnerTransform for Attr {
     fn inner_transform_with(&self, transformer: &mut impl Transformer) -> Transformed<Self> {
         match self {
-            Attr::Diagnostics(_)
-            | Attr::SpvAnnotation(_)
-            | Attr::SpvDebugLine { .. }
-            | Attr::SpvBitflagsOperand(_) => Transformed::Unchanged,
+            Attr::Diagnostics(_) | Attr::SpvAnnotation(_) | Attr::SpvBitflagsOperand(_) => {
+                Transformed::Unchanged
+            }
+
+            &Attr::SpvDebugLine { file_path, line, col } => transform!({
+                file_path -> transformer.transform_interned_str_use(file_path.0).map(OrdAssertEq),
+            } => Attr::SpvDebugLine {
+                file_path,
+                line,
+                col,
+            }),
+
+            Attr::SpvAnnotationWithConstInputs { spv_inst, const_inputs } => transform!({
+                spv_inst -> Transformed::Unchanged,
+                const_inputs -> Transformed::map_iter(
+                    const_inputs.0.iter(),
+                    |&ct| transformer.transform_const_use(ct),
+                ).map(|new_iter| OrdAssertEq(new_iter.collect())),
+            } => Attr::SpvAnnotationWithConstInputs {
+                spv_inst,
+                const_inputs,
+            }),
 
             Attr::QPtr(attr) => transform!({
                 attr -> match attr {
@@ -470,7 +560,9 @@ impl InnerTransform for ConstDef {
                         spv_inst_and_const_inputs: Rc::new((spv_inst.clone(), new_iter.collect())),
                     })
                 }
-                ConstKind::SpvStringLiteralForExtInst(_) => Transformed::Unchanged
+                &ConstKind::SpvStringLiteralForExtInst(s) => transformer
+                    .transform_interned_str_use(s)
+                    .map(ConstKind::SpvStringLiteralForExtInst),
             },
         } => Self {
             attrs,
@@ -483,9 +575,7 @@ impl InnerTransform for ConstDef {
 impl<D: InnerInPlaceTransform> InnerInPlaceTransform for DeclDef<D> {
     fn inner_in_place_transform_with(&mut self, transformer: &mut impl Transformer) {
         match self {
-            Self::Imported(import) => match import {
-                Import::LinkName(_) => {}
-            },
+            Self::Imported(import) => transformer.in_place_transform_import(import),
             Self::Present(def) => def.inner_in_place_transform_with(transformer),
         }
     }
@@ -716,7 +806,10 @@ impl InnerTransform for DataInstFormDef {
                     | QPtrOp::Load
                     | QPtrOp::Store => Transformed::Unchanged,
                 },
-                DataInstKind::SpvInst(_) | DataInstKind::SpvExtInst { .. } => Transformed::Unchanged,
+                DataInstKind::SpvInst(_) => Transformed::Unchanged,
+                &DataInstKind::SpvExtInst { ext_set, inst } => transformer
+                    .transform_interned_str_use(ext_set)
+                    .map(|ext_set| DataInstKind::SpvExtInst { ext_set, inst }),
             },
             // FIXME(eddyb) this should be replaced with an impl of `InnerTransform`
             // for `Option<T>` or some other helper, to avoid "manual transpose".
@@ -767,3 +860,57 @@ impl InnerTransform for Value {
         }
     }
 }
+
+#[test]
+fn rename_interned_strs() {
+    struct Renamer<'a> {
+        cx: &'a crate::Context,
+    }
+
+    impl Transformer for Renamer<'_> {
+        fn transform_interned_str_use(&mut self, s: InternedStr) -> Transformed<InternedStr> {
+            Transformed::Changed(self.cx.intern(format!("renamed_{}", &self.cx[s])))
+        }
+    }
+
+    let spv_words = crate::spv::test_asm::assemble(
+        r#"
+            OpCapability Shader
+            OpCapability Linkage
+            OpMemoryModel Logical GLSL450
+            %5 = OpString "a.hl"
+            OpSource GLSL 450 %5
+            OpDecorate %1 LinkageAttributes "foo" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %1 = OpFunction %2 None %3
+            %4 = OpLabel
+            OpReturn
+            OpFunctionEnd
+            "#,
+    );
+    let mut module =
+        Module::lower_from_spv_words(Rc::new(crate::Context::new()), &spv_words).unwrap();
+
+    let cx = module.cx();
+    Renamer { cx: &cx }.in_place_transform_module(&mut module);
+
+    let link_names: Vec<_> = module
+        .exports
+        .keys()
+        .map(|export_key| match export_key {
+            &ExportKey::LinkName(name) => &cx[name],
+            ExportKey::SpvEntryPoint { .. } => unreachable!(),
+        })
+        .collect();
+    assert_eq!(link_names, ["renamed_foo"]);
+
+    let ModuleDebugInfo::Spv(debug_info) = &module.debug_info;
+    let file_paths: Vec<_> = debug_info
+        .source_languages
+        .values()
+        .flat_map(|sources| sources.file_contents.keys())
+        .map(|&file_path| &cx[file_path])
+        .collect();
+    assert_eq!(file_paths, ["renamed_a.hl"]);
+}
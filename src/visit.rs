@@ -3,12 +3,13 @@
 use crate::func_at::FuncAt;
 use crate::qptr::{self, QPtrAttr, QPtrMemUsage, QPtrMemUsageKind, QPtrOp, QPtrUsage};
 use crate::{
-    cfg, spv, AddrSpace, Attr, AttrSet, AttrSetDef, Const, ConstDef, ConstKind, ControlNode,
-    ControlNodeDef, ControlNodeKind, ControlNodeOutputDecl, ControlRegion, ControlRegionDef,
-    ControlRegionInputDecl, DataInstDef, DataInstForm, DataInstFormDef, DataInstKind, DeclDef,
-    DiagMsgPart, EntityListIter, ExportKey, Exportee, Func, FuncDecl, FuncDefBody, FuncParam,
-    GlobalVar, GlobalVarDecl, GlobalVarDefBody, Import, Module, ModuleDebugInfo, ModuleDialect,
-    SelectionKind, Type, TypeDef, TypeKind, TypeOrConst, Value,
+    cfg, spv, AddrSpace, Attr, AttrSet, AttrSetDef, Const, ConstDef, ConstKind, Context,
+    ControlNode, ControlNodeDef, ControlNodeKind, ControlNodeOutputDecl, ControlRegion,
+    ControlRegionDef, ControlRegionInputDecl, DataInstDef, DataInstForm, DataInstFormDef,
+    DataInstKind, DeclDef, DiagMsgPart, EntityListIter, ExportKey, Exportee, Func, FuncDecl,
+    FuncDefBody, FuncParam, FxIndexSet, GlobalVar, GlobalVarDecl, GlobalVarDefBody, Import,
+    InternedStr, Module, ModuleDebugInfo, ModuleDialect, SelectionKind, Type, TypeDef, TypeKind,
+    TypeOrConst, Value,
 };
 
 // FIXME(eddyb) `Sized` bound shouldn't be needed but removing it requires
@@ -28,8 +29,23 @@ pub trait Visitor<'a>: Sized {
 
     // Leaves (noop default behavior).
     fn visit_spv_dialect(&mut self, _dialect: &spv::Dialect) {}
-    fn visit_spv_module_debug_info(&mut self, _debug_info: &spv::ModuleDebugInfo) {}
-    fn visit_import(&mut self, _import: &Import) {}
+    // NOTE: `InternedStr`s are used for e.g. link names, extended
+    // instruction set names, and debuginfo file paths.
+    fn visit_interned_str_use(&mut self, _s: InternedStr) {}
+
+    // Leaves (defaulting to only visiting any `InternedStr`s).
+    fn visit_spv_module_debug_info(&mut self, debug_info: &spv::ModuleDebugInfo) {
+        for sources in debug_info.source_languages.values() {
+            for &file in sources.file_contents.keys() {
+                self.visit_interned_str_use(file);
+            }
+        }
+    }
+    fn visit_import(&mut self, import: &Import) {
+        match *import {
+            Import::LinkName(name) => self.visit_interned_str_use(name),
+        }
+    }
 
     // Non-leaves (defaulting to calling `.inner_visit_with(self)`).
     fn visit_module(&mut self, module: &'a Module) {
@@ -138,6 +154,11 @@ impl_visit! {
         // NOTE(eddyb) the interpolated parts of `Attr::Diagnostics` aren't visited
         // by default (as they're "inert data"), this is only for `print`'s usage.
         Vec<DiagMsgPart>,
+
+        // NOTE: this allows visiting only a function's body (e.g. for
+        // per-function analyses), without its signature (`FuncDecl` is visited
+        // through `visit_func_decl`, which also covers the whole body).
+        FuncDefBody,
     }
 }
 
@@ -203,7 +224,7 @@ impl InnerVisit for ModuleDebugInfo {
 impl InnerVisit for ExportKey {
     fn inner_visit_with<'a>(&'a self, visitor: &mut impl Visitor<'a>) {
         match self {
-            Self::LinkName(_) => {}
+            &Self::LinkName(name) => visitor.visit_interned_str_use(name),
 
             Self::SpvEntryPoint { imms: _, interface_global_vars } => {
                 for &gv in interface_global_vars {
@@ -236,10 +257,17 @@ impl InnerVisit for AttrSetDef {
 impl InnerVisit for Attr {
     fn inner_visit_with<'a>(&'a self, visitor: &mut impl Visitor<'a>) {
         match self {
-            Attr::Diagnostics(_)
-            | Attr::SpvAnnotation(_)
-            | Attr::SpvDebugLine { .. }
-            | Attr::SpvBitflagsOperand(_) => {}
+            Attr::Diagnostics(_) | Attr::SpvAnnotation(_) | Attr::SpvBitflagsOperand(_) => {}
+
+            Attr::SpvDebugLine { file_path, line: _, col: _ } => {
+                visitor.visit_interned_str_use(file_path.0);
+            }
+
+            Attr::SpvAnnotationWithConstInputs { spv_inst: _, const_inputs } => {
+                for &ct in &const_inputs.0 {
+                    visitor.visit_const_use(ct);
+                }
+            }
 
             Attr::QPtr(attr) => match attr {
                 QPtrAttr::ToSpvPtrInput { input_idx: _, pointee }
@@ -343,7 +371,7 @@ impl InnerVisit for ConstDef {
                     visitor.visit_const_use(ct);
                 }
             }
-            ConstKind::SpvStringLiteralForExtInst(_) => {}
+            &ConstKind::SpvStringLiteralForExtInst(s) => visitor.visit_interned_str_use(s),
         }
     }
 }
@@ -534,7 +562,10 @@ impl InnerVisit for DataInstFormDef {
                 | QPtrOp::Load
                 | QPtrOp::Store => {}
             },
-            DataInstKind::SpvInst(_) | DataInstKind::SpvExtInst { .. } => {}
+            DataInstKind::SpvInst(_) => {}
+            &DataInstKind::SpvExtInst { ext_set, inst: _ } => {
+                visitor.visit_interned_str_use(ext_set);
+            }
         }
         if let Some(ty) = *output_type {
             visitor.visit_type_use(ty);
@@ -577,3 +608,157 @@ impl InnerVisit for Value {
         }
     }
 }
+
+/// [`Visitor`] which visits the definitions of all the uses it's given (and of
+/// everything those definitions use in turn), but only once per definition,
+/// collecting them (deduplicated, in the order they were first found in).
+///
+/// This is most commonly used to find everything reachable from the exports
+/// of a [`Module`] (see [`ReachableUseCollector::visit_module_exports`]),
+/// with any further processing being done on the collected definitions.
+pub(crate) struct ReachableUseCollector<'a> {
+    pub cx: &'a Context,
+    pub module: &'a Module,
+
+    /// Whether to also visit (and collect) `AttrSet`s, which is only needed for
+    /// the few definitions that can only be reached through attributes
+    /// (e.g. `Const`s used by `OpDecorateId`, which can refer to `GlobalVar`s).
+    pub visit_attr_sets: bool,
+
+    pub seen_attr_sets: FxIndexSet<AttrSet>,
+    pub seen_types: FxIndexSet<Type>,
+    pub seen_consts: FxIndexSet<Const>,
+    pub seen_data_inst_forms: FxIndexSet<DataInstForm>,
+    pub seen_global_vars: FxIndexSet<GlobalVar>,
+    pub seen_funcs: FxIndexSet<Func>,
+}
+
+impl<'a> ReachableUseCollector<'a> {
+    pub fn new(module: &'a Module) -> Self {
+        ReachableUseCollector {
+            cx: module.cx_ref(),
+            module,
+
+            visit_attr_sets: false,
+
+            seen_attr_sets: FxIndexSet::default(),
+            seen_types: FxIndexSet::default(),
+            seen_consts: FxIndexSet::default(),
+            seen_data_inst_forms: FxIndexSet::default(),
+            seen_global_vars: FxIndexSet::default(),
+            seen_funcs: FxIndexSet::default(),
+        }
+    }
+
+    pub fn visit_module_exports(&mut self) {
+        let module = self.module;
+        for (export_key, exportee) in &module.exports {
+            export_key.inner_visit_with(self);
+            exportee.inner_visit_with(self);
+        }
+    }
+}
+
+impl Visitor<'_> for ReachableUseCollector<'_> {
+    fn visit_attr_set_use(&mut self, attrs: AttrSet) {
+        if self.visit_attr_sets && self.seen_attr_sets.insert(attrs) {
+            self.visit_attr_set_def(&self.cx[attrs]);
+        }
+    }
+    fn visit_type_use(&mut self, ty: Type) {
+        if self.seen_types.insert(ty) {
+            self.visit_type_def(&self.cx[ty]);
+        }
+    }
+    fn visit_const_use(&mut self, ct: Const) {
+        if self.seen_consts.insert(ct) {
+            self.visit_const_def(&self.cx[ct]);
+        }
+    }
+    fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
+        if self.seen_data_inst_forms.insert(data_inst_form) {
+            self.visit_data_inst_form_def(&self.cx[data_inst_form]);
+        }
+    }
+
+    fn visit_global_var_use(&mut self, gv: GlobalVar) {
+        if self.seen_global_vars.insert(gv) {
+            self.visit_global_var_decl(&self.module.global_vars[gv]);
+        }
+    }
+    fn visit_func_use(&mut self, func: Func) {
+        if self.seen_funcs.insert(func) {
+            self.visit_func_decl(&self.module.funcs[func]);
+        }
+    }
+}
+
+#[test]
+fn visit_single_func_def_body() {
+    let spv_words = spv::test_asm::assemble(
+        r#"
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpDecorate %one_add LinkageAttributes "one_add" Export
+        OpDecorate %two_adds LinkageAttributes "two_adds" Export
+        %void = OpTypeVoid
+        %float = OpTypeFloat 32
+        %one = OpConstant %float 0x3f800000
+        %func_type = OpTypeFunction %void
+        %one_add = OpFunction %void None %func_type
+        %one_add_entry = OpLabel
+        %a = OpFAdd %float %one %one
+        OpReturn
+        OpFunctionEnd
+        %two_adds = OpFunction %void None %func_type
+        %two_adds_entry = OpLabel
+        %b = OpFAdd %float %one %one
+        %c = OpFAdd %float %b %one
+        OpReturn
+        OpFunctionEnd
+        "#,
+    );
+    let module =
+        Module::lower_from_spv_words(std::rc::Rc::new(Context::new()), &spv_words).unwrap();
+
+    struct DataInstCounter {
+        count: usize,
+    }
+    impl Visitor<'_> for DataInstCounter {
+        // NOTE: only the function body itself is of interest here.
+        fn visit_attr_set_use(&mut self, _: AttrSet) {}
+        fn visit_type_use(&mut self, _: Type) {}
+        fn visit_const_use(&mut self, _: Const) {}
+        fn visit_data_inst_form_use(&mut self, _: DataInstForm) {}
+        fn visit_global_var_use(&mut self, _: GlobalVar) {}
+        fn visit_func_use(&mut self, _: Func) {}
+
+        fn visit_data_inst_def(&mut self, data_inst_def: &DataInstDef) {
+            self.count += 1;
+            data_inst_def.inner_visit_with(self);
+        }
+    }
+
+    let cx = module.cx();
+    let count_data_insts = |link_name: &str| {
+        let func = module
+            .exports
+            .iter()
+            .find_map(|(export_key, exportee)| match (export_key, exportee) {
+                (&ExportKey::LinkName(name), &Exportee::Func(func)) if &cx[name] == link_name => {
+                    Some(func)
+                }
+                _ => None,
+            })
+            .unwrap();
+        let DeclDef::Present(func_def_body) = &module.funcs[func].def else {
+            unreachable!();
+        };
+        let mut counter = DataInstCounter { count: 0 };
+        func_def_body.visit_with(&mut counter);
+        counter.count
+    };
+    assert_eq!(count_data_insts("one_add"), 1);
+    assert_eq!(count_data_insts("two_adds"), 2);
+}